@@ -0,0 +1,425 @@
+use crate::http1x::find_in_slice;
+use crate::StatusCode;
+use std::cmp;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::ReadBuf;
+use tokio::prelude::*;
+
+// Upper bound for a chunk size line (size and extensions) and
+// for the trailer section, anything bigger is refused
+const MAX_LINE_LEN: usize = 4096;
+const MAX_TRAILERS_LEN: usize = 8192;
+// Size of the chunks that the encoder emits at most
+const CHUNK_SIZE: usize = 8192;
+
+/// The result of decoding a chunked body, the decoded data
+/// itself is written in the buffer given to the decoder
+pub struct ChunkedBody {
+    /// The trailer fields in raw form, separated by CRLF
+    pub trailers: String,
+    /// Bytes that were read past the end of the body, they
+    /// belong to the next request on the connection
+    pub surplus: Vec<u8>,
+}
+
+/// Decodes a body sent with `Transfer-Encoding: chunked`,
+/// chunk extensions are ignored and trailers are collected
+pub struct ChunkedDecoder<R> {
+    reader: R,
+    buf: Vec<u8>,
+    pos: usize,
+    max_len: Option<usize>,
+    // bytes decoded so far
+    decoded: usize,
+    // bytes of the current chunk that are still to be decoded
+    chunk_left: usize,
+    // the trailers, set once the last chunk was read
+    trailers: Option<Vec<u8>>,
+}
+
+impl<R> ChunkedDecoder<R>
+where
+    R: AsyncRead + Unpin,
+{
+    /// Creates a decoder, `buffered` holds the bytes of the body
+    /// that were already read with the request head
+    pub fn new(reader: R, buffered: &[u8]) -> Self {
+        Self {
+            reader,
+            buf: buffered.to_vec(),
+            pos: 0,
+            max_len: None,
+            decoded: 0,
+            chunk_left: 0,
+            trailers: None,
+        }
+    }
+
+    /// Sets the largest decoded body the decoder accepts, a
+    /// bigger one gives a 413 before its chunks are read
+    pub fn max_len(mut self, max_len: Option<usize>) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Decodes the whole body into `body`
+    pub async fn decode(mut self, body: &mut Vec<u8>) -> Result<ChunkedBody, StatusCode> {
+        while self.decode_chunk(body).await? {}
+        self.finish()
+    }
+
+    /// Decodes the next piece of the body into `body`, a chunk that
+    /// isn't buffered yet is decoded in several pieces. Returns false
+    /// once the last chunk and the trailers were read
+    pub async fn decode_chunk(&mut self, body: &mut Vec<u8>) -> Result<bool, StatusCode> {
+        if self.trailers.is_some() {
+            return Ok(false);
+        }
+        if self.chunk_left == 0 {
+            let line = self.line(MAX_LINE_LEN).await?;
+            // everything after a `;` is a chunk extension
+            let size = line.split(|&c| c == b';').next().unwrap_or(&[]);
+            let size = parse_size(trim(size)).ok_or(StatusCode::BadRequest)?;
+            if size == 0 {
+                self.trailers = Some(self.read_trailers().await?);
+                return Ok(false);
+            }
+            if let Some(max) = self.max_len {
+                if size > max || self.decoded > max - size {
+                    return Err(StatusCode::PayloadTooLarge);
+                }
+            }
+            self.chunk_left = size;
+        }
+        if self.pos == self.buf.len() {
+            // everything buffered was decoded, the buffer is reused so
+            // that a large chunk is never held in memory as a whole
+            self.buf.clear();
+            self.pos = 0;
+            self.read_more().await?;
+        }
+        let len = cmp::min(self.chunk_left, self.buf.len() - self.pos);
+        body.extend_from_slice(&self.buf[self.pos..self.pos + len]);
+        self.pos += len;
+        self.chunk_left -= len;
+        self.decoded += len;
+        if self.chunk_left == 0 {
+            self.fill(2).await?;
+            if &self.buf[self.pos..self.pos + 2] != b"\r\n" {
+                return Err(StatusCode::BadRequest);
+            }
+            self.pos += 2;
+        }
+        Ok(true)
+    }
+
+    /// Returns the trailers and the bytes read past the body, the
+    /// body has to be decoded until `decode_chunk` returns false
+    pub fn finish(&mut self) -> Result<ChunkedBody, StatusCode> {
+        let trailers = self.trailers.take().ok_or(StatusCode::BadRequest)?;
+        Ok(ChunkedBody {
+            trailers: String::from_utf8(trailers).map_err(|_| StatusCode::BadRequest)?,
+            surplus: self.buf.split_off(self.pos),
+        })
+    }
+
+    /// Returns the reader the body is decoded from
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    async fn read_trailers(&mut self) -> Result<Vec<u8>, StatusCode> {
+        let mut trailers = Vec::new();
+        loop {
+            let line = self.line(MAX_LINE_LEN).await?;
+            if line.is_empty() {
+                return Ok(trailers);
+            }
+            if trailers.len() + line.len() > MAX_TRAILERS_LEN {
+                return Err(StatusCode::RequestHeaderFieldsTooLarge);
+            }
+            if !trailers.is_empty() {
+                trailers.extend_from_slice(b"\r\n");
+            }
+            trailers.extend_from_slice(&line);
+        }
+    }
+
+    // Returns the next line without the CRLF
+    async fn line(&mut self, max_len: usize) -> Result<Vec<u8>, StatusCode> {
+        loop {
+            if let Some(i) = find_in_slice(&self.buf[self.pos..], b"\r\n") {
+                let line = self.buf[self.pos..self.pos + i].to_vec();
+                self.pos += i + 2;
+                return Ok(line);
+            }
+            if self.buf.len() - self.pos > max_len {
+                return Err(StatusCode::BadRequest);
+            }
+            self.read_more().await?;
+        }
+    }
+
+    // Makes sure there are at least `len` unread bytes buffered
+    async fn fill(&mut self, len: usize) -> Result<(), StatusCode> {
+        // forget about data that has already been decoded
+        self.buf.drain(..self.pos);
+        self.pos = 0;
+        while self.buf.len() < len {
+            self.read_more().await?;
+        }
+        Ok(())
+    }
+
+    async fn read_more(&mut self) -> Result<(), StatusCode> {
+        let mut temp: [u8; CHUNK_SIZE] = [0; CHUNK_SIZE];
+        let read = self.reader.read(&mut temp).await.unwrap_or(0);
+        if read == 0 {
+            // the connection was closed in the middle of the body
+            return Err(StatusCode::BadRequest);
+        }
+        self.buf.extend_from_slice(&temp[..read]);
+        Ok(())
+    }
+}
+
+/// Encodes everything read from the inner reader with the
+/// chunked transfer coding, used for bodies whose length isn't
+/// known before they are sent
+pub struct ChunkedEncoder<R> {
+    inner: R,
+    chunk: Vec<u8>,
+    out: Vec<u8>,
+    pos: usize,
+    finished: bool,
+}
+
+impl<R> ChunkedEncoder<R>
+where
+    R: AsyncRead + Unpin,
+{
+    /// Wraps a reader, each read on it produces one chunk
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            chunk: vec![0; CHUNK_SIZE],
+            out: Vec::new(),
+            pos: 0,
+            finished: false,
+        }
+    }
+}
+
+impl<R> AsyncRead for ChunkedEncoder<R>
+where
+    R: AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        while this.pos == this.out.len() {
+            if this.finished {
+                return Poll::Ready(Ok(()));
+            }
+            let mut chunk = ReadBuf::new(&mut this.chunk);
+            match Pin::new(&mut this.inner).poll_read(cx, &mut chunk) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+            let data = chunk.filled();
+            this.out.clear();
+            this.pos = 0;
+            if data.is_empty() {
+                // the last chunk, without any trailers
+                this.out.extend_from_slice(b"0\r\n\r\n");
+                this.finished = true;
+            } else {
+                this.out
+                    .extend_from_slice(format!("{:x}\r\n", data.len()).as_bytes());
+                this.out.extend_from_slice(data);
+                this.out.extend_from_slice(b"\r\n");
+            }
+        }
+        let len = buf.remaining().min(this.out.len() - this.pos);
+        buf.put_slice(&this.out[this.pos..this.pos + len]);
+        this.pos += len;
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn trim(data: &[u8]) -> &[u8] {
+    let start = data
+        .iter()
+        .position(|&c| c != b' ' && c != b'\t')
+        .unwrap_or(data.len());
+    let end = data
+        .iter()
+        .rposition(|&c| c != b' ' && c != b'\t')
+        .map_or(start, |i| i + 1);
+    &data[start..end]
+}
+
+fn parse_size(size: &[u8]) -> Option<usize> {
+    if size.is_empty() || !size.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    usize::from_str_radix(std::str::from_utf8(size).ok()?, 16).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::io::{duplex, split};
+
+    async fn decode(
+        buffered: &[u8],
+        rest: &'static [u8],
+    ) -> Result<(Vec<u8>, ChunkedBody), StatusCode> {
+        let (client, server) = duplex(1024);
+        let (mut reader, _writer) = split(server);
+        let (_, mut client) = split(client);
+        client.write_all(rest).await.unwrap();
+        drop(client);
+        let mut body = Vec::new();
+        let decoded = ChunkedDecoder::new(&mut reader, buffered)
+            .decode(&mut body)
+            .await?;
+        Ok((body, decoded))
+    }
+
+    #[octane_macros::test]
+    async fn success_standard() {
+        // Chunks split between the buffer and the stream should be decoded.
+        let (body, decoded) = decode(
+            b"5\r\nhel",
+            b"lo\r\n7;name=value\r\n, world\r\n0\r\n\r\nGET",
+        )
+        .await
+        .unwrap();
+        assert_eq!(body, b"hello, world");
+        assert_eq!(decoded.trailers, "");
+        assert_eq!(decoded.surplus, b"GET");
+    }
+
+    #[octane_macros::test]
+    async fn success_large_chunk() {
+        // A chunk much larger than the buffer should be decoded in
+        // pieces without being buffered as a whole.
+        let len = CHUNK_SIZE * 128;
+        let (client, server) = duplex(CHUNK_SIZE * 4);
+        let (mut reader, _writer) = split(server);
+        let (_, mut client) = split(client);
+        let sender = tokio::spawn(async move {
+            let head = format!("{:x}\r\n", len);
+            client.write_all(head.as_bytes()).await.unwrap();
+            client.write_all(&vec![b'a'; len]).await.unwrap();
+            client.write_all(b"\r\n0\r\n\r\n").await.unwrap();
+        });
+        let mut decoder = ChunkedDecoder::new(&mut reader, b"");
+        let mut body = Vec::new();
+        let mut decoded = 0;
+        while decoder.decode_chunk(&mut body).await.unwrap() {
+            assert!(decoder.buf.len() <= CHUNK_SIZE + MAX_LINE_LEN);
+            assert!(body.iter().all(|&byte| byte == b'a'));
+            decoded += body.len();
+            body.clear();
+        }
+        sender.await.unwrap();
+        assert_eq!(decoded, len);
+    }
+
+    #[octane_macros::test]
+    async fn success_trailers() {
+        // Trailers should be collected.
+        let (body, decoded) = decode(
+            b"",
+            b"A\r\n0123456789\r\n000\r\nExpires: never\r\nX-Sum: 1\r\n\r\n",
+        )
+        .await
+        .unwrap();
+        assert_eq!(body, b"0123456789");
+        assert_eq!(decoded.trailers, "Expires: never\r\nX-Sum: 1");
+        assert!(decoded.surplus.is_empty());
+    }
+
+    #[octane_macros::test]
+    async fn success_decode_chunk() {
+        // A chunk that isn't buffered should be decoded as it arrives.
+        let (client, server) = duplex(1024);
+        let (reader, _writer) = split(server);
+        let (_, mut client) = split(client);
+        client
+            .write_all(b"lo\r\n0\r\nX-Sum: 1\r\n\r\nGET")
+            .await
+            .unwrap();
+        drop(client);
+        let mut decoder = ChunkedDecoder::new(reader, b"5\r\nhel");
+        let mut chunks = Vec::new();
+        loop {
+            let mut chunk = Vec::new();
+            if !decoder.decode_chunk(&mut chunk).await.unwrap() {
+                break;
+            }
+            chunks.push(chunk);
+        }
+        assert_eq!(chunks, vec![b"hel".to_vec(), b"lo".to_vec()]);
+        assert!(!decoder.decode_chunk(&mut Vec::new()).await.unwrap());
+        let decoded = decoder.finish().unwrap();
+        assert_eq!(decoded.trailers, "X-Sum: 1");
+        assert_eq!(decoded.surplus, b"GET");
+        assert!(decoder.finish().is_err());
+    }
+
+    #[octane_macros::test]
+    async fn success_encode() {
+        // Encoded data should decode back to the original.
+        let data: Vec<u8> = (0..20000).map(|i| (i % 251) as u8).collect();
+        let mut encoded = Vec::new();
+        ChunkedEncoder::new(std::io::Cursor::new(data.clone()))
+            .read_to_end(&mut encoded)
+            .await
+            .unwrap();
+        assert!(encoded.starts_with(b"2000\r\n"));
+        assert!(encoded.ends_with(b"\r\n0\r\n\r\n"));
+        let (client, server) = duplex(1024);
+        let (mut reader, _writer) = split(server);
+        drop(client);
+        let mut body = Vec::new();
+        let decoded = ChunkedDecoder::new(&mut reader, &encoded)
+            .decode(&mut body)
+            .await
+            .unwrap();
+        assert_eq!(body, data);
+        assert!(decoded.surplus.is_empty());
+    }
+
+    #[octane_macros::test]
+    async fn fail_malformed() {
+        // Invalid sizes, missing CRLFs and truncated bodies should error.
+        assert!(decode(b"", b"z\r\n").await.is_err());
+        assert!(decode(b"", b"+5\r\nhello\r\n0\r\n\r\n").await.is_err());
+        assert!(decode(b"", b"5\r\nhelloX\r\n0\r\n\r\n").await.is_err());
+        assert!(decode(b"", b"5\r\nhel").await.is_err());
+    }
+
+    #[octane_macros::test]
+    async fn fail_max_len() {
+        // A body over the limit should be refused before its chunk is read.
+        let (client, server) = duplex(1024);
+        let (mut reader, _writer) = split(server);
+        drop(client);
+        let mut body = Vec::new();
+        let decoded = ChunkedDecoder::new(&mut reader, b"5\r\nhello\r\nFFFFFFFF\r\n")
+            .max_len(Some(1024))
+            .decode(&mut body)
+            .await;
+        assert_eq!(decoded.err(), Some(StatusCode::PayloadTooLarge));
+        assert_eq!(body, b"hello");
+    }
+}
@@ -4,8 +4,24 @@ use tokio::io::ReadHalf;
 
 use tokio::prelude::*;
 
+pub mod chunked;
 pub mod raw_request;
 
+pub type Http1xParsed<'a, T> = (String, &'a str, &'a [u8], ReadHalf<T>);
+
+// Length of ` HTTP/1.1` after the request target
+const VERSION_LEN: usize = 9;
+
+/// Bounds on the size of a request head, a longer request
+/// target gives a 414 and a larger header section a 431
+#[derive(Clone, Copy, Debug)]
+pub struct HeadLimits {
+    /// The longest request target, in bytes
+    pub uri: usize,
+    /// The largest header section, in bytes
+    pub headers: usize,
+}
+
 pub struct Http1xReader<'a, T> {
     pub raw_request: RawRequest1x<'a, T>,
 }
@@ -14,22 +30,31 @@ impl<'a, T> Http1xReader<'a, T>
 where
     T: AsyncRead + Unpin,
 {
-    pub async fn new(
+    /// Reads a request head from the stream, returns `Ok(None)` if the
+    /// connection was closed before any bytes of a new request arrived.
+    ///
+    /// `data` can already hold bytes that were read while handling the
+    /// previous request (pipelined requests), these are parsed before
+    /// anything else is read from the stream
+    pub async fn read(
         request: RawRequest1x<'a, T>,
         data: &'a mut Vec<u8>,
-    ) -> Result<(String, &'a str, &'a [u8], ReadHalf<T>), StatusCode> {
+        limits: HeadLimits,
+    ) -> Result<Option<Http1xParsed<'a, T>>, StatusCode> {
         let mut instance = Self {
             raw_request: request,
         };
         let mut buf: [u8; 512] = [0; 512];
 
         loop {
-            let read = instance.raw_request.reader.read(&mut buf).await.unwrap();
-            if read == 0 {
-                return Err(StatusCode::BadRequest);
-            }
-            data.extend_from_slice(&buf[..read]);
+            // empty lines before a request line should be ignored
+            let blank = data
+                .iter()
+                .take_while(|&&c| c == b'\r' || c == b'\n')
+                .count();
+            data.drain(..blank);
             if let Some(i) = find_in_slice(&data[..], b"\r\n\r\n") {
+                check_head(&data[..i], true, limits)?;
                 instance.raw_request.body_remainder = &data[i + 4..];
                 if let Ok(Some((rl, heads))) =
                     std::str::from_utf8(&data[..i]).map(parse_without_body)
@@ -41,14 +66,55 @@ where
                     return Err(StatusCode::BadRequest);
                 }
             }
+            // refuse a head that keeps growing before it's complete
+            check_head(&data[..], false, limits)?;
+            let read = instance
+                .raw_request
+                .reader
+                .read(&mut buf)
+                .await
+                .unwrap_or(0);
+            if read == 0 {
+                if data.is_empty() {
+                    return Ok(None);
+                }
+                return Err(StatusCode::BadRequest);
+            }
+            data.extend_from_slice(&buf[..read]);
         }
-        Ok((
+        Ok(Some((
             instance.raw_request.headers,
             instance.raw_request.request_line,
             instance.raw_request.body_remainder,
             instance.raw_request.reader,
-        ))
+        )))
+    }
+}
+
+// Checks the request line and the headers read so far against the
+// limits, the request line may still be incomplete
+fn check_head(head: &[u8], complete: bool, limits: HeadLimits) -> Result<(), StatusCode> {
+    let line_len = find_in_slice(head, b"\r\n").unwrap_or(head.len());
+    let line = &head[..line_len];
+    let target_len = match line.iter().position(|&c| c == b' ') {
+        Some(start) if complete || line_len < head.len() => {
+            let target = &line[start + 1..];
+            target
+                .iter()
+                .rposition(|&c| c == b' ')
+                .unwrap_or(target.len())
+        }
+        // the version might not have arrived yet
+        Some(start) => (line_len - start - 1).saturating_sub(VERSION_LEN),
+        None => line_len,
+    };
+    if target_len > limits.uri {
+        return Err(StatusCode::UriTooLong);
+    }
+    if head.len() - line_len > limits.headers {
+        return Err(StatusCode::RequestHeaderFieldsTooLarge);
     }
+    Ok(())
 }
 
 pub fn find_in_slice<T: Eq>(haystack: &[T], needle: &[T]) -> Option<usize> {
@@ -76,6 +142,79 @@ pub(crate) fn parse_without_body(data: &str) -> Option<(&str, String)> {
     let n = data.find("\r\n")?;
     let (line, rest) = data.split_at(n);
     let request_line = line;
-    let headers = (&rest[2..]).to_owned();
+    let headers = rest[2..].to_owned();
     Some((request_line, headers))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::io::{duplex, split};
+
+    const LIMITS: HeadLimits = HeadLimits {
+        uri: 64,
+        headers: 128,
+    };
+
+    async fn read_head(request: String) -> Result<(), StatusCode> {
+        let (client, server) = duplex(4096);
+        let (reader, _writer) = split(server);
+        let (_, mut client) = split(client);
+        client.write_all(request.as_bytes()).await.unwrap();
+        drop(client);
+        let mut data = Vec::new();
+        Http1xReader::read(RawRequest1x::new(reader), &mut data, LIMITS)
+            .await
+            .map(|_| ())
+    }
+
+    #[octane_macros::test]
+    async fn success_pipelined() {
+        // Bytes after a request should be available for the next one.
+        let (client, server) = duplex(1024);
+        let (mut reader, _writer) = split(server);
+        let (_, mut client) = split(client);
+        client
+            .write_all(b"\r\nGET /a HTTP/1.1\r\nHost: x\r\n\r\nGET /b HTTP/1.1\r\nHost: x\r\n\r\n")
+            .await
+            .unwrap();
+        drop(client);
+        let mut pipelined = Vec::new();
+        for path in &["GET /a HTTP/1.1", "GET /b HTTP/1.1"] {
+            let mut data = std::mem::take(&mut pipelined);
+            let (headers, request_line, remainder, reader_left) =
+                Http1xReader::read(RawRequest1x::new(reader), &mut data, LIMITS)
+                    .await
+                    .unwrap()
+                    .unwrap();
+            assert_eq!(request_line, *path);
+            assert_eq!(headers, "Host: x");
+            pipelined = remainder.to_vec();
+            reader = reader_left;
+        }
+        let mut data = Vec::new();
+        assert!(
+            Http1xReader::read(RawRequest1x::new(reader), &mut data, LIMITS)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[octane_macros::test]
+    async fn fail_head_limits() {
+        // The target and the headers are measured apart.
+        let uri = format!("GET /{} HTTP/1.1\r\nHost: x\r\n\r\n", "a".repeat(63));
+        assert!(read_head(uri).await.is_ok());
+        let uri = format!("GET /{} HTTP/1.1\r\nHost: x\r\n\r\n", "a".repeat(64));
+        assert_eq!(read_head(uri).await, Err(StatusCode::UriTooLong));
+        // a target that never ends is refused without waiting for the rest
+        let uri = format!("GET /{}", "a".repeat(200));
+        assert_eq!(read_head(uri).await, Err(StatusCode::UriTooLong));
+        let headers = format!("GET / HTTP/1.1\r\nX-Big: {}\r\n\r\n", "b".repeat(128));
+        assert_eq!(
+            read_head(headers).await,
+            Err(StatusCode::RequestHeaderFieldsTooLarge)
+        );
+    }
+}
@@ -9,19 +9,13 @@ pub mod http1x;
 /// # Example
 ///
 /// ```
-/// use octane::prelude::*;
-/// use octane::request::HttpVersion;
+/// use octane_http::HttpVersion;
 ///
-/// let mut app = Octane::new();
-/// app
-/// .get("/",
-///     route!(|req, res| {
-///        if req.request_line.version == HttpVersion::Http11 {
-///            // do something
-///         }
-///         Flow::Stop
-///     }),
-/// );
+/// let version = HttpVersion::Http11;
+/// if version == HttpVersion::Http11 {
+///     // do something
+/// }
+/// assert_eq!(version.to_string(), "1.1");
 /// ```
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum HttpVersion {
@@ -37,16 +31,16 @@ pub enum HttpVersion {
     HttpInvalid,
 }
 
-impl ToString for HttpVersion {
-    fn to_string(&self) -> std::string::String {
-        match self {
+impl fmt::Display for HttpVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let version = match self {
             Self::Http11 => "1.1",
             Self::Http10 => "1.0",
             Self::Http09 => "0.9",
             Self::Http02 => "0.2",
             _ => "",
-        }
-        .to_owned()
+        };
+        write!(f, "{}", version)
     }
 }
 
@@ -114,9 +108,9 @@ status_codes! {
     511 "Network Authentication Required"
 }
 
-impl Into<i32> for StatusCode {
-    fn into(self) -> i32 {
-        let (n, _) = self.fetch();
+impl From<StatusCode> for i32 {
+    fn from(code: StatusCode) -> i32 {
+        let (n, _) = code.fetch();
         n
     }
 }
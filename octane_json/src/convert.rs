@@ -0,0 +1,382 @@
+use crate::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::hash::BuildHasher;
+
+/// Converts a rust value to a json [`Value`](../enum.Value.html). The
+/// conversion returns `None` when the value has no json representation,
+/// like a `NaN` float.
+///
+/// Use the `ToJSON` derive macro to implement it for your own structs
+/// and enums.
+///
+/// # Example
+///
+/// ```
+/// use octane_json::ToJSON;
+///
+/// let numbers = vec![Some(1), None, Some(3)];
+/// assert_eq!(numbers.to_json_string().unwrap(), "[1,null,3]");
+/// ```
+pub trait ToJSON {
+    fn to_json(&self) -> Option<Value>;
+
+    /// Serialises the value to a json string without whitespace.
+    fn to_json_string(&self) -> Option<String> {
+        self.to_json().map(|val| val.to_string())
+    }
+
+    /// Serialises the value to an indented json string.
+    fn to_json_string_pretty(&self) -> Option<String> {
+        self.to_json().map(|val| val.to_string_pretty())
+    }
+}
+
+/// Converts a json [`Value`](../enum.Value.html) to a rust value,
+/// returning `None` if the json doesn't have the expected shape.
+///
+/// Use the `FromJSON` derive macro to implement it for your own structs
+/// and enums.
+///
+/// # Example
+///
+/// ```
+/// use octane_json::FromJSON;
+/// use std::collections::HashMap;
+///
+/// let map: HashMap<String, Vec<u8>> = FromJSON::from_json_string(r#"{"a": [1, 2]}"#).unwrap();
+/// assert_eq!(map["a"], vec![1, 2]);
+/// assert!(u8::from_json_string("256").is_none());
+/// ```
+pub trait FromJSON: Sized {
+    fn from_json(val: Value) -> Option<Self>;
+
+    /// Parses the string and converts the resulting value.
+    fn from_json_string(data: &str) -> Option<Self> {
+        Value::parse(data).ok().and_then(Self::from_json)
+    }
+}
+
+impl ToJSON for Value {
+    fn to_json(&self) -> Option<Value> {
+        Some(self.clone())
+    }
+}
+
+impl FromJSON for Value {
+    fn from_json(val: Value) -> Option<Self> {
+        Some(val)
+    }
+}
+
+impl ToJSON for () {
+    fn to_json(&self) -> Option<Value> {
+        Some(Value::Null)
+    }
+}
+
+impl FromJSON for () {
+    fn from_json(val: Value) -> Option<Self> {
+        if val.is_null() {
+            Some(())
+        } else {
+            None
+        }
+    }
+}
+
+impl ToJSON for bool {
+    fn to_json(&self) -> Option<Value> {
+        Some(Value::Bool(*self))
+    }
+}
+
+impl FromJSON for bool {
+    fn from_json(val: Value) -> Option<Self> {
+        val.as_bool()
+    }
+}
+
+macro_rules! integer_json {
+    ($($t: ty),*) => {
+        $(
+            impl ToJSON for $t {
+                fn to_json(&self) -> Option<Value> {
+                    match i64::try_from(*self) {
+                        Ok(i) => Some(Value::Integer(i)),
+                        Err(_) => Some(Value::Float(*self as f64)),
+                    }
+                }
+            }
+
+            impl FromJSON for $t {
+                fn from_json(val: Value) -> Option<Self> {
+                    if let Some(i) = val.as_i64() {
+                        return <$t>::try_from(i).ok();
+                    }
+                    // integers too large for an i64 are parsed as floats
+                    match val {
+                        Value::Float(f) if f.fract() == 0.0 && f >= 0.0 && f < <$t>::MAX as f64 => {
+                            Some(f as $t)
+                        }
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+integer_json!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+macro_rules! float_json {
+    ($($t: ty),*) => {
+        $(
+            impl ToJSON for $t {
+                fn to_json(&self) -> Option<Value> {
+                    if self.is_finite() {
+                        Some(Value::Float(*self as f64))
+                    } else {
+                        None
+                    }
+                }
+            }
+
+            impl FromJSON for $t {
+                fn from_json(val: Value) -> Option<Self> {
+                    val.as_f64().map(|f| f as $t)
+                }
+            }
+        )*
+    };
+}
+
+float_json!(f32, f64);
+
+impl ToJSON for str {
+    fn to_json(&self) -> Option<Value> {
+        Some(Value::String(self.to_owned()))
+    }
+}
+
+impl ToJSON for String {
+    fn to_json(&self) -> Option<Value> {
+        Some(Value::String(self.clone()))
+    }
+}
+
+impl FromJSON for String {
+    fn from_json(val: Value) -> Option<Self> {
+        match val {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl ToJSON for char {
+    fn to_json(&self) -> Option<Value> {
+        Some(Value::String(self.to_string()))
+    }
+}
+
+impl FromJSON for char {
+    fn from_json(val: Value) -> Option<Self> {
+        let s = val.as_str()?;
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    }
+}
+
+impl<T: ToJSON + ?Sized> ToJSON for &T {
+    fn to_json(&self) -> Option<Value> {
+        (**self).to_json()
+    }
+}
+
+impl<T: ToJSON + ?Sized> ToJSON for Box<T> {
+    fn to_json(&self) -> Option<Value> {
+        (**self).to_json()
+    }
+}
+
+impl<T: FromJSON> FromJSON for Box<T> {
+    fn from_json(val: Value) -> Option<Self> {
+        T::from_json(val).map(Box::new)
+    }
+}
+
+/// `None` is serialised as `null`.
+impl<T: ToJSON> ToJSON for Option<T> {
+    fn to_json(&self) -> Option<Value> {
+        match self {
+            Some(val) => val.to_json(),
+            None => Some(Value::Null),
+        }
+    }
+}
+
+impl<T: FromJSON> FromJSON for Option<T> {
+    fn from_json(val: Value) -> Option<Self> {
+        match val {
+            Value::Null => Some(None),
+            val => T::from_json(val).map(Some),
+        }
+    }
+}
+
+impl<T: ToJSON> ToJSON for [T] {
+    fn to_json(&self) -> Option<Value> {
+        self.iter()
+            .map(ToJSON::to_json)
+            .collect::<Option<_>>()
+            .map(Value::Array)
+    }
+}
+
+impl<T: ToJSON> ToJSON for Vec<T> {
+    fn to_json(&self) -> Option<Value> {
+        self.as_slice().to_json()
+    }
+}
+
+impl<T: FromJSON> FromJSON for Vec<T> {
+    fn from_json(val: Value) -> Option<Self> {
+        match val {
+            Value::Array(arr) => arr.into_iter().map(T::from_json).collect(),
+            _ => None,
+        }
+    }
+}
+
+impl<T: ToJSON, S: BuildHasher> ToJSON for HashMap<String, T, S> {
+    fn to_json(&self) -> Option<Value> {
+        self.iter()
+            .map(|(key, val)| Some((key.clone(), val.to_json()?)))
+            .collect::<Option<_>>()
+            .map(Value::Object)
+    }
+}
+
+impl<T: FromJSON, S: BuildHasher + Default> FromJSON for HashMap<String, T, S> {
+    fn from_json(val: Value) -> Option<Self> {
+        match val {
+            Value::Object(obj) => obj
+                .into_iter()
+                .map(|(key, val)| Some((key, T::from_json(val)?)))
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+impl<T: ToJSON> ToJSON for BTreeMap<String, T> {
+    fn to_json(&self) -> Option<Value> {
+        self.iter()
+            .map(|(key, val)| Some((key.clone(), val.to_json()?)))
+            .collect::<Option<_>>()
+            .map(Value::Object)
+    }
+}
+
+impl<T: FromJSON> FromJSON for BTreeMap<String, T> {
+    fn from_json(val: Value) -> Option<Self> {
+        match val {
+            Value::Object(obj) => obj
+                .into_iter()
+                .map(|(key, val)| Some((key, T::from_json(val)?)))
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! tuple_json {
+    ($(($($name: ident $idx: tt),+)),*) => {
+        $(
+            /// Tuples are serialised as arrays.
+            impl<$($name: ToJSON),+> ToJSON for ($($name,)+) {
+                fn to_json(&self) -> Option<Value> {
+                    Some(Value::Array(vec![$(self.$idx.to_json()?),+]))
+                }
+            }
+
+            impl<$($name: FromJSON),+> FromJSON for ($($name,)+) {
+                fn from_json(val: Value) -> Option<Self> {
+                    let mut arr = match val {
+                        Value::Array(arr) => arr.into_iter(),
+                        _ => return None,
+                    };
+                    let ret = ($($name::from_json(arr.next()?)?,)+);
+                    if arr.next().is_some() {
+                        return None;
+                    }
+                    Some(ret)
+                }
+            }
+        )*
+    };
+}
+
+tuple_json!((A 0), (A 0, B 1), (A 0, B 1, C 2), (A 0, B 1, C 2, D 3));
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn success_to_json() {
+        assert_eq!(5u8.to_json(), Some(Value::Integer(5)));
+        assert_eq!(u64::MAX.to_json(), Some(Value::Float(u64::MAX as f64)));
+        assert_eq!(1.5f32.to_json(), Some(Value::Float(1.5)));
+        assert_eq!(f64::INFINITY.to_json(), None);
+        assert_eq!(vec![1.0, f64::NAN].to_json(), None);
+        assert_eq!("a\"".to_json_string().unwrap(), r#""a\"""#);
+        assert_eq!(
+            ('x', (), true).to_json_string().unwrap(),
+            r#"["x",null,true]"#
+        );
+        let mut map = BTreeMap::new();
+        map.insert("b".to_owned(), vec![Some(1)]);
+        map.insert("a".to_owned(), vec![None]);
+        assert_eq!(map.to_json_string().unwrap(), r#"{"a":[null],"b":[1]}"#);
+        let boxed: Box<[u8]> = Box::new([1, 2]);
+        assert_eq!(boxed.to_json_string_pretty().unwrap(), "[\n  1,\n  2\n]");
+    }
+
+    #[test]
+    pub fn success_from_json() {
+        assert_eq!(i8::from_json_string("-128"), Some(-128));
+        assert_eq!(u32::from_json_string("2.0"), Some(2));
+        assert_eq!(
+            u64::from_json_string("10000000000000000000"),
+            Some(10_000_000_000_000_000_000)
+        );
+        assert_eq!(f32::from_json_string("3"), Some(3.0));
+        assert_eq!(char::from_json_string("\"é\""), Some('é'));
+        assert_eq!(
+            <(String, Option<bool>)>::from_json_string(r#"["a", null]"#),
+            Some(("a".to_owned(), None))
+        );
+        let map: HashMap<String, Box<u8>> = FromJSON::from_json_string(r#"{"a": 1}"#).unwrap();
+        assert_eq!(*map["a"], 1);
+        assert_eq!(Value::from_json_string("[]"), Some(Value::Array(vec![])));
+    }
+
+    #[test]
+    pub fn fail_from_json() {
+        assert_eq!(u8::from_json_string("-1"), None);
+        assert_eq!(i32::from_json_string("1.5"), None);
+        assert_eq!(i64::from_json_string("\"1\""), None);
+        assert_eq!(bool::from_json_string("1"), None);
+        assert_eq!(char::from_json_string("\"ab\""), None);
+        assert_eq!(<Vec<u8>>::from_json_string("[1, \"2\"]"), None);
+        assert_eq!(<(u8, u8)>::from_json_string("[1, 2, 3]"), None);
+        assert_eq!(<(u8, u8)>::from_json_string("[1]"), None);
+        assert_eq!(String::from_json_string("\"a"), None);
+        assert_eq!(<()>::from_json_string("0"), None);
+    }
+}
@@ -0,0 +1,250 @@
+//! Dependency free json support for Octane. This crate holds the
+//! [`Value`](enum.Value.html) type, a parser and a serialiser for it
+//! and the [`ToJSON`](convert/trait.ToJSON.html)/[`FromJSON`](convert/trait.FromJSON.html)
+//! traits which convert rust types to and from json values.
+//!
+//! You would usually use this through `octane::json` and derive the traits
+//! with the `ToJSON` and `FromJSON` derive macros.
+//!
+//! # Example
+//!
+//! ```
+//! use octane_json::{FromJSON, ToJSON, Value};
+//!
+//! let value: Value = r#"{"name": "John", "tags": ["a", "b"]}"#.parse().unwrap();
+//! assert_eq!(value.get("name").and_then(Value::as_str), Some("John"));
+//! let tags: Vec<String> = FromJSON::from_json(value.get("tags").unwrap().clone()).unwrap();
+//! assert_eq!(tags.to_json_string().unwrap(), r#"["a","b"]"#);
+//! ```
+pub mod convert;
+mod parse;
+
+pub use crate::convert::{FromJSON, ToJSON};
+pub use crate::parse::ParseError;
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+/// A json value. Numbers without a fraction or an exponent that fit in an
+/// `i64` are parsed as `Integer`, every other number is a `Float`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(HashMap<String, Value>),
+}
+
+impl Value {
+    /// Parses a json document, surrounding whitespace is allowed but any
+    /// other trailing data is an error.
+    pub fn parse(data: &str) -> Result<Self, ParseError> {
+        parse::parse(data)
+    }
+
+    /// Serialises the value with two space indentation.
+    pub fn to_string_pretty(&self) -> String {
+        let mut ret = String::new();
+        write_value(self, &mut ret, Some(0));
+        ret
+    }
+
+    /// Returns the value of the key if the value is an object.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.as_object().and_then(|obj| obj.get(key))
+    }
+
+    /// Returns the element at the index if the value is an array.
+    pub fn index(&self, index: usize) -> Option<&Value> {
+        self.as_array().and_then(|arr| arr.get(index))
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == Value::Null
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as an integer, floats with no fractional part
+    /// that fit in an `i64` are converted.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Integer(i) => Some(*i),
+            Value::Float(f)
+                if f.fract() == 0.0 && *f >= i64::MIN as f64 && *f < i64::MAX as f64 =>
+            {
+                Some(*f as i64)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Integer(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Value>> {
+        match self {
+            Value::Array(arr) => Some(arr),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&HashMap<String, Value>> {
+        match self {
+            Value::Object(obj) => Some(obj),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Value {
+    type Err = ParseError;
+
+    fn from_str(data: &str) -> Result<Self, Self::Err> {
+        parse::parse(data)
+    }
+}
+
+/// Serialises the value without any whitespace. Object keys are written
+/// in sorted order so the output is stable.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut ret = String::new();
+        write_value(self, &mut ret, None);
+        f.write_str(&ret)
+    }
+}
+
+fn write_indent(out: &mut String, indent: Option<usize>) {
+    if let Some(level) = indent {
+        out.push('\n');
+        for _ in 0..level {
+            out.push_str("  ");
+        }
+    }
+}
+
+fn write_value(value: &Value, out: &mut String, indent: Option<usize>) {
+    let inner = indent.map(|level| level + 1);
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Integer(i) => {
+            let _ = write!(out, "{}", i);
+        }
+        // json has no representation for NaN and infinity
+        Value::Float(f) if !f.is_finite() => out.push_str("null"),
+        Value::Float(f) => {
+            let _ = write!(out, "{}", f);
+        }
+        Value::String(s) => write_string(s, out),
+        Value::Array(arr) => {
+            out.push('[');
+            for (i, val) in arr.iter().enumerate() {
+                if i != 0 {
+                    out.push(',');
+                }
+                write_indent(out, inner);
+                write_value(val, out, inner);
+            }
+            if !arr.is_empty() {
+                write_indent(out, indent);
+            }
+            out.push(']');
+        }
+        Value::Object(obj) => {
+            let mut keys: Vec<&String> = obj.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.iter().enumerate() {
+                if i != 0 {
+                    out.push(',');
+                }
+                write_indent(out, inner);
+                write_string(key, out);
+                out.push(':');
+                if indent.is_some() {
+                    out.push(' ');
+                }
+                write_value(&obj[*key], out, inner);
+            }
+            if !keys.is_empty() {
+                write_indent(out, indent);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn write_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn success_serialise() {
+        let value = Value::parse(r#"{"b": [1, 2.5, null], "a": "x\"\n\u0001", "c": {}}"#).unwrap();
+        assert_eq!(
+            value.to_string(),
+            r#"{"a":"x\"\n\u0001","b":[1,2.5,null],"c":{}}"#
+        );
+        assert_eq!(
+            value.to_string_pretty(),
+            "{\n  \"a\": \"x\\\"\\n\\u0001\",\n  \"b\": [\n    1,\n    2.5,\n    null\n  ],\n  \"c\": {}\n}"
+        );
+        assert_eq!(Value::Float(f64::NAN).to_string(), "null");
+        assert_eq!(Value::parse(&value.to_string()).unwrap(), value);
+    }
+
+    #[test]
+    pub fn success_accessors() {
+        let value: Value = r#"{"a": [true, 3, 4.0, 4.5]}"#.parse().unwrap();
+        let arr = value.get("a").unwrap();
+        assert_eq!(arr.index(0).and_then(Value::as_bool), Some(true));
+        assert_eq!(arr.index(1).and_then(Value::as_i64), Some(3));
+        assert_eq!(arr.index(2).and_then(Value::as_i64), Some(4));
+        assert_eq!(arr.index(3).and_then(Value::as_i64), None);
+        assert_eq!(arr.index(3).and_then(Value::as_f64), Some(4.5));
+        assert!(value.get("b").is_none());
+        assert!(arr.index(4).is_none());
+    }
+}
@@ -0,0 +1,316 @@
+use crate::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+/// Nesting depth after which parsing fails, so that untrusted input
+/// can't overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+/// The error returned when a json document is invalid, holds the byte
+/// offset at which parsing failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseError {
+    pub position: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid json at byte {}", self.position)
+    }
+}
+
+impl Error for ParseError {}
+
+pub fn parse(data: &str) -> Result<Value, ParseError> {
+    let mut parser = Parser {
+        data: data.as_bytes(),
+        pos: 0,
+    };
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    if parser.pos != parser.data.len() {
+        return Err(parser.error());
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self) -> ParseError {
+        ParseError { position: self.pos }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Result<u8, ParseError> {
+        let byte = self.peek().ok_or_else(|| self.error())?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), ParseError> {
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\n') | Some(b'\r') | Some(b'\t') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn literal(&mut self, lit: &[u8], value: Value) -> Result<Value, ParseError> {
+        if self.data[self.pos..].starts_with(lit) {
+            self.pos += lit.len();
+            Ok(value)
+        } else {
+            Err(self.error())
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, ParseError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error());
+        }
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'n') => self.literal(b"null", Value::Null),
+            Some(b't') => self.literal(b"true", Value::Bool(true)),
+            Some(b'f') => self.literal(b"false", Value::Bool(false)),
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b'[') => self.array(depth),
+            Some(b'{') => self.object(depth),
+            Some(b'-') | Some(b'0'..=b'9') => self.number(),
+            _ => Err(self.error()),
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Value, ParseError> {
+        self.expect(b'[')?;
+        let mut arr = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Value::Array(arr));
+        }
+        loop {
+            arr.push(self.value(depth + 1)?);
+            self.skip_whitespace();
+            match self.next()? {
+                b',' => continue,
+                b']' => return Ok(Value::Array(arr)),
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.error());
+                }
+            }
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Value, ParseError> {
+        self.expect(b'{')?;
+        let mut obj = HashMap::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Value::Object(obj));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(b':')?;
+            let value = self.value(depth + 1)?;
+            obj.insert(key, value);
+            self.skip_whitespace();
+            match self.next()? {
+                b',' => continue,
+                b'}' => return Ok(Value::Object(obj)),
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.error());
+                }
+            }
+        }
+    }
+
+    fn digits(&mut self) -> usize {
+        let start = self.pos;
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    fn number(&mut self) -> Result<Value, ParseError> {
+        let start = self.pos;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        let int_start = self.pos;
+        let int_len = self.digits();
+        if int_len == 0 || (int_len > 1 && self.data[int_start] == b'0') {
+            return Err(ParseError {
+                position: int_start,
+            });
+        }
+        let mut is_float = false;
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            is_float = true;
+            if self.digits() == 0 {
+                return Err(self.error());
+            }
+        }
+        if let Some(b'e') | Some(b'E') = self.peek() {
+            self.pos += 1;
+            is_float = true;
+            if let Some(b'+') | Some(b'-') = self.peek() {
+                self.pos += 1;
+            }
+            if self.digits() == 0 {
+                return Err(self.error());
+            }
+        }
+        // the slice only holds ascii characters which were checked above
+        let num = std::str::from_utf8(&self.data[start..self.pos]).unwrap();
+        if !is_float {
+            if let Ok(i) = num.parse() {
+                return Ok(Value::Integer(i));
+            }
+        }
+        num.parse()
+            .map(Value::Float)
+            .map_err(|_| ParseError { position: start })
+    }
+
+    fn hex_escape(&mut self) -> Result<u32, ParseError> {
+        let mut ret = 0;
+        for _ in 0..4 {
+            let digit = (self.next()? as char).to_digit(16).ok_or(ParseError {
+                position: self.pos - 1,
+            })?;
+            ret = ret * 16 + digit;
+        }
+        Ok(ret)
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.expect(b'"')?;
+        let mut ret = Vec::new();
+        loop {
+            match self.next()? {
+                b'"' => break,
+                b'\\' => {
+                    let c = match self.next()? {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let start = self.pos - 2;
+                            let mut code = self.hex_escape()?;
+                            // characters outside the bmp are escaped as a surrogate pair
+                            if (0xd800..0xdc00).contains(&code) {
+                                self.expect(b'\\')?;
+                                self.expect(b'u')?;
+                                let low = self.hex_escape()?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return Err(ParseError { position: start });
+                                }
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            }
+                            std::char::from_u32(code).ok_or(ParseError { position: start })?
+                        }
+                        _ => {
+                            self.pos -= 1;
+                            return Err(self.error());
+                        }
+                    };
+                    let mut buf = [0; 4];
+                    ret.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                0..=0x1f => {
+                    self.pos -= 1;
+                    return Err(self.error());
+                }
+                byte => ret.push(byte),
+            }
+        }
+        // the input is a str and escapes are pushed as whole characters
+        // so the bytes are always valid utf-8
+        Ok(String::from_utf8(ret).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn success_parse() {
+        let value = parse(
+            r#" {"a": [1, -2, 3.5, 1e3, -0, 12345678901234567890], "b": {"c": null},
+                "d": true, "e": false, "f": "\u00e9\ud83d\ude00\t\/", "g": []} "#,
+        )
+        .unwrap();
+        let mut obj = HashMap::new();
+        obj.insert(
+            "a".to_owned(),
+            Value::Array(vec![
+                Value::Integer(1),
+                Value::Integer(-2),
+                Value::Float(3.5),
+                Value::Float(1000.0),
+                Value::Integer(0),
+                Value::Float(12345678901234567890.0),
+            ]),
+        );
+        let mut b = HashMap::new();
+        b.insert("c".to_owned(), Value::Null);
+        obj.insert("b".to_owned(), Value::Object(b));
+        obj.insert("d".to_owned(), Value::Bool(true));
+        obj.insert("e".to_owned(), Value::Bool(false));
+        obj.insert("f".to_owned(), Value::String("é😀\t/".to_owned()));
+        obj.insert("g".to_owned(), Value::Array(vec![]));
+        assert_eq!(value, Value::Object(obj));
+        assert_eq!(parse("\"ünï\"").unwrap(), Value::String("ünï".to_owned()));
+    }
+
+    #[test]
+    pub fn fail_parse() {
+        assert_eq!(parse("").unwrap_err().position, 0);
+        assert_eq!(parse("[1,]").unwrap_err().position, 3);
+        assert_eq!(parse("{\"a\" 1}").unwrap_err().position, 5);
+        assert_eq!(parse("01").unwrap_err().position, 0);
+        assert_eq!(parse("1.").unwrap_err().position, 2);
+        assert_eq!(parse("true false").unwrap_err().position, 5);
+        assert_eq!(parse("\"a\nb\"").unwrap_err().position, 2);
+        assert_eq!(parse("\"\\ud83d\"").unwrap_err().position, 7);
+        assert_eq!(parse("\"\\x\"").unwrap_err().position, 2);
+        assert!(parse("nul").is_err());
+        assert!(parse("{1: 2}").is_err());
+        assert!(parse(&"[".repeat(MAX_DEPTH + 2)).is_err());
+        assert!(parse(&format!(
+            "{}{}",
+            "[".repeat(MAX_DEPTH),
+            "]".repeat(MAX_DEPTH)
+        ))
+        .is_ok());
+    }
+}
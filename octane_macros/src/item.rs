@@ -0,0 +1,339 @@
+use crate::util::extend;
+use proc_macro::{Delimiter, Spacing, TokenStream, TokenTree};
+
+pub struct Field {
+    pub ident: String,
+    pub key: String,
+    pub skip: bool,
+}
+
+pub enum Fields {
+    Named(Vec<Field>),
+    Unnamed(Vec<Field>),
+    Unit,
+}
+
+pub struct Variant {
+    pub ident: String,
+    pub key: String,
+    pub fields: Fields,
+}
+
+pub enum Data {
+    Struct(Fields),
+    Enum(Vec<Variant>),
+}
+
+pub struct ItemInfo {
+    pub name: String,
+    gen_between: TokenStream,
+    where_between: TokenStream,
+    generics: Vec<String>,
+    type_params: Vec<String>,
+    pub data: Data,
+}
+
+#[derive(Default)]
+struct Attrs {
+    rename: Option<String>,
+    skip: bool,
+}
+
+pub fn compile_error(msg: &str) -> TokenStream {
+    format!("compile_error!({:?});", msg).parse().unwrap()
+}
+
+fn is_punct(tok: &TokenTree, c: char) -> bool {
+    matches!(tok, TokenTree::Punct(p) if p.as_char() == c)
+}
+
+fn is_ident(tok: &TokenTree, name: &str) -> bool {
+    matches!(tok, TokenTree::Ident(i) if i.to_string() == name)
+}
+
+/// Splits the tokens on the commas which aren't inside of generics.
+fn split_commas(toks: TokenStream) -> Vec<Vec<TokenTree>> {
+    let mut ret = vec![Vec::new()];
+    let mut generic_level = 0;
+    let mut last_joint_dash = false;
+    for tok in toks {
+        if let TokenTree::Punct(p) = &tok {
+            match p.as_char() {
+                '<' => generic_level += 1,
+                // the arrow of a fn type isn't a closing bracket
+                '>' if !last_joint_dash => generic_level -= 1,
+                ',' if generic_level == 0 => {
+                    ret.push(Vec::new());
+                    continue;
+                }
+                _ => {}
+            }
+            last_joint_dash = p.as_char() == '-' && p.spacing() == Spacing::Joint;
+        } else {
+            last_joint_dash = false;
+        }
+        ret.last_mut().unwrap().push(tok);
+    }
+    if ret.last().map(Vec::is_empty).unwrap_or(false) {
+        ret.pop();
+    }
+    ret
+}
+
+fn parse_string(tok: Option<&TokenTree>) -> Result<String, String> {
+    if let Some(TokenTree::Literal(lit)) = tok {
+        let lit = lit.to_string();
+        if lit.len() >= 2 && lit.starts_with('"') && lit.ends_with('"') && !lit.contains('\\') {
+            return Ok(lit[1..lit.len() - 1].to_owned());
+        }
+    }
+    Err("Expected a string literal without escapes.".to_owned())
+}
+
+/// Consumes the attributes at the start of the tokens and returns the
+/// options of the `#[<attr>(...)]` ones.
+fn parse_attrs(toks: &[TokenTree], pos: &mut usize, attr: &str) -> Result<Attrs, String> {
+    let mut attrs = Attrs::default();
+    while *pos + 1 < toks.len() && is_punct(&toks[*pos], '#') {
+        let grp = match &toks[*pos + 1] {
+            TokenTree::Group(grp) if grp.delimiter() == Delimiter::Bracket => grp,
+            _ => break,
+        };
+        *pos += 2;
+        let inner: Vec<TokenTree> = grp.stream().into_iter().collect();
+        if !inner
+            .first()
+            .map(|tok| is_ident(tok, attr))
+            .unwrap_or(false)
+        {
+            continue;
+        }
+        let opts = match inner.get(1) {
+            Some(TokenTree::Group(opts)) if opts.delimiter() == Delimiter::Parenthesis => opts,
+            _ => return Err(format!("Expected #[{}(...)].", attr)),
+        };
+        for opt in split_commas(opts.stream()) {
+            match opt.first() {
+                Some(tok) if is_ident(tok, "skip") && opt.len() == 1 => attrs.skip = true,
+                Some(tok)
+                    if is_ident(tok, "rename") && opt.len() == 3 && is_punct(&opt[1], '=') =>
+                {
+                    attrs.rename = Some(parse_string(opt.get(2))?);
+                }
+                _ => {
+                    return Err(format!(
+                        "Unknown {} attribute, expected `rename = \"...\"` or `skip`.",
+                        attr
+                    ))
+                }
+            }
+        }
+    }
+    Ok(attrs)
+}
+
+fn skip_visibility(toks: &[TokenTree], pos: &mut usize) {
+    if toks
+        .get(*pos)
+        .map(|tok| is_ident(tok, "pub"))
+        .unwrap_or(false)
+    {
+        *pos += 1;
+        if let Some(TokenTree::Group(grp)) = toks.get(*pos) {
+            if grp.delimiter() == Delimiter::Parenthesis {
+                *pos += 1;
+            }
+        }
+    }
+}
+
+pub fn unraw(ident: &str) -> String {
+    ident.trim_start_matches("r#").to_owned()
+}
+
+fn parse_fields(grp: &TokenTree, attr: &str) -> Result<Fields, String> {
+    let grp = match grp {
+        TokenTree::Group(grp) => grp,
+        _ => return Ok(Fields::Unit),
+    };
+    let named = match grp.delimiter() {
+        Delimiter::Brace => true,
+        Delimiter::Parenthesis => false,
+        _ => return Err("Unexpected field delimiter.".to_owned()),
+    };
+    let mut fields = Vec::new();
+    for (i, seg) in split_commas(grp.stream()).into_iter().enumerate() {
+        let mut pos = 0;
+        let attrs = parse_attrs(&seg, &mut pos, attr)?;
+        skip_visibility(&seg, &mut pos);
+        let ident = if named {
+            match seg.get(pos) {
+                Some(TokenTree::Ident(ident)) => ident.to_string(),
+                _ => return Err("Expected a field name.".to_owned()),
+            }
+        } else {
+            i.to_string()
+        };
+        fields.push(Field {
+            key: attrs.rename.unwrap_or_else(|| unraw(&ident)),
+            ident,
+            skip: attrs.skip,
+        });
+    }
+    Ok(if named {
+        Fields::Named(fields)
+    } else {
+        Fields::Unnamed(fields)
+    })
+}
+
+fn parse_variants(toks: TokenStream, attr: &str) -> Result<Vec<Variant>, String> {
+    let mut variants = Vec::new();
+    for seg in split_commas(toks) {
+        let mut pos = 0;
+        let attrs = parse_attrs(&seg, &mut pos, attr)?;
+        if attrs.skip {
+            return Err("Variants can't be skipped.".to_owned());
+        }
+        let ident = match seg.get(pos) {
+            Some(TokenTree::Ident(ident)) => ident.to_string(),
+            _ => return Err("Expected a variant name.".to_owned()),
+        };
+        let fields = match seg.get(pos + 1) {
+            Some(grp @ TokenTree::Group(_)) => parse_fields(grp, attr)?,
+            _ => Fields::Unit,
+        };
+        variants.push(Variant {
+            key: attrs.rename.unwrap_or_else(|| unraw(&ident)),
+            ident,
+            fields,
+        });
+    }
+    Ok(variants)
+}
+
+/// Parses a struct or an enum, the field and variant options are read
+/// from the `#[<attr>(...)]` attributes.
+pub fn parse_item(toks: TokenStream, attr: &str) -> Result<ItemInfo, String> {
+    let toks: Vec<TokenTree> = toks.into_iter().collect();
+    let mut pos = toks
+        .iter()
+        .position(|tok| is_ident(tok, "struct") || is_ident(tok, "enum"))
+        .ok_or_else(|| "Only structs and enums are supported.".to_owned())?;
+    let is_enum = is_ident(&toks[pos], "enum");
+    let name = toks
+        .get(pos + 1)
+        .ok_or_else(|| "Expected a name.".to_owned())?
+        .to_string();
+    pos += 2;
+    let mut gen_between = TokenStream::new();
+    let mut generics = Vec::new();
+    let mut type_params = Vec::new();
+    if toks.get(pos).map(|tok| is_punct(tok, '<')).unwrap_or(false) {
+        let mut generic_level = 0;
+        let mut is_gen_name = true;
+        let mut in_default = false;
+        let mut last_joint_dash = false;
+        while let Some(tok) = toks.get(pos) {
+            pos += 1;
+            let c = match tok {
+                TokenTree::Punct(p) => Some(p.as_char()),
+                _ => None,
+            };
+            match c {
+                Some('<') => generic_level += 1,
+                Some('>') if !last_joint_dash => generic_level -= 1,
+                _ => {}
+            }
+            last_joint_dash = matches!(tok, TokenTree::Punct(p) if p.as_char() == '-' && p.spacing() == Spacing::Joint);
+            if generic_level == 1 && c == Some(',') {
+                is_gen_name = true;
+                in_default = false;
+            } else if generic_level == 1 && c == Some('=') {
+                // defaults aren't allowed on impl generics
+                in_default = true;
+            } else if generic_level == 1 && is_gen_name {
+                match tok {
+                    TokenTree::Punct(p) if p.as_char() == '\'' => {
+                        generics.push(format!("'{}", toks[pos]));
+                        is_gen_name = false;
+                    }
+                    TokenTree::Ident(i) if i.to_string() == "const" => {
+                        generics.push(toks[pos].to_string());
+                        is_gen_name = false;
+                    }
+                    TokenTree::Ident(i) => {
+                        generics.push(i.to_string());
+                        type_params.push(i.to_string());
+                        is_gen_name = false;
+                    }
+                    _ => {}
+                }
+            }
+            if !in_default || generic_level == 0 {
+                extend(&mut gen_between, tok.clone());
+            }
+            if generic_level == 0 {
+                break;
+            }
+        }
+    }
+    let mut where_between = TokenStream::new();
+    let mut body = None;
+    let mut in_where = false;
+    while let Some(tok) = toks.get(pos) {
+        pos += 1;
+        match tok {
+            TokenTree::Group(grp) if grp.delimiter() == Delimiter::Brace => {
+                body = Some(tok);
+                break;
+            }
+            TokenTree::Group(grp) if grp.delimiter() == Delimiter::Parenthesis && !in_where => {
+                body = Some(tok);
+            }
+            tok if is_punct(tok, ';') => break,
+            tok if is_ident(tok, "where") => in_where = true,
+            tok if in_where => extend(&mut where_between, tok.clone()),
+            _ => {}
+        }
+    }
+    let data = if is_enum {
+        match body {
+            Some(TokenTree::Group(grp)) => Data::Enum(parse_variants(grp.stream(), attr)?),
+            _ => return Err("Could not find the enum body.".to_owned()),
+        }
+    } else {
+        match body {
+            Some(grp) => Data::Struct(parse_fields(grp, attr)?),
+            None => Data::Struct(Fields::Unit),
+        }
+    };
+    Ok(ItemInfo {
+        name,
+        gen_between,
+        where_between,
+        generics,
+        type_params,
+        data,
+    })
+}
+
+/// Header of the trait impl, the type parameters are bound by `bound`.
+pub fn impl_header(info: &ItemInfo, trait_path: &str, bound: &str) -> String {
+    let gen_list = if info.generics.is_empty() {
+        String::new()
+    } else {
+        format!("<{}>", info.generics.join(", "))
+    };
+    let mut where_between = info.where_between.to_string();
+    if !where_between.trim().is_empty() && !where_between.trim_end().ends_with(',') {
+        where_between.push(',');
+    }
+    for param in &info.type_params {
+        where_between.push_str(&format!("{}: {},", param, bound));
+    }
+    format!(
+        "impl{} {} for {}{} where {}",
+        info.gen_between, trait_path, info.name, gen_list, where_between
+    )
+}
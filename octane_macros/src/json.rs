@@ -1,382 +1,262 @@
-use crate::util::extend;
-use proc_macro::{Delimiter, Ident, TokenStream, TokenTree};
+use crate::item::{compile_error, impl_header, parse_item, unraw, Data, Field, Fields, Variant};
+use proc_macro::TokenStream;
 
-pub struct StructInfo {
-    name: String,
-    gen_between: TokenStream,
-    where_between: TokenStream,
-    generics: Vec<String>,
+/// Path of the json module as seen from the crate using the derives
+const JSON: &str = "::octane::json";
+
+/// The name a field is bound to when destructuring an enum variant.
+fn binding(field: &Field) -> String {
+    format!("__field_{}", unraw(&field.ident))
 }
 
-pub fn handle_derive<
-    F: Fn(TokenStream, StructInfo) -> TokenStream,
-    K: Fn(TokenStream, StructInfo) -> TokenStream,
->(
-    toks: TokenStream,
-    process_braces: F,
-    process_parens: K,
-) -> TokenStream {
-    let mut tok_iter = toks.into_iter();
-    if tok_iter
-        .by_ref()
-        .find(|tok| match tok {
-            TokenTree::Ident(i) => i.to_string() == "struct",
-            _ => false,
-        })
-        .is_none()
-    {
-        return r#"compile_error!("Only structs are supported.");"#.parse().unwrap();
-    }
-    let name = tok_iter.next().unwrap().to_string();
-    let mut gen_between = TokenStream::new();
-    let mut where_between = TokenStream::new();
-    let mut cur = &mut gen_between;
-    let mut generic_level = 0;
-    let mut generics = Vec::new();
-    let mut is_gen_name = true;
-    for tok in tok_iter {
-        if tok.to_string() == "<" {
-            generic_level += 1;
-        }
-        if generic_level == 1 {
-            if is_gen_name {
-                if let TokenTree::Ident(i) = &tok {
-                    generics.push(i.to_string());
-                    is_gen_name = false;
-                }
-            } else if tok.to_string() == "," {
-                is_gen_name = true;
+/// Builds the pattern that destructures the variant into its bindings.
+fn variant_pattern(variant: &Variant) -> String {
+    match &variant.fields {
+        Fields::Named(fields) => {
+            let mut binds = String::new();
+            for field in fields.iter().filter(|field| !field.skip) {
+                binds.push_str(&format!("{}: {}, ", field.ident, binding(field)));
             }
+            format!("Self::{} {{ {}.. }}", variant.ident, binds)
         }
-        if tok.to_string() == ">" {
-            generic_level -= 1;
-        }
-        if generic_level != 0 {
-            extend(cur, tok);
-            continue;
-        }
-        if let TokenTree::Group(grp) = &tok {
-            match grp.delimiter() {
-                Delimiter::Brace => {
-                    return process_braces(
-                        grp.stream(),
-                        StructInfo {
-                            name,
-                            gen_between,
-                            where_between,
-                            generics,
-                        },
-                    )
-                }
-                Delimiter::Parenthesis => {
-                    return process_parens(
-                        grp.stream(),
-                        StructInfo {
-                            name,
-                            gen_between,
-                            where_between,
-                            generics,
-                        },
-                    )
-                }
-                _ => {}
-            };
-        }
-        if tok.to_string() == "where" {
-            cur = &mut where_between;
-            continue;
+        Fields::Unnamed(fields) => {
+            let binds: Vec<String> = fields
+                .iter()
+                .map(|field| {
+                    if field.skip {
+                        "_".to_owned()
+                    } else {
+                        binding(field)
+                    }
+                })
+                .collect();
+            format!("Self::{}({})", variant.ident, binds.join(", "))
         }
-        extend(cur, tok);
+        Fields::Unit => format!("Self::{}", variant.ident),
     }
-    r#"compile_error!("Could not find compatible struct body."#
-        .parse()
-        .unwrap()
 }
 
-pub fn derive_from_json(toks: TokenStream) -> TokenStream {
-    handle_derive(toks, fromjson_braces, fromjson_parens)
+fn object_to_json<F: Fn(&Field) -> String>(fields: &[Field], access: F) -> String {
+    let mut inserts = String::new();
+    for field in fields.iter().filter(|field| !field.skip) {
+        inserts.push_str(&format!(
+            "__obj.insert({:?}.to_owned(), {}::ToJSON::to_json(&{})?);",
+            field.key,
+            JSON,
+            access(field)
+        ));
+    }
+    format!(
+        "{{ let mut __obj = ::std::collections::HashMap::new(); {} {}::Value::Object(__obj) }}",
+        inserts, JSON
+    )
 }
 
-fn fromjson_braces(toks: TokenStream, mut info: StructInfo) -> TokenStream {
-    let mut fields: Vec<Ident> = Vec::new();
-    let mut is_field = true;
-    for tok in toks {
-        match tok {
-            TokenTree::Ident(ident) if is_field => {
-                is_field = false;
-                fields.push(ident);
-            }
-            TokenTree::Punct(p) if p.as_char() == ',' => {
-                is_field = true;
+fn array_to_json<F: Fn(&Field) -> String>(fields: &[Field], access: F) -> String {
+    let vals: Vec<String> = fields
+        .iter()
+        .filter(|field| !field.skip)
+        .map(|field| format!("{}::ToJSON::to_json(&{})?", JSON, access(field)))
+        .collect();
+    format!("{}::Value::Array(vec![{}])", JSON, vals.join(", "))
+}
+
+/// Tagged value of an enum variant, as in `{"Variant": ...}`.
+fn tagged(key: &str, inner: &str) -> String {
+    format!(
+        "{{ let mut __obj = ::std::collections::HashMap::new(); \
+        __obj.insert({:?}.to_owned(), {}); {}::Value::Object(__obj) }}",
+        key, inner, JSON
+    )
+}
+
+pub fn derive_to_json(toks: TokenStream) -> TokenStream {
+    let info = match parse_item(toks, "json") {
+        Ok(info) => info,
+        Err(e) => return compile_error(&e),
+    };
+    let body = match &info.data {
+        Data::Struct(Fields::Named(fields)) => {
+            format!(
+                "Some({})",
+                object_to_json(fields, |f| format!("self.{}", f.ident))
+            )
+        }
+        Data::Struct(Fields::Unnamed(fields)) => {
+            format!(
+                "Some({})",
+                array_to_json(fields, |f| format!("self.{}", f.ident))
+            )
+        }
+        Data::Struct(Fields::Unit) => format!("Some({}::Value::Null)", JSON),
+        Data::Enum(variants) if variants.is_empty() => "match *self {}".to_owned(),
+        Data::Enum(variants) => {
+            let mut arms = String::new();
+            for variant in variants {
+                let value = match &variant.fields {
+                    Fields::Named(fields) => tagged(&variant.key, &object_to_json(fields, binding)),
+                    Fields::Unnamed(fields) if fields.iter().filter(|f| !f.skip).count() == 1 => {
+                        let field = fields.iter().find(|f| !f.skip).unwrap();
+                        let inner = format!("{}::ToJSON::to_json({})?", JSON, binding(field));
+                        tagged(&variant.key, &inner)
+                    }
+                    Fields::Unnamed(fields) => {
+                        tagged(&variant.key, &array_to_json(fields, binding))
+                    }
+                    Fields::Unit => {
+                        format!("{}::Value::String({:?}.to_owned())", JSON, variant.key)
+                    }
+                };
+                arms.push_str(&format!("{} => Some({}),", variant_pattern(variant), value));
             }
-            _ => {}
-        };
-    }
-    let mut vals = String::new();
-    for field in fields {
-        vals.push_str(&format!(
-            "{0}: crate::FromJSON::from_json(obj.remove({0:?})?)?,",
-            field.to_string()
-        ));
-    }
-    let mut gen_list: String = String::new();
-    if !info.generics.is_empty() {
-        gen_list.push('<');
-    }
-    for (i, s) in info.generics.iter().enumerate() {
-        gen_list.push_str(s);
-        if i < info.generics.len() - 1 {
-            gen_list.push(',');
+            format!("match self {{ {} }}", arms)
         }
-    }
-    if !info.generics.is_empty() {
-        gen_list.push('>');
-    }
-    let mut comma = ", ";
-    if info
-        .where_between
-        .clone()
-        .into_iter()
-        .last()
-        .map(|v| v.to_string() == ",")
-        .unwrap_or(true)
-    {
-        comma = "";
-    }
-    for gen in info.generics {
-        info.where_between.extend::<TokenStream>(
-            format!("{}{}: crate::FromJSON", comma, gen)
-                .parse()
-                .unwrap(),
-        );
-        comma = ", ";
-    }
+    };
+    let to_json = format!("{}::ToJSON", JSON);
     format!(
-        "\
-    impl{} crate::FromJSON for {}{} where {} {{\
-        fn from_json(val: crate::Value) -> Option<Self> {{\
-            if let crate::Value::Object(mut obj) = val {{\
-                let ret = Self {{\
-                    {}\
-                }};\
-                if !obj.is_empty() {{\
-                    return None;\
-                }}\
-                Some(ret)\
-            }} else {{\
-                None\
-            }}\
-        }}\
-    }}",
-        info.gen_between, info.name, gen_list, info.where_between, vals
+        "{} {{ \
+            #[allow(unused_mut)] \
+            fn to_json(&self) -> Option<{}::Value> {{ {} }} \
+        }}",
+        impl_header(&info, &to_json, &to_json),
+        JSON,
+        body
     )
     .parse()
     .unwrap()
 }
 
-fn fromjson_parens(toks: TokenStream, mut info: StructInfo) -> TokenStream {
-    let mut fields = 0;
-    let mut is_field = true;
-    for tok in toks {
-        match tok {
-            TokenTree::Ident(_) if is_field => {
-                is_field = false;
-                fields += 1;
-            }
-            TokenTree::Punct(p) if p.as_char() == ',' => {
-                is_field = true;
-            }
-            _ => {}
-        };
-    }
+fn object_from_json(fields: &[Field], path: &str, val: &str) -> String {
     let mut vals = String::new();
-    for _ in 0..fields {
-        vals.push_str("crate::FromJSON::from_json(it.next()?)?,");
-    }
-    let mut gen_list: String = String::new();
-    if !info.generics.is_empty() {
-        gen_list.push('<');
-    }
-    for (i, s) in info.generics.iter().enumerate() {
-        gen_list.push_str(s);
-        if i < info.generics.len() - 1 {
-            gen_list.push(',');
+    for field in fields {
+        if field.skip {
+            vals.push_str(&format!(
+                "{}: ::std::default::Default::default(),",
+                field.ident
+            ));
+        } else {
+            // missing keys are read as null so that optional fields can be left out
+            vals.push_str(&format!(
+                "{0}: {1}::FromJSON::from_json(__obj.remove({2:?}).unwrap_or({1}::Value::Null))?,",
+                field.ident, JSON, field.key
+            ));
         }
     }
-    if !info.generics.is_empty() {
-        gen_list.push('>');
-    }
-    let mut comma = ", ";
-    if info
-        .where_between
-        .clone()
-        .into_iter()
-        .last()
-        .map(|v| v.to_string() == ",")
-        .unwrap_or(true)
-    {
-        comma = "";
-    }
-    for gen in info.generics {
-        info.where_between.extend::<TokenStream>(
-            format!("{}{}: crate::FromJSON", comma, gen)
-                .parse()
-                .unwrap(),
-        );
-        comma = ", ";
-    }
     format!(
-        "\
-    impl{} crate::FromJSON for {}{} where {} {{\
-        fn from_json(val: crate::Value) -> Option<Self> {{\
-            if let crate::Value::Array(arr) = val {{\
-                let mut it = arr.into_iter();
-                let ret = Self (\
-                    {}\
-                );\
-                if it.next().is_some() {{\
-                    return None;\
-                }}\
-                Some(ret)\
-            }} else {{\
-                None\
-            }}\
-        }}\
-    }}",
-        info.gen_between, info.name, gen_list, info.where_between, vals
+        "match {val} {{ \
+            {json}::Value::Object(mut __obj) => {{ \
+                let __ret = {path} {{ {vals} }}; \
+                if __obj.is_empty() {{ Some(__ret) }} else {{ None }} \
+            }} \
+            _ => None, \
+        }}",
+        val = val,
+        json = JSON,
+        path = path,
+        vals = vals
     )
-    .parse()
-    .unwrap()
 }
 
-pub fn derive_to_json(toks: TokenStream) -> TokenStream {
-    handle_derive(toks, tojson_braces, tojson_parens)
-}
-
-fn tojson_braces(toks: TokenStream, mut info: StructInfo) -> TokenStream {
-    let mut fields: Vec<Ident> = Vec::new();
-    let mut is_field = true;
-    for tok in toks {
-        match tok {
-            TokenTree::Ident(ident) if is_field => {
-                is_field = false;
-                fields.push(ident);
-            }
-            TokenTree::Punct(p) if p.as_char() == ',' => {
-                is_field = true;
-            }
-            _ => {}
-        };
-    }
+fn array_from_json(fields: &[Field], path: &str, val: &str) -> String {
     let mut vals = String::new();
     for field in fields {
-        vals.push_str(&format!(
-            "obj.insert({0:?}.to_owned(), crate::ToJSON::to_json(self.{0})?);",
-            field.to_string()
-        ));
-    }
-    let mut gen_list: String = String::new();
-    if !info.generics.is_empty() {
-        gen_list.push('<');
-    }
-    for (i, s) in info.generics.iter().enumerate() {
-        gen_list.push_str(s);
-        if i < info.generics.len() - 1 {
-            gen_list.push(',');
+        if field.skip {
+            vals.push_str("::std::default::Default::default(),");
+        } else {
+            vals.push_str(&format!("{}::FromJSON::from_json(__it.next()?)?,", JSON));
         }
     }
-    if !info.generics.is_empty() {
-        gen_list.push('>');
-    }
-    let mut comma = ", ";
-    if info
-        .where_between
-        .clone()
-        .into_iter()
-        .last()
-        .map(|v| v.to_string() == ",")
-        .unwrap_or(true)
-    {
-        comma = "";
-    }
-    for gen in info.generics {
-        info.where_between
-            .extend::<TokenStream>(format!("{}{}: crate::ToJSON", comma, gen).parse().unwrap());
-        comma = ", ";
-    }
     format!(
-        "\
-    impl{} crate::ToJSON for {}{} where {} {{\
-        fn to_json(self) -> Option<crate::Value> {{\
-            let mut obj = std::collections::HashMap::new();\
-            {}\
-            Some(crate::Value::Object(obj))\
-        }}\
-    }}",
-        info.gen_between, info.name, gen_list, info.where_between, vals
+        "match {val} {{ \
+            {json}::Value::Array(__arr) => {{ \
+                let mut __it = __arr.into_iter(); \
+                let __ret = {path}({vals}); \
+                if __it.next().is_none() {{ Some(__ret) }} else {{ None }} \
+            }} \
+            _ => None, \
+        }}",
+        val = val,
+        json = JSON,
+        path = path,
+        vals = vals
     )
-    .parse()
-    .unwrap()
 }
 
-fn tojson_parens(toks: TokenStream, mut info: StructInfo) -> TokenStream {
-    let mut fields = 0;
-    let mut is_field = true;
-    for tok in toks {
-        match tok {
-            TokenTree::Ident(_) if is_field => {
-                is_field = false;
-                fields += 1;
-            }
-            TokenTree::Punct(p) if p.as_char() == ',' => {
-                is_field = true;
+pub fn derive_from_json(toks: TokenStream) -> TokenStream {
+    let info = match parse_item(toks, "json") {
+        Ok(info) => info,
+        Err(e) => return compile_error(&e),
+    };
+    let body = match &info.data {
+        Data::Struct(Fields::Named(fields)) => object_from_json(fields, "Self", "val"),
+        Data::Struct(Fields::Unnamed(fields)) => array_from_json(fields, "Self", "val"),
+        Data::Struct(Fields::Unit) => format!(
+            "match val {{ {}::Value::Null => Some(Self), _ => None }}",
+            JSON
+        ),
+        Data::Enum(variants) => {
+            let mut unit_arms = String::new();
+            let mut tagged_arms = String::new();
+            for variant in variants {
+                let path = format!("Self::{}", variant.ident);
+                match &variant.fields {
+                    Fields::Unit => {
+                        unit_arms.push_str(&format!("{:?} => Some({}),", variant.key, path));
+                    }
+                    Fields::Named(fields) => tagged_arms.push_str(&format!(
+                        "{:?} => {},",
+                        variant.key,
+                        object_from_json(fields, &path, "__val")
+                    )),
+                    Fields::Unnamed(fields) if fields.iter().filter(|f| !f.skip).count() == 1 => {
+                        let vals: Vec<String> = fields
+                            .iter()
+                            .map(|f| {
+                                if f.skip {
+                                    "::std::default::Default::default()".to_owned()
+                                } else {
+                                    format!("{}::FromJSON::from_json(__val)?", JSON)
+                                }
+                            })
+                            .collect();
+                        tagged_arms.push_str(&format!(
+                            "{:?} => Some({}({})),",
+                            variant.key,
+                            path,
+                            vals.join(", ")
+                        ));
+                    }
+                    Fields::Unnamed(fields) => tagged_arms.push_str(&format!(
+                        "{:?} => {},",
+                        variant.key,
+                        array_from_json(fields, &path, "__val")
+                    )),
+                }
             }
-            _ => {}
-        };
-    }
-    let mut vals = String::new();
-    for i in 0..fields {
-        vals.push_str(&format!("arr.push(crate::ToJSON::to_json(self.{})?);", i));
-    }
-    let mut gen_list: String = String::new();
-    if !info.generics.is_empty() {
-        gen_list.push('<');
-    }
-    for (i, s) in info.generics.iter().enumerate() {
-        gen_list.push_str(s);
-        if i < info.generics.len() - 1 {
-            gen_list.push(',');
+            format!(
+                "match val {{ \
+                    {json}::Value::String(__tag) => match __tag.as_str() {{ {unit} _ => None }}, \
+                    {json}::Value::Object(__tagged) if __tagged.len() == 1 => {{ \
+                        let (__tag, __val) = __tagged.into_iter().next()?; \
+                        match __tag.as_str() {{ {tagged} _ => None }} \
+                    }} \
+                    _ => None, \
+                }}",
+                json = JSON,
+                unit = unit_arms,
+                tagged = tagged_arms
+            )
         }
-    }
-    if !info.generics.is_empty() {
-        gen_list.push('>');
-    }
-    let mut comma = ", ";
-    if info
-        .where_between
-        .clone()
-        .into_iter()
-        .last()
-        .map(|v| v.to_string() == ",")
-        .unwrap_or(true)
-    {
-        comma = "";
-    }
-    for gen in info.generics {
-        info.where_between
-            .extend::<TokenStream>(format!("{}{}: crate::ToJSON", comma, gen).parse().unwrap());
-        comma = ", ";
-    }
+    };
+    let from_json = format!("{}::FromJSON", JSON);
     format!(
-        "\
-    impl{} crate::ToJSON for {}{} where {} {{\
-        fn to_json(self) -> Option<crate::Value> {{\
-            let mut arr = Vec::new();\
-            {}\
-            Some(crate::Value::Array(arr))\
-        }}\
-    }}",
-        info.gen_between, info.name, gen_list, info.where_between, vals
+        "{} {{ \
+            #[allow(unused_variables)] \
+            fn from_json(val: {}::Value) -> Option<Self> {{ {} }} \
+        }}",
+        impl_header(&info, &from_json, &from_json),
+        JSON,
+        body
     )
     .parse()
     .unwrap()
@@ -1,6 +1,8 @@
 extern crate proc_macro;
 
+mod item;
 mod json;
+mod query;
 mod status;
 mod stream_parser;
 mod util;
@@ -19,8 +21,14 @@ pub fn status_codes(toks: TokenStream) -> TokenStream {
     status::status_codes(toks)
 }
 
-/// Derive FromJSON to implement the [`FromJSON`](convert/trait.FromJSON.html) trait that allows
-/// deserialising struct values to a struct
+/// Derive FromJSON to implement the [`FromJSON`](../octane/json/trait.FromJSON.html) trait that allows
+/// deserialising json values to a struct or an enum
+///
+/// Structs with named fields are read from objects and tuple structs from
+/// arrays. Missing keys are read as `null` so `Option` fields can be left out,
+/// while unknown keys make the conversion fail. Enums follow the same format
+/// as [`ToJSON`](derive.ToJSON.html). Fields can be renamed with
+/// `#[json(rename = "...")]`, skipped fields are set to their `Default` value.
 ///
 /// # Example
 ///
@@ -30,30 +38,30 @@ pub fn status_codes(toks: TokenStream) -> TokenStream {
 /// #[derive(FromJSON, ToJSON)]
 /// struct User {
 ///     id: u64,
+///     #[json(rename = "userName")]
 ///     name: String,
-///     email: String,
-/// }
-///
-/// impl User {
-///     pub fn new() -> Self {
-///         Self {
-///             id: 1,
-///             name: "John Doe".to_string(),
-///             email: "JohnDoe@hotmail.com".to_string(),
-///         }
-///     }
+///     email: Option<String>,
+///     #[json(skip)]
+///     logged_in: bool,
 /// }
 ///
-/// let json_string = User::new().to_json_string().unwrap();
-/// let user_back: Option<User> = User::from_json_string(&json_string);
+/// let user = User::from_json_string(r#"{"id": 1, "userName": "John Doe"}"#).unwrap();
+/// assert_eq!(user.email, None);
 /// ```
-#[proc_macro_derive(FromJSON)]
+#[proc_macro_derive(FromJSON, attributes(json))]
 pub fn derive_from_json(toks: TokenStream) -> TokenStream {
     json::derive_from_json(toks)
 }
 
-/// Derive ToJSON to implement [`ToJSON`](convert/trait.ToJSON.html) trait that allows
-/// serialising struct values to valid json
+/// Derive ToJSON to implement [`ToJSON`](../octane/json/trait.ToJSON.html) trait that allows
+/// serialising struct and enum values to valid json
+///
+/// Structs with named fields become objects, tuple structs arrays and unit
+/// structs `null`. Unit variants of enums become their name as a string and
+/// other variants an object with the name as the only key, holding the
+/// fields like a struct would, or the value itself if there's a single
+/// field. Use `#[json(rename = "...")]` on a field or a variant to change its
+/// key and `#[json(skip)]` to leave a field out.
 ///
 /// # Example
 ///
@@ -61,34 +69,57 @@ pub fn derive_from_json(toks: TokenStream) -> TokenStream {
 /// use octane::prelude::*;
 ///
 /// #[derive(ToJSON)]
-/// struct User {
-///     id: u64,
-///     name: String,
-///     email: String,
+/// enum Shape {
+///     Empty,
+///     Circle(f64),
+///     #[json(rename = "rect")]
+///     Rectangle { width: f64, height: f64 },
 /// }
 ///
-/// impl User {
-///     pub fn new() -> Self {
-///         Self {
-///             id: 1,
-///             name: "John Doe".to_string(),
-///             email: "JohnDoe@hotmail.com".to_string(),
-///         }
-///     }
-/// }
-///
-/// let json_string = User::new().to_json_string();
+/// assert_eq!(Shape::Empty.to_json_string().unwrap(), r#""Empty""#);
+/// assert_eq!(Shape::Circle(1.5).to_json_string().unwrap(), r#"{"Circle":1.5}"#);
 /// ```
-#[proc_macro_derive(ToJSON)]
+#[proc_macro_derive(ToJSON, attributes(json))]
 pub fn derive_to_json(toks: TokenStream) -> TokenStream {
     json::derive_to_json(toks)
 }
 
+/// Derive FromQuery to implement the [`FromQuery`](../octane/extract/trait.FromQuery.html)
+/// trait that builds a struct from the fields of a query string or an url encoded form
+///
+/// Every field is parsed with the `FromParam` trait, `Option` fields may be
+/// missing and unknown fields are ignored. Use `#[query(rename = "...")]` on a
+/// field to change its key and `#[query(skip)]` to set it to its `Default` value.
+///
+/// # Example
+///
+/// ```
+/// use octane::extract::FromQuery;
+///
+/// #[derive(FromQuery)]
+/// struct Search {
+///     #[query(rename = "q")]
+///     text: String,
+///     page: Option<u32>,
+/// }
+/// ```
+#[proc_macro_derive(FromQuery, attributes(query))]
+pub fn derive_from_query(toks: TokenStream) -> TokenStream {
+    query::derive_from_query(toks)
+}
+
 /// The main attribute is just like #[tokio::main] but it defines
 /// some parameters which are specific to octane
 ///
-/// octane::main attribute sets the thread stack to 10 megabytes, core threads
-/// to the number of cpus available * 2, use this in production
+/// octane::main attribute builds the runtime when the program starts, with a
+/// worker thread per cpu and a 10 megabytes thread stack. The `OCTANE_WORKER_THREADS`,
+/// `OCTANE_MAX_BLOCKING_THREADS`, `OCTANE_THREAD_STACK_SIZE` and `OCTANE_THREAD_NAME`
+/// environment variables override them, use this in production
+///
+/// The runtime is built before the app, so the thread settings of its
+/// config, like `app.settings.worker_threads(2)`, don't apply to it. Use
+/// the environment variables, or `Octane::run` from a synchronous main
+/// to build the runtime from the config of the app
 ///
 /// # Example
 ///
@@ -109,35 +140,28 @@ pub fn derive_to_json(toks: TokenStream) -> TokenStream {
 ///         ),
 ///     )?;
 ///
-///     app.listen(8080).await
+///     app.listen(8080, || {}).await
 /// }
 /// ```
 #[proc_macro_attribute]
 pub fn main(_attr: TokenStream, item: TokenStream) -> TokenStream {
     let stream = StreamParser::new(item.into());
     let properties = stream.parse();
-    let num_cpus = num_cpus::get() * 2;
-    let compile_error;
-    if properties.is_async {
-        compile_error = quote! {};
+    let compile_error = if properties.is_async {
+        quote! {}
     } else {
-        compile_error = quote! {
+        quote! {
             compile_error!("the async keyword is missing from function declaration");
         }
-    }
+    };
     let signature = properties.signature;
     let rest = properties.rest;
     let tokens = quote! {
         #signature {
             #compile_error
-            let mut builder = tokio::runtime::Builder::new_multi_thread();
-            builder
-                .enable_io()
-                .thread_stack_size(10485760)
-                .thread_name("octane-main")
-                .worker_threads(#num_cpus);
-
-            let mut runtime = builder.build().expect("Unable to build tokio runtime");
+            let runtime = octane::config::OctaneConfig::new()
+                .build_runtime()
+                .expect("Unable to build tokio runtime");
             runtime.block_on(async {
                 #rest
             })
@@ -156,14 +180,13 @@ pub fn main(_attr: TokenStream, item: TokenStream) -> TokenStream {
 pub fn test(_attr: TokenStream, item: TokenStream) -> TokenStream {
     let stream = StreamParser::new(item.into());
     let properties = stream.parse();
-    let compile_error;
-    if properties.is_async {
-        compile_error = quote! {};
+    let compile_error = if properties.is_async {
+        quote! {}
     } else {
-        compile_error = quote! {
+        quote! {
             compile_error!("the async keyword is missing from function declaration");
         }
-    }
+    };
     let signature = properties.signature;
     let rest = properties.rest;
     let tokens = quote! {
@@ -172,7 +195,7 @@ pub fn test(_attr: TokenStream, item: TokenStream) -> TokenStream {
             #compile_error
             let mut builder = tokio::runtime::Builder::new_current_thread();
             builder
-                .enable_io()
+                .enable_all()
                 .thread_name("octane-test");
 
             let mut runtime = builder.build().expect("Unable to build tokio runtime");
@@ -193,7 +216,9 @@ pub fn test(_attr: TokenStream, item: TokenStream) -> TokenStream {
 /// # Example
 ///
 /// ```
-/// let path = path!("/templates");
+/// use octane::prelude::*;
+///
+/// let path = path!("/src");
 /// ```
 ///
 /// This allows for safety, you cannot compile code that has
@@ -201,7 +226,7 @@ pub fn test(_attr: TokenStream, item: TokenStream) -> TokenStream {
 #[proc_macro]
 pub fn path(input: TokenStream) -> TokenStream {
     let input: Vec<TokenTree> = input.into_iter().collect();
-    let value = match &input.get(0) {
+    let value = match &input.first() {
         Some(TokenTree::Literal(literal)) => literal.to_string(),
         _ => panic!(),
     };
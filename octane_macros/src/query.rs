@@ -0,0 +1,48 @@
+use crate::item::{compile_error, impl_header, parse_item, Data, Fields};
+use proc_macro::TokenStream;
+
+/// Path of the extract module as seen from the crate using the derive
+const EXTRACT: &str = "::octane::extract";
+
+pub fn derive_from_query(toks: TokenStream) -> TokenStream {
+    let info = match parse_item(toks, "query") {
+        Ok(info) => info,
+        Err(e) => return compile_error(&e),
+    };
+    let fields = match &info.data {
+        Data::Struct(Fields::Named(fields)) => fields,
+        _ => return compile_error("Only structs with named fields are supported."),
+    };
+    let mut vals = String::new();
+    for field in fields {
+        if field.skip {
+            vals.push_str(&format!(
+                "{}: ::std::default::Default::default(),",
+                field.ident
+            ));
+        } else {
+            vals.push_str(&format!(
+                "{0}: match query.get({1:?}) {{ \
+                    Some(value) => {2}::FromParam::from_param(value)?, \
+                    None => {2}::FromParam::from_missing()?, \
+                }},",
+                field.ident, field.key, EXTRACT
+            ));
+        }
+    }
+    format!(
+        "{} {{ \
+            fn from_query(query: &::std::collections::HashMap<String, String>) -> Option<Self> {{ \
+                Some(Self {{ {} }}) \
+            }} \
+        }}",
+        impl_header(
+            &info,
+            &format!("{}::FromQuery", EXTRACT),
+            &format!("{}::FromParam", EXTRACT)
+        ),
+        vals
+    )
+    .parse()
+    .unwrap()
+}
@@ -0,0 +1,266 @@
+#[cfg(feature = "compression")]
+use crate::responder::BoxReader;
+#[cfg(feature = "compression")]
+use async_compression::tokio_03::bufread::{BrotliEncoder, GzipEncoder, ZlibEncoder};
+#[cfg(feature = "compression")]
+use async_compression::Level;
+#[cfg(feature = "compression")]
+use flate2::read::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
+use octane_http::StatusCode;
+#[cfg(feature = "compression")]
+use std::io::Read;
+#[cfg(feature = "compression")]
+use tokio::io::BufReader;
+use tokio::task;
+
+// Bodies smaller than this don't shrink enough to pay for the
+// encoder and the chunked framing
+#[cfg(feature = "compression")]
+pub(crate) const MIN_COMPRESS_LEN: usize = 1024;
+
+/// A content coding the server can produce
+#[cfg_attr(not(feature = "compression"), allow(dead_code))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Encoding {
+    Brotli,
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    // in the order of preference when the client weighs them equally
+    #[cfg_attr(not(feature = "compression"), allow(dead_code))]
+    pub(crate) const SUPPORTED: [Encoding; 3] =
+        [Encoding::Brotli, Encoding::Gzip, Encoding::Deflate];
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+    /// Picks the coding the client prefers among the offered ones from
+    /// an `Accept-Encoding` value, None if it accepts none of them or
+    /// prefers `identity`
+    pub(crate) fn negotiate(accept: &str, offered: &[Encoding]) -> Option<Self> {
+        let mut weights = Vec::new();
+        for item in accept.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+            let mut params = item.split(';').map(str::trim);
+            let coding = params.next().unwrap_or("").to_ascii_lowercase();
+            let mut quality = 1.0;
+            for param in params {
+                if let Some((key, value)) = param.split_once('=') {
+                    if key.trim().eq_ignore_ascii_case("q") {
+                        quality = value.trim().parse().unwrap_or(0.0);
+                    }
+                }
+            }
+            weights.push((coding, quality));
+        }
+        let weight = |name: &str| -> Option<f32> {
+            weights
+                .iter()
+                .find(|(coding, _)| coding == name || (name == "gzip" && coding == "x-gzip"))
+                .or_else(|| weights.iter().find(|(coding, _)| coding == "*"))
+                .map(|(_, quality)| *quality)
+        };
+        let identity = weights
+            .iter()
+            .find(|(coding, _)| coding == "identity")
+            .map_or(0.0, |(_, quality)| *quality);
+        let mut best: Option<(Encoding, f32)> = None;
+        for encoding in offered {
+            if let Some(quality) = weight(encoding.name()) {
+                if quality > 0.0 && best.map_or(true, |(_, max)| quality > max) {
+                    best = Some((*encoding, quality));
+                }
+            }
+        }
+        best.filter(|(_, quality)| *quality >= identity)
+            .map(|(encoding, _)| encoding)
+    }
+    /// Wraps a body in a streaming encoder
+    #[cfg(feature = "compression")]
+    pub(crate) fn encode(self, body: BoxReader) -> BoxReader {
+        let body = BufReader::new(body);
+        match self {
+            // the default brotli quality is far too slow to
+            // compress responses as they are sent
+            Encoding::Brotli => Box::new(BrotliEncoder::with_quality(body, Level::Precise(4))),
+            Encoding::Gzip => Box::new(GzipEncoder::new(body)),
+            Encoding::Deflate => Box::new(ZlibEncoder::new(body)),
+        }
+    }
+}
+
+/// Decodes a request body sent with the given `Content-Encoding`, None
+/// if it isn't encoded. Unknown codings give a 415 and a body growing
+/// past `max` once decoded gives a 413. Inflating is CPU bound, it
+/// runs on the blocking threads rather than on the workers
+pub(crate) async fn decode_body(
+    body: &[u8],
+    content_encoding: &str,
+    max: Option<usize>,
+) -> Result<Option<Vec<u8>>, StatusCode> {
+    let codings: Vec<String> = content_encoding
+        .split(',')
+        .map(|coding| coding.trim().to_ascii_lowercase())
+        .filter(|coding| !coding.is_empty() && coding != "identity")
+        .collect();
+    if codings.is_empty() {
+        return Ok(None);
+    }
+    let mut decoded = body.to_vec();
+    task::spawn_blocking(move || {
+        // the codings are listed in the order they were applied
+        for coding in codings.iter().rev() {
+            decoded = decode(&decoded, coding, max)?;
+        }
+        Ok(Some(decoded))
+    })
+    .await
+    .map_err(|_| StatusCode::InternalServerError)?
+}
+
+#[cfg(feature = "compression")]
+fn decode(body: &[u8], coding: &str, max: Option<usize>) -> Result<Vec<u8>, StatusCode> {
+    match coding {
+        "gzip" | "x-gzip" => read_limited(MultiGzDecoder::new(body), max),
+        // deflate is meant to be zlib wrapped, but some clients
+        // send the raw stream
+        "deflate" if is_zlib(body) => read_limited(ZlibDecoder::new(body), max),
+        "deflate" => read_limited(DeflateDecoder::new(body), max),
+        _ => Err(StatusCode::UnsupportedMediaType),
+    }
+}
+
+#[cfg(not(feature = "compression"))]
+fn decode(_: &[u8], _: &str, _: Option<usize>) -> Result<Vec<u8>, StatusCode> {
+    Err(StatusCode::UnsupportedMediaType)
+}
+
+#[cfg(feature = "compression")]
+fn is_zlib(body: &[u8]) -> bool {
+    match body {
+        [cmf, flg, ..] => cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0,
+        _ => false,
+    }
+}
+
+// Reads at most one byte past the limit, that's enough to
+// know the body is too large without inflating all of it
+#[cfg(feature = "compression")]
+fn read_limited<R: Read>(reader: R, max: Option<usize>) -> Result<Vec<u8>, StatusCode> {
+    let limit = max.map_or(u64::MAX, |max| max as u64 + 1);
+    let mut decoded = Vec::new();
+    reader
+        .take(limit)
+        .read_to_end(&mut decoded)
+        .map_err(|_| StatusCode::BadRequest)?;
+    if max.map_or(false, |max| decoded.len() > max) {
+        return Err(StatusCode::PayloadTooLarge);
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn success_negotiate() {
+        let negotiate = |accept| Encoding::negotiate(accept, &Encoding::SUPPORTED);
+        assert_eq!(negotiate("gzip, deflate, br"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("gzip;q=1.0, br;q=0.5"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("deflate, gzip;q=0.8"), Some(Encoding::Deflate));
+        assert_eq!(negotiate("x-gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("*;q=0.5, br;q=0"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("GZIP ; Q=0.3"), Some(Encoding::Gzip));
+    }
+
+    #[test]
+    fn fail_negotiate() {
+        let negotiate = |accept| Encoding::negotiate(accept, &Encoding::SUPPORTED);
+        assert_eq!(negotiate(""), None);
+        assert_eq!(negotiate("identity"), None);
+        assert_eq!(negotiate("compress, zstd"), None);
+        assert_eq!(negotiate("gzip;q=0"), None);
+        assert_eq!(negotiate("gzip;q=0.2, identity;q=0.5"), None);
+        assert_eq!(Encoding::negotiate("br", &[Encoding::Gzip]), None);
+    }
+
+    #[cfg(feature = "compression")]
+    #[crate::test]
+    async fn success_decode_body() {
+        use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+        use flate2::Compression;
+        use std::io::Write;
+
+        let body = b"{\"event\": \"click\"}\n".repeat(100);
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(&body).unwrap();
+        let gzip = gzip.finish().unwrap();
+        assert_eq!(
+            decode_body(&gzip, "gzip", None).await,
+            Ok(Some(body.clone()))
+        );
+        assert_eq!(
+            decode_body(&gzip, "identity, GZIP", None).await,
+            Ok(Some(body.clone()))
+        );
+
+        let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+        zlib.write_all(&body).unwrap();
+        let zlib = zlib.finish().unwrap();
+        assert_eq!(
+            decode_body(&zlib, "deflate", None).await,
+            Ok(Some(body.clone()))
+        );
+        let mut raw = DeflateEncoder::new(Vec::new(), Compression::default());
+        raw.write_all(&body).unwrap();
+        let raw = raw.finish().unwrap();
+        assert_eq!(
+            decode_body(&raw, "deflate", None).await,
+            Ok(Some(body.clone()))
+        );
+
+        // applied in order, gzip first
+        let mut both = ZlibEncoder::new(Vec::new(), Compression::default());
+        both.write_all(&gzip).unwrap();
+        let both = both.finish().unwrap();
+        assert_eq!(
+            decode_body(&both, "gzip, deflate", None).await,
+            Ok(Some(body))
+        );
+        assert_eq!(decode_body(b"plain", "identity", None).await, Ok(None));
+    }
+
+    #[crate::test]
+    async fn fail_decode_body() {
+        assert_eq!(
+            decode_body(b"data", "compress", None).await,
+            Err(StatusCode::UnsupportedMediaType)
+        );
+        #[cfg(feature = "compression")]
+        {
+            use flate2::write::GzEncoder;
+            use flate2::Compression;
+            use std::io::Write;
+
+            // a small body inflating far past the limit
+            let mut bomb = GzEncoder::new(Vec::new(), Compression::best());
+            bomb.write_all(&vec![0; 1024 * 1024]).unwrap();
+            let bomb = bomb.finish().unwrap();
+            assert!(bomb.len() < 4096);
+            assert_eq!(
+                decode_body(&bomb, "gzip", Some(64 * 1024)).await,
+                Err(StatusCode::PayloadTooLarge)
+            );
+            assert_eq!(
+                decode_body(b"not gzip", "gzip", None).await,
+                Err(StatusCode::BadRequest)
+            );
+        }
+    }
+}
@@ -1,6 +1,11 @@
 use crate::default;
 use core::time::Duration;
+use std::env;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::path::PathBuf;
+use std::str::FromStr;
+use tokio::runtime::{Builder, Runtime};
 #[cfg(feature = "rustls")]
 use tokio_rustls::rustls::{
     internal::pemfile::{certs, rsa_private_keys},
@@ -80,6 +85,77 @@ impl Ssl {
     }
 }
 
+/// Options for the Unix domain socket created by
+/// [`listen_unix`](crate::Octane::listen_unix). By default
+/// the socket file keeps the permissions given by the
+/// umask and belongs to the user running the server
+///
+/// ```no_run
+/// use octane::config::OctaneConfig;
+///
+/// let mut config = OctaneConfig::new();
+/// config.unix.mode(0o660).group(33);
+/// ```
+#[derive(Clone)]
+pub struct UnixSocket {
+    /// The permission bits of the socket file, like `0o660`.
+    pub mode: Option<u32>,
+    /// The user id that should own the socket file.
+    pub owner: Option<u32>,
+    /// The group id that should own the socket file.
+    pub group: Option<u32>,
+}
+
+impl UnixSocket {
+    /// Returns a new UnixSocket struct instance which leaves
+    /// the socket file as it is created
+    pub fn new() -> Self {
+        UnixSocket {
+            mode: None,
+            owner: None,
+            group: None,
+        }
+    }
+    /// Sets the permission bits of the socket file
+    pub fn mode(&mut self, mode: u32) -> &mut Self {
+        self.mode = Some(mode);
+        self
+    }
+    /// Sets the user id that owns the socket file
+    pub fn owner(&mut self, uid: u32) -> &mut Self {
+        self.owner = Some(uid);
+        self
+    }
+    /// Sets the group id that owns the socket file
+    pub fn group(&mut self, gid: u32) -> &mut Self {
+        self.group = Some(gid);
+        self
+    }
+}
+
+/// How the `ETag` of a file sent with
+/// [`send_file`](crate::responder::Response::send_file) is computed
+///
+/// ```no_run
+/// use octane::config::{EntityTag, OctaneConfig};
+///
+/// let mut config = OctaneConfig::new();
+/// config.entity_tag = EntityTag::Strong;
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntityTag {
+    /// A weak tag made of the size and the modification time of
+    /// the file, it's cheap but can't be used for ranges
+    Weak,
+    /// A strong tag made of a hash of the file contents, the whole
+    /// file is read on the blocking threads the first time it's
+    /// sent, the tag is cached until its size or modification time
+    /// change
+    Strong,
+    /// No `ETag` is sent, `Last-Modified` is still sent
+    None,
+}
+
 /// An independent OctaneConfig struct that can be used
 /// separately from the app structure and then be appended
 /// to it.
@@ -101,11 +177,48 @@ impl Ssl {
 /// item. If no config is specified then defaults are used.
 ///
 pub struct OctaneConfig {
-    /// The duration for keep alive requests. It is 5 seconds by default
+    /// The duration for keep alive requests, a persistent connection is closed
+    /// when it stays idle for longer. It is 5 seconds by default, `None` disables
+    /// keep alive
     pub keep_alive: Option<Duration>,
+    /// How long a new connection may take to send the head of its first
+    /// request, it is closed when it takes longer. It is 10 seconds by
+    /// default, `None` waits as long as the client wants
+    pub header_timeout: Option<Duration>,
+    /// How long a graceful shutdown waits for open connections to finish
+    /// before returning anyway. It is 30 seconds by default, `None` waits
+    /// for all of them
+    pub shutdown_timeout: Option<Duration>,
     /// An instance of the `Ssl` struct to store the values of key and certificates.
     pub ssl: Ssl,
+    /// The interfaces that [`listen`](crate::Octane::listen) binds to, both
+    /// for plain and Ssl connections. The server listens on every IPv4
+    /// interface when it's empty, which is the default
+    pub hosts: Vec<IpAddr>,
+    /// Permissions and ownership of the socket file when listening
+    /// on a Unix domain socket.
+    pub unix: UnixSocket,
+    /// The kind of `ETag` sent along with files, weak by default.
+    pub entity_tag: EntityTag,
+    /// The largest request body the server reads, a bigger one is
+    /// rejected with a 413 before any of it is buffered. It is 10 MiB
+    /// by default, `None` removes the limit. Routes can have their own
+    /// limit with [`body_limit`](crate::Octane::body_limit)
+    pub max_body_size: Option<usize>,
+    /// The longest request target, a longer one gets a 414. It is
+    /// 8 KiB by default
+    pub max_uri_len: usize,
+    /// The largest header section of a request, a larger one gets
+    /// a 431. It is 16 KiB by default
+    pub max_header_size: usize,
+    /// The largest size a request body sent with a `Content-Encoding`
+    /// may reach once decoded, larger ones are rejected with a 413. It
+    /// is 16 MiB by default, `None` removes the limit
+    pub max_decompressed_size: Option<usize>,
     worker_threads: Option<usize>,
+    max_blocking_threads: Option<usize>,
+    thread_stack_size: Option<usize>,
+    thread_name: Option<String>,
 }
 
 /// Shared config trait which allows us to use the config
@@ -205,23 +318,161 @@ impl OctaneConfig {
         OctaneConfig {
             ssl: Ssl::new(),
             keep_alive: Some(Duration::from_secs(5)),
+            header_timeout: Some(Duration::from_secs(10)),
+            shutdown_timeout: Some(Duration::from_secs(30)),
+            hosts: Vec::new(),
+            unix: UnixSocket::new(),
+            entity_tag: EntityTag::Weak,
+            max_body_size: Some(10 * 1024 * 1024),
+            max_uri_len: 8 * 1024,
+            max_header_size: 16 * 1024,
+            max_decompressed_size: Some(16 * 1024 * 1024),
             worker_threads: None,
+            max_blocking_threads: None,
+            thread_stack_size: None,
+            thread_name: None,
         }
     }
     // Appends a settings instance to self
     pub(crate) fn append(&mut self, settings: Self) {
         self.ssl = settings.ssl;
         self.keep_alive = settings.keep_alive;
+        self.header_timeout = settings.header_timeout;
+        self.shutdown_timeout = settings.shutdown_timeout;
+        self.hosts.extend(settings.hosts);
+        self.unix = settings.unix;
+        self.entity_tag = settings.entity_tag;
+        self.max_body_size = settings.max_body_size;
+        self.max_uri_len = settings.max_uri_len;
+        self.max_header_size = settings.max_header_size;
+        self.max_decompressed_size = settings.max_decompressed_size;
+        self.worker_threads = settings.worker_threads.or(self.worker_threads);
+        self.max_blocking_threads = settings.max_blocking_threads.or(self.max_blocking_threads);
+        self.thread_stack_size = settings.thread_stack_size.or(self.thread_stack_size);
+        self.thread_name = settings.thread_name.or_else(|| self.thread_name.take());
+    }
+
+    /// Adds an interface for the server to listen on, call it
+    /// once for every address. An IPv6 host only accepts IPv6
+    /// connections, add an IPv4 host too for dual stack
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use octane::config::OctaneConfig;
+    /// use std::net::{Ipv4Addr, Ipv6Addr};
+    ///
+    /// let mut config = OctaneConfig::new();
+    /// config
+    ///     .host(Ipv4Addr::LOCALHOST.into())
+    ///     .host(Ipv6Addr::LOCALHOST.into());
+    /// ```
+    pub fn host(&mut self, host: IpAddr) -> &mut Self {
+        self.hosts.push(host);
+        self
+    }
+    // The addresses to listen on for a given port
+    pub(crate) fn socket_addrs(&self, port: u16) -> Vec<SocketAddr> {
+        if self.hosts.is_empty() {
+            vec![SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port)]
+        } else {
+            self.hosts
+                .iter()
+                .map(|host| SocketAddr::new(*host, port))
+                .collect()
+        }
     }
 
-    /// Sets the number of worker threads, this is settings
-    /// which will be applied to the `core_threads` method
-    /// on the runtime builder struct. Alias for
-    /// [tokio's core_threads](https://docs.rs/tokio/0.2.13/tokio/runtime/struct.Builder.html#method.core_threads)
+    /// Sets the number of worker threads of the runtime, it is
+    /// the number of cpus by default. The `OCTANE_WORKER_THREADS`
+    /// environment variable is used when it's not set
+    ///
+    /// The thread settings only apply to the runtimes built with
+    /// [`build_runtime`](OctaneConfig::build_runtime), like the one of
+    /// [`Octane::run`](crate::Octane::run). `#[octane::main]` builds
+    /// its runtime before the app and its config exist, only the
+    /// environment variables reach it
     pub fn worker_threads(&mut self, threads: usize) -> &mut Self {
         self.worker_threads = Some(threads);
         self
     }
+    /// Sets the maximum number of threads used for blocking work,
+    /// like reading files, on top of the worker threads. Tokio's
+    /// default is used when neither this nor the
+    /// `OCTANE_MAX_BLOCKING_THREADS` environment variable is set,
+    /// `#[octane::main]` ignores it like
+    /// [`worker_threads`](OctaneConfig::worker_threads)
+    pub fn max_blocking_threads(&mut self, threads: usize) -> &mut Self {
+        self.max_blocking_threads = Some(threads);
+        self
+    }
+    /// Sets the stack size of the runtime threads in bytes, it is 10
+    /// megabytes by default. The `OCTANE_THREAD_STACK_SIZE` environment
+    /// variable is used when it's not set, `#[octane::main]` only
+    /// reads the variable
+    pub fn thread_stack_size(&mut self, size: usize) -> &mut Self {
+        self.thread_stack_size = Some(size);
+        self
+    }
+    /// Sets the name of the runtime threads, it is `octane-main`
+    /// by default. The `OCTANE_THREAD_NAME` environment variable is
+    /// used when it's not set, `#[octane::main]` only reads the
+    /// variable
+    pub fn thread_name(&mut self, name: &str) -> &mut Self {
+        self.thread_name = Some(name.to_owned());
+        self
+    }
+    /// Builds a tokio runtime with the thread settings of the config,
+    /// the settings that are not set are read from the environment
+    /// when the function is called. [`Octane::run`](crate::Octane::run)
+    /// uses it with the config of the app, `#[octane::main]` with an
+    /// empty config as the app doesn't exist yet
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use octane::config::OctaneConfig;
+    ///
+    /// let mut config = OctaneConfig::new();
+    /// let runtime = config.worker_threads(2).build_runtime().unwrap();
+    /// runtime.block_on(async { println!("Hello from the runtime") });
+    /// ```
+    pub fn build_runtime(&self) -> io::Result<Runtime> {
+        let worker_threads = match self.worker_threads {
+            Some(threads) => threads,
+            None => from_env("OCTANE_WORKER_THREADS")?.unwrap_or_else(num_cpus::get),
+        };
+        let max_blocking_threads = match self.max_blocking_threads {
+            Some(threads) => Some(threads),
+            None => from_env("OCTANE_MAX_BLOCKING_THREADS")?,
+        };
+        let thread_stack_size = match self.thread_stack_size {
+            Some(size) => size,
+            None => from_env("OCTANE_THREAD_STACK_SIZE")?.unwrap_or(10 * 1024 * 1024),
+        };
+        let thread_name = match &self.thread_name {
+            Some(name) => name.clone(),
+            None => from_env("OCTANE_THREAD_NAME")?.unwrap_or_else(|| "octane-main".to_owned()),
+        };
+        // tokio panics on an empty thread pool
+        if worker_threads == 0 || max_blocking_threads == Some(0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The runtime needs at least one thread of each kind",
+            ));
+        }
+        let mut builder = Builder::new_multi_thread();
+        builder
+            .enable_all()
+            .worker_threads(worker_threads)
+            .thread_stack_size(thread_stack_size)
+            .thread_name(thread_name);
+        if let Some(threads) = max_blocking_threads {
+            // tokio counts the worker threads in its limit
+            builder.max_threads(worker_threads + threads);
+        }
+        builder.build()
+    }
 
     // Get the certs as a Vec<Certificate>, a user will not have to
     // use this directly, this is used and done for them
@@ -241,8 +492,22 @@ impl OctaneConfig {
     }
 }
 
+// Reads and parses an environment variable, an unset variable is None
+fn from_env<T: FromStr>(name: &str) -> io::Result<Option<T>> {
+    match env::var(name) {
+        Ok(value) => value.parse().map(Some).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid value for {}", name),
+            )
+        }),
+        Err(_) => Ok(None),
+    }
+}
+
 default!(OctaneConfig);
 default!(Ssl);
+default!(UnixSocket);
 
 impl Config for OctaneConfig {
     fn set_keepalive(&mut self, duration: Duration) {
@@ -258,3 +523,22 @@ impl Config for OctaneConfig {
         &mut self.ssl
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn success_build_runtime() {
+        let mut config = OctaneConfig::new();
+        config.worker_threads(1).max_blocking_threads(1);
+        let runtime = config.build_runtime().unwrap();
+        assert_eq!(runtime.block_on(async { 1 + 1 }), 2);
+    }
+
+    #[test]
+    fn fail_build_runtime() {
+        let mut config = OctaneConfig::new();
+        assert!(config.worker_threads(0).build_runtime().is_err());
+    }
+}
@@ -9,8 +9,17 @@ pub const LF: char = '\n';
 pub const CRLF: &str = "\r\n";
 pub const B_CRLF: &[u8] = b"\r\n";
 pub const WEEKS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+pub const LONG_WEEKS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
 pub const MONTHS: [&str; 12] = [
-    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sept", "Oct", "Nov", "Dec",
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
 ];
 
 // constants for time.rs
@@ -19,6 +28,17 @@ pub const DAYS_PER_400Y: i64 = 365 * 400 + 97;
 pub const DAYS_PER_100Y: i64 = 365 * 100 + 24;
 pub const DAYS_PER_4Y: i64 = 365 * 4 + 1;
 pub static DAYS_IN_MONTH: [i64; 12] = [31, 30, 31, 30, 31, 31, 30, 31, 30, 31, 31, 29];
+// headers that must not be sent in the trailer section of a chunked body
+pub const FORBIDDEN_TRAILERS: [&str; 8] = [
+    "transfer-encoding",
+    "content-length",
+    "content-type",
+    "content-encoding",
+    "host",
+    "cookie",
+    "authorization",
+    "trailer",
+];
 pub const NOT_FOUND: &str = r#"<!DOCTYPE html><html><head><title>404 NOT FOUND - OCTANE</title></head><body style="padding: 20px;"><h2 style="text-align: center;">404 NOT FOUND</h2><hr><h5>OCTANE - 0.1</h2></body></html>"#;
 
 lazy_static! {
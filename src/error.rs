@@ -24,9 +24,6 @@ pub struct Error {
 #[derive(Debug, Clone, PartialEq, Eq)]
 /// Custom error type for invalid paths
 pub struct InvalidPathError;
-#[derive(Debug, Clone, PartialEq, Eq)]
-// Custom error type for invalid SSL certificates
-pub struct InvalidCertError;
 
 /// Takes in a http stream and a error code and sends to the client
 #[macro_export]
@@ -45,17 +42,22 @@ impl Error {
     {
         Error { kind: status_code }.send(stream).await
     }
+    // Creates the response that is sent for the given error code
+    pub(crate) fn response<'a>(status_code: StatusCode) -> Response<'a> {
+        let mut res = Response::new_from_slice(NOT_FOUND.as_bytes());
+        res.status(status_code)
+            .default_headers()
+            .set("Content-Type", "text/html");
+        res
+    }
     async fn send<S>(self, stream: S) -> Result<(), Box<dyn error::Error>>
     where
         S: AsyncWrite + Unpin,
     {
-        let mut res = Response::new_from_slice(NOT_FOUND.as_bytes());
-        res.status(self.kind)
-            .default_headers()
-            .set("Content-Type", "text/html");
-
-        let response = res.get_data();
-        Octane::send(response, stream).await?;
+        let mut res = Self::response(self.kind);
+        // the connection is always dropped after an error
+        res.set("Connection", "close");
+        Octane::send(res.get_data(), stream).await?;
         Ok(())
     }
 }
@@ -0,0 +1,585 @@
+use crate::json::FromJSON;
+#[cfg(feature = "url_variables")]
+use crate::query::unescape_hex;
+use crate::request::MatchedRequest;
+use crate::responder::Response;
+use crate::router::Flow;
+use octane_http::StatusCode;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::ops::{Deref, DerefMut};
+use std::str::FromStr;
+
+/// Types which can be extracted from a request, used by the
+/// [`extract`](../macro.extract.html) macro. The `name` is the name
+/// of the closure parameter, which the extractors reading a single
+/// value like [`Path`](struct.Path.html) use as their key. An error
+/// is sent back as the status of the response.
+///
+/// # Example
+///
+/// ```
+/// use octane::extract::FromRequest;
+/// use octane::request::MatchedRequest;
+/// use octane::StatusCode;
+///
+/// struct Token(String);
+///
+/// impl FromRequest for Token {
+///     fn from_request(req: &MatchedRequest, _name: &str) -> Result<Self, StatusCode> {
+///         match req.headers.get("authorization") {
+///             Some(value) if value.starts_with("Bearer ") => Ok(Token(value[7..].to_owned())),
+///             _ => Err(StatusCode::Unauthorized),
+///         }
+///     }
+/// }
+/// ```
+pub trait FromRequest: Sized {
+    fn from_request(req: &MatchedRequest, name: &str) -> Result<Self, StatusCode>;
+}
+
+/// Types which can be parsed from a single value of a path variable,
+/// query field, header or cookie. A missing value only parses for
+/// `Option`, which is `None` then.
+pub trait FromParam: Sized {
+    fn from_param(value: &str) -> Option<Self>;
+
+    /// The value to use when the parameter is missing
+    fn from_missing() -> Option<Self> {
+        None
+    }
+}
+
+macro_rules! from_str_param {
+    ($($t: ty),*) => {
+        $(
+            impl FromParam for $t {
+                fn from_param(value: &str) -> Option<Self> {
+                    value.parse().ok()
+                }
+            }
+        )*
+    };
+}
+
+from_str_param!(
+    i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64, bool, char, String,
+    IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, Uuid
+);
+
+impl<T: FromParam> FromParam for Option<T> {
+    fn from_param(value: &str) -> Option<Self> {
+        T::from_param(value).map(Some)
+    }
+
+    fn from_missing() -> Option<Self> {
+        Some(None)
+    }
+}
+
+/// Types which can be built from the fields of a query string or an url
+/// encoded form. Derive it on structs with named fields, which are parsed
+/// with [`FromParam`](trait.FromParam.html) while unknown fields are
+/// ignored. The fields support the `#[query(rename = "...")]` and
+/// `#[query(skip)]` attributes.
+///
+/// # Example
+///
+/// ```
+/// use octane::extract::FromQuery;
+/// use std::collections::HashMap;
+///
+/// #[derive(FromQuery)]
+/// struct Search {
+///     #[query(rename = "q")]
+///     text: String,
+///     page: Option<u32>,
+/// }
+///
+/// let mut query = HashMap::new();
+/// query.insert("q".to_owned(), "octane".to_owned());
+/// let search = Search::from_query(&query).unwrap();
+/// assert_eq!(search.page, None);
+/// ```
+pub trait FromQuery: Sized {
+    fn from_query(query: &HashMap<String, String>) -> Option<Self>;
+}
+
+pub use octane_macros::FromQuery;
+
+impl FromQuery for HashMap<String, String> {
+    fn from_query(query: &HashMap<String, String>) -> Option<Self> {
+        Some(query.clone())
+    }
+}
+
+macro_rules! extractor {
+    ($(#[$doc: meta])* $name: ident) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub struct $name<T>(pub T);
+
+        impl<T> $name<T> {
+            /// Returns the extracted value
+            pub fn into_inner(self) -> T {
+                self.0
+            }
+        }
+
+        impl<T> Deref for $name<T> {
+            type Target = T;
+
+            fn deref(&self) -> &T {
+                &self.0
+            }
+        }
+
+        impl<T> DerefMut for $name<T> {
+            fn deref_mut(&mut self) -> &mut T {
+                &mut self.0
+            }
+        }
+    };
+}
+
+extractor!(
+    /// Extracts the url variable with the same name as the parameter,
+    /// percent decoded. Requires the `url_variables` feature
+    Path
+);
+extractor!(
+    /// Extracts the query string into a [`FromQuery`](trait.FromQuery.html) type
+    Query
+);
+extractor!(
+    /// Extracts an `application/x-www-form-urlencoded` body into a
+    /// [`FromQuery`](trait.FromQuery.html) type
+    Form
+);
+extractor!(
+    /// Extracts a json body with the [`FromJSON`](../json/trait.FromJSON.html) trait
+    Json
+);
+extractor!(
+    /// Extracts the header named like the parameter, with underscores
+    /// read as dashes so `user_agent` is the `User-Agent` header
+    Header
+);
+extractor!(
+    /// Extracts the value of the cookie with the same name as the
+    /// parameter. Requires the `cookies` feature
+    Cookie
+);
+
+fn parse_param<T: FromParam>(value: Option<&str>) -> Result<T, StatusCode> {
+    match value {
+        Some(value) => T::from_param(value),
+        None => T::from_missing(),
+    }
+    .ok_or(StatusCode::BadRequest)
+}
+
+#[cfg(feature = "url_variables")]
+impl<T: FromParam> FromRequest for Path<T> {
+    fn from_request(req: &MatchedRequest, name: &str) -> Result<Self, StatusCode> {
+        // the route doesn't have the variable, which is a mistake of the server
+        let value = req.vars.get(name).ok_or(StatusCode::InternalServerError)?;
+        T::from_param(&unescape_hex(value))
+            .map(Path)
+            .ok_or(StatusCode::BadRequest)
+    }
+}
+
+impl<T: FromQuery> FromRequest for Query<T> {
+    fn from_request(req: &MatchedRequest, _name: &str) -> Result<Self, StatusCode> {
+        T::from_query(&req.get_query())
+            .map(Query)
+            .ok_or(StatusCode::BadRequest)
+    }
+}
+
+impl<T: FromQuery> FromRequest for Form<T> {
+    fn from_request(req: &MatchedRequest, _name: &str) -> Result<Self, StatusCode> {
+        T::from_query(&req.get_form()?)
+            .map(Form)
+            .ok_or(StatusCode::BadRequest)
+    }
+}
+
+impl<T: FromJSON> FromRequest for Json<T> {
+    fn from_request(req: &MatchedRequest, _name: &str) -> Result<Self, StatusCode> {
+        req.parse_json().map(Json)
+    }
+}
+
+impl<T: FromParam> FromRequest for Header<T> {
+    fn from_request(req: &MatchedRequest, name: &str) -> Result<Self, StatusCode> {
+        let value = req.headers.get(&name.replace('_', "-"));
+        parse_param(value.map(String::as_str)).map(Header)
+    }
+}
+
+#[cfg(feature = "cookies")]
+impl<T: FromParam> FromRequest for Cookie<T> {
+    fn from_request(req: &MatchedRequest, name: &str) -> Result<Self, StatusCode> {
+        let value = req.cookies.iter().find(|c| c.name() == name);
+        parse_param(value.map(|c| c.value())).map(Cookie)
+    }
+}
+
+// Sends the status of a failed extraction, used by the extract macros
+#[doc(hidden)]
+pub fn reject(res: &mut Response, status: StatusCode) -> Flow {
+    res.status(status).send(status.to_string());
+    Flow::Stop
+}
+
+/// An UUID in the hyphenated `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx` form,
+/// which is also how it's displayed. Parsing is case insensitive and
+/// accepts the 32 digits without hyphens too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Uuid([u8; 16]);
+
+impl Uuid {
+    pub fn from_bytes(bytes: [u8; 16]) -> Self {
+        Uuid(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+}
+
+/// The error returned when parsing an invalid UUID
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidUuid;
+
+impl fmt::Display for InvalidUuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid uuid")
+    }
+}
+
+impl std::error::Error for InvalidUuid {}
+
+impl FromStr for Uuid {
+    type Err = InvalidUuid;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let digits: Vec<u8> = match value.len() {
+            32 => value.bytes().collect(),
+            36 => {
+                let bytes = value.as_bytes();
+                if [8, 13, 18, 23].iter().any(|&i| bytes[i] != b'-') {
+                    return Err(InvalidUuid);
+                }
+                bytes.iter().copied().filter(|&b| b != b'-').collect()
+            }
+            _ => return Err(InvalidUuid),
+        };
+        if digits.len() != 32 {
+            return Err(InvalidUuid);
+        }
+        let mut ret = [0; 16];
+        for (byte, pair) in ret.iter_mut().zip(digits.chunks(2)) {
+            let hex = |c: u8| (c as char).to_digit(16).ok_or(InvalidUuid);
+            *byte = (hex(pair[0])? * 16 + hex(pair[1])?) as u8;
+        }
+        Ok(Uuid(ret))
+    }
+}
+
+impl fmt::Display for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if i == 4 || i == 6 || i == 8 || i == 10 {
+                f.write_str("-")?;
+            }
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds a route closure like [`route!()`](macro.route.html), with
+/// extra typed parameters after `req` and `res`. Each parameter is
+/// extracted with its [`FromRequest`](extract/trait.FromRequest.html)
+/// implementation before the body runs, and if one fails the status
+/// it gives is sent, usually a 400, and the body doesn't run. Url
+/// variables are extracted with [`Path`](extract/struct.Path.html)
+/// when the `url_variables` feature is enabled.
+///
+/// # Example
+///
+/// ```
+/// use octane::extract::{FromQuery, Header, Json, Query};
+/// use octane::prelude::*;
+///
+/// #[derive(FromQuery)]
+/// struct Page {
+///     offset: Option<usize>,
+/// }
+///
+/// #[derive(FromJSON)]
+/// struct Comment {
+///     text: String,
+/// }
+///
+/// let mut app = Octane::new();
+/// app.get(
+///     "/users",
+///     extract!(|req, res, page: Query<Page>| {
+///         let offset = page.offset.unwrap_or(0);
+///         res.send(format!("Users from {}", offset));
+///         Flow::Stop
+///     }),
+/// );
+/// app.post(
+///     "/comments",
+///     extract!(|req, res, comment: Json<Comment>, user_agent: Header<Option<String>>| {
+///         res.send(format!("{} bytes", comment.text.len()));
+///         Flow::Stop
+///     }),
+/// );
+/// ```
+#[macro_export]
+macro_rules! extract {
+    ( | $req : ident, $res : ident $(, $name : ident : $kind : ty)* $(,)? | $body : expr ) => {{
+        $crate::route!(|$req, $res| {
+            $(
+                let $name: $kind =
+                    match <$kind as $crate::extract::FromRequest>::from_request($req, stringify!($name)) {
+                        Ok(value) => value,
+                        Err(status) => return $crate::extract::reject($res, status),
+                    };
+            )*
+            $body
+        })
+    }};
+}
+
+/// Just like the [`extract!()`](macro.extract.html) macro but the body
+/// is an async block, like with [`async_route!()`](macro.async_route.html).
+/// Captured values that aren't `Copy` are listed after `move` the same way
+///
+/// # Example
+///
+/// ```
+/// use octane::extract::Header;
+/// use octane::prelude::*;
+///
+/// async fn fetch_name(id: u64) -> String {
+///     format!("user {}", id)
+/// }
+///
+/// let mut app = Octane::new();
+/// app.get(
+///     "/me",
+///     async_extract!(|req, res, x_user_id: Header<u64>| {
+///         res.send(fetch_name(*x_user_id).await);
+///         Flow::Stop
+///     }),
+/// );
+/// ```
+#[macro_export]
+macro_rules! async_extract {
+    (
+        $(move [ $($cap : ident),* $(,)? ])?
+        | $req : ident, $res : ident $(, $name : ident : $kind : ty)* $(,)? | $body : expr
+    ) => {{
+        $crate::async_route!(move [$($($cap),*)?] |$req, $res| {
+            $(
+                let $name: $kind =
+                    match <$kind as $crate::extract::FromRequest>::from_request($req, stringify!($name)) {
+                        Ok(value) => value,
+                        Err(status) => return $crate::extract::reject($res, status),
+                    };
+            )*
+            $body
+        })
+    }};
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::request::{Headers, Request, RequestLine};
+    use crate::router::Closure;
+    use std::sync::Arc;
+
+    #[derive(Debug, PartialEq, FromQuery)]
+    struct Search {
+        #[query(rename = "q")]
+        text: String,
+        page: Option<u32>,
+        strict: bool,
+        #[query(skip)]
+        cached: Vec<u8>,
+    }
+
+    fn request<'a>(request_line: &str, headers: &'a Headers, body: &'a [u8]) -> Request<'a> {
+        let request_line = RequestLine::parse(request_line).unwrap();
+        Request::parse(request_line, headers, body).unwrap()
+    }
+
+    fn headers(headers: &str) -> Headers {
+        Headers::parse(headers.to_owned()).unwrap()
+    }
+
+    #[test]
+    fn success_from_param() {
+        assert_eq!(u8::from_param("12"), Some(12));
+        assert_eq!(u8::from_param("-1"), None);
+        assert_eq!(<Option<u8>>::from_param("1"), Some(Some(1)));
+        assert_eq!(<Option<u8>>::from_missing(), Some(None));
+        assert_eq!(String::from_missing(), None);
+        let uuid = Uuid::from_param("67E55044-10b1-426f-9247-bb680e5fe0c8").unwrap();
+        assert_eq!(uuid.to_string(), "67e55044-10b1-426f-9247-bb680e5fe0c8");
+        assert_eq!(
+            Uuid::from_param("67e5504410b1426f9247bb680e5fe0c8"),
+            Some(uuid)
+        );
+        assert_eq!(uuid.as_bytes()[..2], [0x67, 0xe5]);
+        assert!(Uuid::from_param("67e55044-10b1-426f-9247-bb680e5fe0cg").is_none());
+        assert!(Uuid::from_param("67e55044-10b1-426f-9247_bb680e5fe0c8").is_none());
+        assert!(Uuid::from_param("67e55044").is_none());
+    }
+
+    #[test]
+    fn success_from_request() {
+        let headers =
+            headers("Content-Type: application/json\r\nX-Request-Id: 7\r\nCookie: session=abc");
+        let req = MatchedRequest {
+            request: request(
+                "POST /a?q=rust%20web&strict=true&other=1 HTTP/1.1",
+                &headers,
+                b"{\"a\": [1]}",
+            ),
+            #[cfg(feature = "url_variables")]
+            vars: vec![("id", "a%20b")].into_iter().collect(),
+        };
+        let Query(search) = Query::<Search>::from_request(&req, "search").unwrap();
+        assert_eq!(
+            search,
+            Search {
+                text: "rust web".to_owned(),
+                page: None,
+                strict: true,
+                cached: vec![],
+            }
+        );
+        let json = Json::<HashMap<String, Vec<u8>>>::from_request(&req, "body").unwrap();
+        assert_eq!(json["a"], vec![1]);
+        assert_eq!(
+            *Header::<u32>::from_request(&req, "x_request_id").unwrap(),
+            7
+        );
+        assert_eq!(
+            *Header::<Option<u32>>::from_request(&req, "x_missing").unwrap(),
+            None
+        );
+        #[cfg(feature = "url_variables")]
+        assert_eq!(
+            Path::<String>::from_request(&req, "id")
+                .unwrap()
+                .into_inner(),
+            "a b"
+        );
+        #[cfg(feature = "cookies")]
+        assert_eq!(
+            *Cookie::<String>::from_request(&req, "session").unwrap(),
+            "abc"
+        );
+    }
+
+    #[test]
+    fn fail_from_request() {
+        let headers = headers("X-Request-Id: a");
+        let req = MatchedRequest {
+            request: request("GET /a?q=x&page=first HTTP/1.1", &headers, b""),
+            #[cfg(feature = "url_variables")]
+            vars: vec![("id", "12a")].into_iter().collect(),
+        };
+        // page isn't a number and strict is missing
+        assert_eq!(
+            Query::<Search>::from_request(&req, "search"),
+            Err(StatusCode::BadRequest)
+        );
+        assert_eq!(
+            Json::<Vec<u8>>::from_request(&req, "body"),
+            Err(StatusCode::UnsupportedMediaType)
+        );
+        assert_eq!(
+            Form::<HashMap<String, String>>::from_request(&req, "form"),
+            Err(StatusCode::UnsupportedMediaType)
+        );
+        assert_eq!(
+            Header::<u32>::from_request(&req, "x_request_id"),
+            Err(StatusCode::BadRequest)
+        );
+        assert_eq!(
+            Header::<u32>::from_request(&req, "x_missing"),
+            Err(StatusCode::BadRequest)
+        );
+        #[cfg(feature = "url_variables")]
+        {
+            assert_eq!(
+                Path::<u32>::from_request(&req, "id"),
+                Err(StatusCode::BadRequest)
+            );
+            assert_eq!(
+                Path::<u32>::from_request(&req, "other"),
+                Err(StatusCode::InternalServerError)
+            );
+        }
+    }
+
+    #[crate::test]
+    async fn success_extract() {
+        let closure: Closure = extract!(|req, res, search: Query<Search>| {
+            res.send(search.text.clone());
+            Flow::Stop
+        });
+        let headers = headers("Host: a");
+        let req = MatchedRequest {
+            request: request("GET /?q=hello&strict=false HTTP/1.1", &headers, b""),
+            #[cfg(feature = "url_variables")]
+            vars: HashMap::new(),
+        };
+        let mut res = Response::new_empty();
+        closure(&req, &mut res).await;
+        assert_eq!(res.status_code, StatusCode::Ok);
+        assert_eq!(res.content_len, Some(5));
+
+        let req = MatchedRequest {
+            request: request("GET /?q=hello HTTP/1.1", &headers, b""),
+            #[cfg(feature = "url_variables")]
+            vars: HashMap::new(),
+        };
+        let mut res = Response::new_empty();
+        closure(&req, &mut res).await;
+        assert_eq!(res.status_code, StatusCode::BadRequest);
+    }
+
+    #[crate::test]
+    async fn success_async_extract() {
+        let prefix = Arc::new(String::from("hello "));
+        let closure: Closure = async_extract!(move [prefix] |req, res, search: Query<Search>| {
+            res.send(format!("{}{}", prefix, search.text));
+            Flow::Stop
+        });
+        let headers = headers("Host: a");
+        for _ in 0..2 {
+            let req = MatchedRequest {
+                request: request("GET /?q=you&strict=true HTTP/1.1", &headers, b""),
+                #[cfg(feature = "url_variables")]
+                vars: HashMap::new(),
+            };
+            let mut res = Response::new_empty();
+            closure(&req, &mut res).await;
+            assert_eq!(res.status_code, StatusCode::Ok);
+            assert_eq!(res.content_len, Some(9));
+        }
+    }
+}
@@ -1,19 +1,32 @@
+use crate::config::EntityTag;
+use crate::time::HttpDate;
 use crate::util::AsyncReader;
+use std::collections::HashMap;
 use std::error::Error;
 use std::ffi::OsStr;
 use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::fs::{File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom};
 use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+lazy_static! {
+    // The strong tags hashed already, by path, along with the size
+    // and the modification time of the file they were hashed from
+    static ref STRONG_TAGS: Mutex<HashMap<PathBuf, (u64, Option<SystemTime>, String)>> =
+        Mutex::new(HashMap::new());
+}
 
 /// The FileHandler structure is a helper struct
 /// to manage files, contents and extensions also
 /// to decide their mime types accordingly
 pub struct FileHandler {
-    pub file_name: String,
     pub file: AsyncReader<File>,
     pub extension: String,
     pub meta: Metadata,
+    path: PathBuf,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -40,10 +53,10 @@ impl FileHandler {
                     .and_then(OsStr::to_str)
                     .unwrap_or("");
                 Ok(FileHandler {
-                    file_name: path.file_name().and_then(OsStr::to_str).unwrap().to_owned(),
                     file: AsyncReader::new(file),
                     extension: extension.to_owned(),
                     meta,
+                    path: path.clone(),
                 })
             } else {
                 Err(Box::new(FileHandlerError::new(1)))
@@ -52,6 +65,65 @@ impl FileHandler {
             Err(Box::new(FileHandlerError::new(0)))
         }
     }
+    /// Returns the `Last-Modified` value of the file, None
+    /// if the platform doesn't record modification times
+    pub fn last_modified(&self) -> Option<String> {
+        let modified = self.meta.modified().ok()?;
+        Some(HttpDate::from(modified).to_string())
+    }
+    /// Returns the quoted `ETag` value of the file, a strong tag
+    /// reads the whole file and rewinds it afterwards, unless the
+    /// same version of the file was hashed before
+    pub fn entity_tag(&mut self, kind: EntityTag) -> io::Result<Option<String>> {
+        match kind {
+            EntityTag::None => Ok(None),
+            EntityTag::Weak => {
+                let modified = self
+                    .meta
+                    .modified()
+                    .ok()
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |time| time.as_nanos());
+                Ok(Some(format!("W/\"{:x}-{:x}\"", self.meta.len(), modified)))
+            }
+            EntityTag::Strong => {
+                if let Some(tag) = self.cached_tag() {
+                    return Ok(Some(tag));
+                }
+                let file = &mut self.file.reader;
+                // FNV-1a, the tag has to stay the same across restarts
+                let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+                let mut buf = [0; 8192];
+                loop {
+                    let read = file.read(&mut buf)?;
+                    if read == 0 {
+                        break;
+                    }
+                    for byte in &buf[..read] {
+                        hash = (hash ^ u64::from(*byte)).wrapping_mul(0x100_0000_01b3);
+                    }
+                }
+                file.seek(SeekFrom::Start(0))?;
+                let tag = format!("\"{:x}-{:016x}\"", self.meta.len(), hash);
+                if let Ok(mut tags) = STRONG_TAGS.lock() {
+                    let version = (self.meta.len(), self.meta.modified().ok(), tag.clone());
+                    tags.insert(self.path.clone(), version);
+                }
+                Ok(Some(tag))
+            }
+        }
+    }
+    /// Returns the strong `ETag` of the file if this version of
+    /// it was hashed already, it never reads the file
+    pub fn cached_tag(&self) -> Option<String> {
+        let tags = STRONG_TAGS.lock().ok()?;
+        let (len, modified, tag) = tags.get(&self.path)?;
+        if *len == self.meta.len() && *modified == self.meta.modified().ok() {
+            Some(tag.clone())
+        } else {
+            None
+        }
+    }
     /// A helper method to get extension from a
     /// PathBuf
     pub fn get_extension(path: &PathBuf) -> String {
@@ -63,6 +135,38 @@ impl FileHandler {
                 .to_string(),
         )
     }
+    /// Returns true for the mime types `mime_type` gives to
+    /// formats that are compressed already, compressing them
+    /// again only costs time
+    #[cfg(feature = "compression")]
+    pub fn is_compressed(mime: &str) -> bool {
+        let mime = mime.split(';').next().unwrap_or("").trim();
+        match mime.split('/').next().unwrap_or("") {
+            "audio" | "video" => true,
+            "image" => mime != "image/svg+xml" && mime != "image/bmp",
+            "font" => mime == "font/woff" || mime == "font/woff2",
+            _ => matches!(
+                mime,
+                "application/x-freearc"
+                    | "application/x-bzip"
+                    | "application/x-bzip2"
+                    | "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+                    | "application/epub+zip"
+                    | "application/gzip"
+                    | "application/java-archive"
+                    | "application/vnd.oasis.opendocument.presentation"
+                    | "application/vnd.oasis.opendocument.spreadsheet"
+                    | "application/vnd.oasis.opendocument.text"
+                    | "application/ogg"
+                    | "application/pdf"
+                    | "application/vnd.openxmlformats-officedocument.presentationml.presentation"
+                    | "application/vnd.rar"
+                    | "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+                    | "application/zip"
+                    | "application/x-7z-compressed"
+            ),
+        }
+    }
     /// Perform a match on the extension and
     /// return the mime type accordingly
     pub fn mime_type(extension: String) -> String {
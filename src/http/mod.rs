@@ -18,14 +18,22 @@ pub struct Http<'a> {
     pub request: &'a Request<'a>,
     pub err_code: Option<StatusCode>,
     pub keep_alive: KeepAliveState,
+    pub max_requests: Option<u64>,
 }
 
 impl<'a> Http<'a> {
     pub fn validate(request: &'a Request<'a>) -> Self {
+        // http 1.1 connections are persistent unless told otherwise,
+        // older versions have to opt in with a connection header
+        let keep_alive = match request.request_line.version {
+            HttpVersion::Http11 => KeepAliveState::UserDefined,
+            _ => KeepAliveState::Close,
+        };
         let mut handler = Self {
             request,
             err_code: None,
-            keep_alive: KeepAliveState::Close,
+            keep_alive,
+            max_requests: None,
         };
         match request.request_line.version {
             HttpVersion::Http11 => http11_check(&mut handler),
@@ -47,4 +55,84 @@ impl<'a> Http<'a> {
     pub fn keep_alive(&self) -> bool {
         !(self.keep_alive == KeepAliveState::Close)
     }
+    // Returns the idle timeout to use for the connection, the
+    // timeout requested by the client can only shorten the one
+    // configured on the server
+    pub fn timeout(&self, configured: Option<Duration>) -> Option<Duration> {
+        match self.keep_alive {
+            KeepAliveState::Close => None,
+            KeepAliveState::UserDefined => configured,
+            KeepAliveState::Particular(duration) => configured.map(|c| c.min(duration)),
+        }
+    }
+    // Checks if the connection header contains the given token,
+    // the header can be a comma separated list like `keep-alive, Upgrade`
+    pub fn connection_has(&self, token: &str) -> bool {
+        self.request
+            .headers
+            .get("connection")
+            .map(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::request::{Headers, RequestLine};
+
+    fn validate_with<T>(request_line: &str, headers: &str, check: T)
+    where
+        T: FnOnce(Http),
+    {
+        let headers = Headers::parse(headers.to_owned()).unwrap();
+        let request_line = RequestLine::parse(request_line).unwrap();
+        let request = Request::parse(request_line, &headers, &[]).unwrap();
+        check(Http::validate(&request));
+    }
+
+    #[test]
+    fn success_http11_persistent() {
+        // http 1.1 connections should stay open by default
+        validate_with("GET / HTTP/1.1", "Host: localhost", |checker| {
+            assert_eq!(checker.keep_alive, KeepAliveState::UserDefined);
+            assert_eq!(
+                checker.timeout(Some(Duration::from_secs(5))),
+                Some(Duration::from_secs(5))
+            );
+        });
+        validate_with(
+            "GET / HTTP/1.1",
+            "Host: localhost\r\nConnection: Close",
+            |checker| {
+                assert!(!checker.keep_alive());
+                assert_eq!(checker.timeout(Some(Duration::from_secs(5))), None);
+            },
+        );
+    }
+
+    #[test]
+    fn success_http10_keepalive() {
+        // http 1.0 connections should only stay open when asked to
+        validate_with("GET / HTTP/1.0", "Host: localhost", |checker| {
+            assert_eq!(checker.keep_alive, KeepAliveState::Close);
+        });
+        validate_with(
+            "GET / HTTP/1.0",
+            "Connection: keep-alive\r\nKeep-Alive: timeout=2, max=10",
+            |checker| {
+                assert_eq!(
+                    checker.keep_alive,
+                    KeepAliveState::Particular(Duration::from_secs(2))
+                );
+                assert_eq!(checker.max_requests, Some(10));
+                // the client can't ask for more than what is configured
+                assert_eq!(
+                    checker.timeout(Some(Duration::from_secs(1))),
+                    Some(Duration::from_secs(1))
+                );
+                assert_eq!(checker.timeout(None), None);
+            },
+        );
+    }
 }
@@ -1,6 +1,6 @@
 #![allow(clippy::all)]
 #![allow(clippy::type_complexity)]
-#![forbid(unsafe_code)]
+#![deny(unsafe_code)]
 //! Octane is a web server that's modelled after express (a very
 //! popular and easy to use web framework) for rust.
 //!
@@ -53,6 +53,9 @@
 //! - `cookies`: To enable basic cookie parsing and value handling.
 //! - `url_variables`: To support variables in url.
 //! - `raw_headers`: To have access to original, un-normalized headers.
+//! - `compression`: To compress responses with gzip, deflate or brotli
+//! when the client accepts it, and to decode gzip and deflate request bodies.
+//! Compressed responses get weak `ETag`s, so it's left out of the defaults.
 //! - `rustls`: To use rustls for ssl.
 //! - `openSSL`: To use openssl for ssl.
 //! - `default`: The default set includes faithful, query_strings, cookies,
@@ -66,6 +69,9 @@
 //! to enable the feature.
 #[macro_use]
 extern crate lazy_static;
+// lets the json derives, which generate `::octane::json` paths, work inside the crate
+extern crate self as octane;
+pub(crate) mod compression;
 /// Configurations for Octane web server
 pub mod config;
 pub(crate) mod constants;
@@ -73,11 +79,21 @@ pub(crate) mod constants;
 /// Module for cookie parsing and handling
 pub mod cookie;
 pub(crate) mod error;
+/// Typed extractors, which parse the parameters of the closures built
+/// with the [`extract`](macro.extract.html) macro from the request
+pub mod extract;
 pub(crate) mod file_handler;
 pub(crate) mod http;
+/// Dependency free json values, parsing and serialisation with the
+/// [`ToJSON`](json/trait.ToJSON.html) and [`FromJSON`](json/trait.FromJSON.html) traits
+pub use octane_json as json;
 pub(crate) mod middlewares;
+/// Parsing of `multipart/form-data` bodies, with fields and files
+pub mod multipart;
 pub(crate) mod path;
-pub(crate) mod query;
+/// Parsing of url encoded forms, with the limits of the parser
+pub mod query;
+pub(crate) mod range;
 /// Request module contains the ongoing request and methods to read from it
 pub mod request;
 /// Responder module contains the response which will be sent
@@ -85,9 +101,14 @@ pub mod responder;
 /// The router module has utilities to create routes and custom routers
 pub mod router;
 pub(crate) mod server;
+/// Middleware serving the files of a directory
+pub use crate::middlewares::static_dir::StaticDir;
 /// Server struct that manages request/response and allows the routes to enter in
 pub use crate::server::Octane;
+/// Date type of the http headers, with parsing and formatting
+pub use crate::time::HttpDate;
 pub(crate) mod server_builder;
+pub(crate) mod shutdown;
 pub(crate) mod time;
 pub(crate) mod tls;
 pub(crate) mod util;
@@ -101,18 +122,19 @@ pub use octane_macros::test;
 /// Prelude here brings in scope, the [`Route`](router/trait.Route.html) and
 /// [`Config`](config/trait.Config.html) trait, [`Octane`](struct.Octane.html) main server
 /// and [`Router`](router/struct.Router.html) struct with the [`Flow`](router/enum.Flow.html)
-/// and the [`Value`](../octane_json/enum.Value.html) enum, the [`route`](macro.route.html),
-/// [`route_next`](macro.route_next.html), [`path`](macro.path.html), [`route_stop`](macro.route_stop.html)
-/// macros with the [`ToJSON`](../octane_json/convert/trait.ToJSON.html)
-/// /[`FromJSON`](../octane_json/convert/trait.FromJSON.html) derive macros
+/// and the [`Value`](json/enum.Value.html) enum, the [`route`](macro.route.html),
+/// [`route_next`](macro.route_next.html), [`path`](macro.path.html), [`route_stop`](macro.route_stop.html),
+/// [`async_route`](macro.async_route.html) macros with the [`ToJSON`](json/trait.ToJSON.html)
+/// /[`FromJSON`](json/trait.FromJSON.html) traits and their derive macros
 pub mod prelude {
     pub use crate::config::Config;
+    pub use crate::json::{FromJSON, ToJSON, Value};
     pub use crate::Octane;
     pub use crate::{
-        route, route_next, route_stop,
+        async_extract, async_route, extract, route, route_next, route_stop,
         router::{Flow, Route, Router},
     };
-    pub use octane_macros::path;
+    pub use octane_macros::{path, FromJSON, ToJSON};
 }
 
 #[cfg(all(feature = "openSSL", feature = "rustls"))]
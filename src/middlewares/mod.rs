@@ -1,3 +1,5 @@
+pub mod static_dir;
+
 use crate::router::Closure;
 
 pub struct Closures {
@@ -0,0 +1,440 @@
+use crate::compression::Encoding;
+use crate::config::EntityTag;
+use crate::file_handler::FileHandler;
+use crate::path::PathBuf;
+use crate::query::unescape_hex;
+use crate::request::{MatchedRequest, RequestMethod};
+use crate::responder::Response;
+use crate::router::{Closure, Flow};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf as StdPathBuf};
+use std::sync::Arc;
+use tokio::task;
+
+// The siblings looked for next to a file, in the order
+// of preference when the client weighs them equally
+const PRECOMPRESSED: [(Encoding, &str); 2] = [(Encoding::Brotli, "br"), (Encoding::Gzip, "gz")];
+
+// What a request maps to in the directory
+enum Target {
+    File {
+        file: FileHandler,
+        tag: Option<String>,
+        // the coding of the precompressed sibling which is sent
+        // instead of the file, with the content type of the file
+        encoding: Option<(Encoding, String)>,
+        // the file has precompressed siblings
+        vary: bool,
+    },
+    Listing(String),
+}
+
+/// StaticDir serves the files of a directory under a url
+/// prefix. Requests which don't map to anything in the directory
+/// fall through to the next closure with `Flow::Next`, so it can
+/// be added before or after the other routes
+///
+/// Paths that would leave the directory and hidden files, the
+/// ones starting with a dot, are never served
+///
+/// When a file has a `.br` or `.gz` sibling, like `app.js.br` next
+/// to `app.js`, the sibling is sent instead to clients accepting
+/// that coding, so nothing is compressed at request time
+///
+/// # Example
+///
+/// ```no_run
+/// use octane::prelude::*;
+/// use octane::StaticDir;
+///
+/// let mut app = Octane::new();
+/// let mut assets = StaticDir::new("public");
+/// assets.prefix("/assets").listing(true);
+/// app.add(assets.handler());
+/// ```
+#[derive(Clone, Debug)]
+pub struct StaticDir {
+    root: StdPathBuf,
+    prefix: PathBuf,
+    index: Option<String>,
+    listing: bool,
+    precompressed: bool,
+}
+
+impl StaticDir {
+    /// Returns a StaticDir which serves `root` at `/`, with
+    /// `index.html` as the index file, without listings and
+    /// with precompressed siblings
+    pub fn new(root: &str) -> Self {
+        StaticDir {
+            root: StdPathBuf::from(root),
+            prefix: PathBuf::new(),
+            index: Some("index.html".to_owned()),
+            listing: false,
+            precompressed: true,
+        }
+    }
+    /// Sets the url prefix the directory is served under
+    ///
+    /// # Panics
+    ///
+    /// If the prefix goes above the root with `..`
+    pub fn prefix(&mut self, prefix: &str) -> &mut Self {
+        self.prefix = PathBuf::parse(prefix).expect("Invalid static directory prefix");
+        self
+    }
+    /// Sets the file which is served for a directory,
+    /// `None` disables it
+    pub fn index(&mut self, index: Option<&str>) -> &mut Self {
+        self.index = index.map(str::to_owned);
+        self
+    }
+    /// Renders a listing of the directory contents when
+    /// a directory has no index file
+    pub fn listing(&mut self, listing: bool) -> &mut Self {
+        self.listing = listing;
+        self
+    }
+    /// Serves the `.br` and `.gz` siblings of the files
+    /// to the clients accepting them
+    pub fn precompressed(&mut self, precompressed: bool) -> &mut Self {
+        self.precompressed = precompressed;
+        self
+    }
+    /// Returns the closure serving the directory, which can
+    /// be passed to `app.add()`
+    pub fn handler(&self) -> Closure {
+        let dir = Arc::new(self.clone());
+        Box::new(move |req, res| Box::pin(dir.clone().serve(req, res)))
+    }
+
+    async fn serve(self: Arc<Self>, req: &MatchedRequest<'_>, res: &mut Response<'_>) -> Flow {
+        let request_line = &req.request_line;
+        if request_line.method != RequestMethod::Get && request_line.method != RequestMethod::Head {
+            return Flow::Next;
+        }
+        let relative = match self.relative_path(&request_line.path) {
+            Some(relative) => relative,
+            None => return Flow::Next,
+        };
+        let accept = req.headers.get("accept-encoding").cloned();
+        let entity_tag = res.entity_tag;
+        // the filesystem calls block, they don't run on the workers
+        let target =
+            task::spawn_blocking(move || self.find(relative, accept.as_deref(), entity_tag)).await;
+        match target {
+            Ok(Some(Target::File {
+                file,
+                tag,
+                encoding,
+                vary,
+            })) => {
+                res.send_opened_file(file, tag);
+                // the file varies even when it's sent as it is
+                if vary {
+                    res.set("Vary", "Accept-Encoding");
+                }
+                if let Some((encoding, content_type)) = encoding {
+                    res.set("Content-Type", &content_type)
+                        .set("Content-Encoding", encoding.name());
+                }
+                Flow::Stop
+            }
+            Ok(Some(Target::Listing(listing))) => {
+                res.send(listing);
+                Flow::Stop
+            }
+            _ => Flow::Next,
+        }
+    }
+
+    // Looks up what the relative path maps to in the directory
+    fn find(
+        &self,
+        relative: PathBuf,
+        accept: Option<&str>,
+        entity_tag: EntityTag,
+    ) -> Option<Target> {
+        let path = self.root.join(StdPathBuf::from(relative.clone()));
+        let meta = fs::metadata(&path).ok()?;
+        if !meta.is_dir() {
+            return self.open(&path, accept, entity_tag);
+        }
+        if let Some(index) = &self.index {
+            let index_path = path.join(index);
+            if index_path.is_file() {
+                return self.open(&index_path, accept, entity_tag);
+            }
+        }
+        if self.listing {
+            return self
+                .render_listing(&path, &relative)
+                .ok()
+                .map(Target::Listing);
+        }
+        None
+    }
+
+    // Opens the file, or the precompressed sibling of it the client prefers
+    fn open(&self, path: &Path, accept: Option<&str>, entity_tag: EntityTag) -> Option<Target> {
+        let open = |path: &Path| {
+            let mut file = FileHandler::handle_file(&path.to_path_buf()).ok()?;
+            let tag = file.entity_tag(entity_tag).ok()?;
+            Some((file, tag))
+        };
+        let (sibling, vary) = if self.precompressed {
+            Self::find_precompressed(path, accept)
+        } else {
+            (None, false)
+        };
+        if let Some((encoding, sibling)) = sibling {
+            if let Some((file, tag)) = open(&sibling) {
+                let content_type = FileHandler::get_extension(&path.to_path_buf());
+                return Some(Target::File {
+                    file,
+                    tag,
+                    encoding: Some((encoding, content_type)),
+                    vary,
+                });
+            }
+        }
+        let (file, tag) = open(path)?;
+        Some(Target::File {
+            file,
+            tag,
+            encoding: None,
+            vary,
+        })
+    }
+
+    // Returns the precompressed sibling the client prefers, None if the
+    // client wants the file as it is, and whether the file has siblings
+    fn find_precompressed(
+        path: &Path,
+        accept: Option<&str>,
+    ) -> (Option<(Encoding, StdPathBuf)>, bool) {
+        let mut offered = Vec::new();
+        for (encoding, extension) in PRECOMPRESSED.iter() {
+            let mut sibling = path.as_os_str().to_owned();
+            sibling.push(".");
+            sibling.push(extension);
+            let sibling = StdPathBuf::from(sibling);
+            if sibling.is_file() {
+                offered.push((*encoding, sibling));
+            }
+        }
+        if offered.is_empty() {
+            return (None, false);
+        }
+        let encodings: Vec<Encoding> = offered.iter().map(|(encoding, _)| *encoding).collect();
+        let chosen = accept.and_then(|accept| Encoding::negotiate(accept, &encodings));
+        let sibling = offered
+            .into_iter()
+            .find(|(encoding, _)| Some(*encoding) == chosen);
+        (sibling, true)
+    }
+
+    // Maps the request path to a path relative to the root, None if it's
+    // not under the prefix or would leave the directory
+    fn relative_path(&self, path: &PathBuf) -> Option<PathBuf> {
+        // the path is parsed again once decoded, escaped dots and
+        // slashes can't sneak past the traversal check that way
+        let relative = PathBuf::parse(&unescape_hex(&path.to_string()))
+            .ok()?
+            .subtract(&self.prefix)?;
+        let forbidden =
+            |chunk: &String| chunk.starts_with('.') || chunk.contains('\\') || chunk.contains('\0');
+        if relative.iter().any(forbidden) {
+            return None;
+        }
+        Some(relative)
+    }
+
+    fn render_listing(&self, dir: &Path, relative: &PathBuf) -> io::Result<String> {
+        let base = self.prefix.concat(relative);
+        let mut href = String::from("/");
+        let mut parent = String::new();
+        for chunk in base.iter() {
+            parent = href.clone();
+            href.push_str(&escape_url(chunk));
+            href.push('/');
+        }
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = match entry.file_name().into_string() {
+                Ok(name) if !name.starts_with('.') => name,
+                _ => continue,
+            };
+            entries.push((name, entry.file_type()?.is_dir()));
+        }
+        entries.sort();
+        let title = escape_html(&format!("/{}", base));
+        let mut html = format!(
+            "<!DOCTYPE html><html><head><title>Index of {0}</title></head><body><h1>Index of {0}</h1><ul>",
+            title
+        );
+        // the parent of the prefix isn't served by this directory
+        if !relative.is_empty() {
+            html.push_str(&format!("<li><a href=\"{}\">../</a></li>", parent));
+        }
+        for (name, is_dir) in entries {
+            let slash = if is_dir { "/" } else { "" };
+            html.push_str(&format!(
+                "<li><a href=\"{}{}{}\">{}{}</a></li>",
+                href,
+                escape_url(&name),
+                slash,
+                escape_html(&name),
+                slash
+            ));
+        }
+        html.push_str("</ul></body></html>");
+        Ok(html)
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn escape_url(chunk: &str) -> String {
+    let mut escaped = String::with_capacity(chunk.len());
+    for byte in chunk.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::request::{Headers, Request, RequestLine};
+    use crate::router::{Route, Router};
+    use tokio::io::AsyncReadExt;
+
+    #[test]
+    fn success_relative_path() {
+        let mut dir = StaticDir::new("public");
+        dir.prefix("/assets/");
+        let relative = |path: &str| {
+            dir.relative_path(&PathBuf::parse(path).unwrap())
+                .map(|path| path.to_string())
+        };
+        assert_eq!(
+            relative("/assets/css/main.css"),
+            Some("css/main.css/".into())
+        );
+        assert_eq!(relative("/assets/a%20b.js"), Some("a b.js/".into()));
+        assert_eq!(relative("/assets"), Some("".into()));
+        assert_eq!(relative("/other/main.css"), None);
+    }
+
+    #[test]
+    fn fail_relative_path() {
+        let mut dir = StaticDir::new("public");
+        dir.prefix("/assets");
+        let relative = |path: &str| dir.relative_path(&PathBuf::parse(path).unwrap());
+        assert!(relative("/assets/%2e%2e/secret").is_none());
+        assert!(relative("/assets/%2E%2E%2F%2E%2E/etc/passwd").is_none());
+        assert!(relative("/assets/.env").is_none());
+        assert!(relative("/assets/..%5c..%5cwin.ini").is_none());
+    }
+
+    #[test]
+    fn success_listing() {
+        let root = std::env::temp_dir().join(format!("octane-static-{}", std::process::id()));
+        fs::create_dir_all(root.join("sub dir")).unwrap();
+        fs::write(root.join("<b>.txt"), b"").unwrap();
+        fs::write(root.join(".hidden"), b"").unwrap();
+        let mut dir = StaticDir::new(root.to_str().unwrap());
+        dir.prefix("/files");
+        let html = dir.render_listing(&root, &PathBuf::new()).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+        assert!(html.contains("<a href=\"/files/%3Cb%3E.txt\">&lt;b&gt;.txt</a>"));
+        assert!(html.contains("<a href=\"/files/sub%20dir/\">sub dir/</a>"));
+        assert!(!html.contains("hidden"));
+        assert!(!html.contains("../"));
+    }
+
+    #[test]
+    fn success_precompressed() {
+        let root =
+            std::env::temp_dir().join(format!("octane-precompressed-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("app.js"), b"plain").unwrap();
+        fs::write(root.join("app.js.gz"), b"gzip").unwrap();
+        fs::write(root.join("app.js.br"), b"brotli").unwrap();
+        let dir = StaticDir::new(root.to_str().unwrap());
+        let found = |accept: Option<&str>| {
+            let relative = PathBuf::parse("/app.js").unwrap();
+            match dir.find(relative, accept, EntityTag::None) {
+                Some(Target::File { encoding, vary, .. }) => (encoding, vary),
+                _ => panic!("app.js wasn't found"),
+            }
+        };
+        let gzip = found(Some("gzip, br;q=0.5"));
+        let brotli = found(Some("gzip, br"));
+        let identity = found(Some("deflate"));
+        let missing = found(None);
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(
+            gzip,
+            (Some((Encoding::Gzip, "text/javascript".into())), true)
+        );
+        assert_eq!(
+            brotli.0.map(|(encoding, _)| encoding),
+            Some(Encoding::Brotli)
+        );
+        assert_eq!(identity, (None, true));
+        assert_eq!(missing, (None, true));
+    }
+
+    #[crate::test]
+    async fn success_serve() {
+        let root = std::env::temp_dir().join(format!("octane-serve-{}", std::process::id()));
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("docs").join("index.html"), b"index").unwrap();
+        fs::write(root.join("app.js"), b"plain").unwrap();
+        fs::write(root.join("app.js.gz"), b"gzip").unwrap();
+        let mut router = Router::new();
+        router
+            .add(StaticDir::new(root.to_str().unwrap()).handler())
+            .unwrap();
+        let headers = Headers::parse("Accept-Encoding: gzip".to_owned()).unwrap();
+        let mut served = Vec::new();
+        for line in &[
+            "GET /docs HTTP/1.1",
+            "GET /app.js HTTP/1.1",
+            "GET /none HTTP/1.1",
+        ] {
+            let request = Request::parse(RequestLine::parse(line).unwrap(), &headers, &[]).unwrap();
+            let mut res = Response::new_empty();
+            router.run(request, &mut res).await;
+            let encoding = res.get("Content-Encoding").cloned();
+            let mut body = String::new();
+            res.get_data().1.read_to_string(&mut body).await.unwrap();
+            served.push((body, encoding));
+        }
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(served[0], ("index".into(), None));
+        assert_eq!(served[1], ("gzip".into(), Some("gzip".into())));
+        assert_eq!(served[2], ("".into(), None));
+    }
+}
@@ -0,0 +1,852 @@
+use crate::constants::*;
+use crate::default;
+use crate::query::unescape_hex;
+use crate::request::{Headers, Request};
+use crate::util::parse_params;
+use octane_http::http1x::find_in_slice;
+use octane_http::StatusCode;
+use std::borrow::Cow;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+
+// RFC 2046 caps boundaries at 70 characters
+const MAX_BOUNDARY_LEN: usize = 70;
+// The headers of a part are buffered whole before they are parsed
+const MAX_PART_HEADERS_LEN: usize = 16 * 1024;
+
+static SPOOL_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Parses `multipart/form-data` request bodies, the format browsers
+/// use to submit forms with files. The limits answer with a status
+/// code that can be sent as is
+///
+/// # Example
+///
+/// ```
+/// use octane::prelude::*;
+/// use octane::multipart::MultipartParser;
+/// use octane::request::RequestMethod;
+///
+/// let mut app = Octane::new();
+/// // the files are written to disk as they are read
+/// app.stream_body(RequestMethod::Post, "/upload").unwrap();
+/// app.post(
+///     "/upload",
+///     async_route!(|req, res| {
+///         let mut parser = MultipartParser::new();
+///         parser
+///             .max_part_size(Some(64 * 1024 * 1024))
+///             .spool_files(Some(1024 * 1024));
+///         match parser.parse_stream(req).await {
+///             Ok(form) => {
+///                 let title = form.field("title").unwrap_or("untitled");
+///                 let files = form.files().count();
+///                 res.send(format!("{}: {} files", title, files));
+///             }
+///             Err(status) => {
+///                 res.status(status).send("Invalid form");
+///             }
+///         }
+///         Flow::Stop
+///     }),
+/// )
+/// .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct MultipartParser {
+    max_part_size: Option<usize>,
+    max_parts: Option<usize>,
+    spool_files: Option<usize>,
+    spool_dir: PathBuf,
+}
+
+impl MultipartParser {
+    /// Returns a parser without limits, other than the size of the
+    /// body, which keeps every part in memory
+    pub fn new() -> Self {
+        MultipartParser {
+            max_part_size: None,
+            max_parts: None,
+            spool_files: None,
+            spool_dir: std::env::temp_dir(),
+        }
+    }
+    /// Sets the largest part accepted, a bigger one gives a 413
+    pub fn max_part_size(&mut self, max: Option<usize>) -> &mut Self {
+        self.max_part_size = max;
+        self
+    }
+    /// Sets the largest number of parts accepted, more give a 413
+    pub fn max_parts(&mut self, max: Option<usize>) -> &mut Self {
+        self.max_parts = max;
+        self
+    }
+    /// Writes the files larger than `threshold` bytes to temporary
+    /// files, which are removed when the part is dropped unless they
+    /// are persisted. None keeps every file in memory. Only the files
+    /// of a body read with [`parse_stream`](#method.parse_stream) are
+    /// written as they arrive, without being held in memory whole
+    pub fn spool_files(&mut self, threshold: Option<usize>) -> &mut Self {
+        self.spool_files = threshold;
+        self
+    }
+    /// Sets the directory of the temporary files, the one of the
+    /// system by default
+    pub fn spool_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Self {
+        self.spool_dir = dir.as_ref().to_path_buf();
+        self
+    }
+    /// Parses the body of the request. A request which isn't
+    /// `multipart/form-data` gives a 415, a malformed body a 400 and
+    /// a temporary file which can't be written a 500. The body of
+    /// the routes which stream it is parsed with
+    /// [`parse_stream`](#method.parse_stream)
+    pub fn parse<'a>(&self, request: &Request<'a>) -> Result<Multipart<'a>, StatusCode> {
+        let boundary = request_boundary(request)?;
+        self.parse_body(request.body, &boundary)
+    }
+    /// Parses the body of a route set with
+    /// [`Router::stream_body`](crate::router::Router::stream_body)
+    /// as it's read from the client. The limits are checked as the
+    /// parts arrive and the spooled files are written piece by piece.
+    /// Other requests are parsed like with [`parse`](#method.parse)
+    pub async fn parse_stream<'a>(
+        &self,
+        request: &Request<'a>,
+    ) -> Result<Multipart<'a>, StatusCode> {
+        let stream = match &request.body_stream {
+            Some(stream) => stream,
+            None => return self.parse(request),
+        };
+        let boundary = request_boundary(request)?;
+        let mut splitter = Splitter::new(&boundary);
+        let mut parts = Vec::new();
+        let mut buffer = Vec::new();
+        let mut last = false;
+        loop {
+            let (event, used) = splitter.next(&buffer, last)?;
+            match event {
+                Some(Event::Part(part)) => {
+                    close_spooled(&mut parts).await?;
+                    self.push_part(&mut parts, part)?;
+                }
+                Some(Event::Data(data)) => {
+                    if let Some(part) = parts.last_mut() {
+                        self.stream_data(part, data).await?;
+                    }
+                }
+                Some(Event::End) => {
+                    close_spooled(&mut parts).await?;
+                    // the epilogue is read so that the connection
+                    // can be used for another request
+                    while stream.chunk().await?.is_some() {}
+                    return Ok(Multipart { parts });
+                }
+                None if used == 0 => {
+                    if last {
+                        return Err(StatusCode::BadRequest);
+                    }
+                    match stream.chunk().await? {
+                        Some(chunk) => buffer.extend_from_slice(&chunk),
+                        None => last = true,
+                    }
+                }
+                None => {}
+            }
+            buffer.drain(..used);
+        }
+    }
+    fn parse_body<'a>(&self, body: &'a [u8], boundary: &str) -> Result<Multipart<'a>, StatusCode> {
+        let mut splitter = Splitter::new(boundary);
+        let mut parts = Vec::new();
+        let mut pos = 0;
+        loop {
+            let (event, used) = splitter.next(&body[pos..], true)?;
+            pos += used;
+            match event {
+                Some(Event::Part(part)) => self.push_part(&mut parts, part)?,
+                // the whole body is there, so the data of
+                // a part always comes in one piece
+                Some(Event::Data(data)) => {
+                    if let Some(part) = parts.last_mut() {
+                        if self.max_part_size.map_or(false, |max| data.len() > max) {
+                            return Err(StatusCode::PayloadTooLarge);
+                        }
+                        part.data = match self.spool_files {
+                            Some(threshold) if part.is_file() && data.len() > threshold => {
+                                PartData::Spooled(TempFile::write(&self.spool_dir, data)?)
+                            }
+                            _ => PartData::Memory(Cow::Borrowed(data)),
+                        };
+                    }
+                }
+                Some(Event::End) => return Ok(Multipart { parts }),
+                None if used == 0 => return Err(StatusCode::BadRequest),
+                None => {}
+            }
+        }
+    }
+    fn push_part<'a>(&self, parts: &mut Vec<Part<'a>>, part: Part<'a>) -> Result<(), StatusCode> {
+        if self.max_parts.map_or(false, |max| parts.len() >= max) {
+            return Err(StatusCode::PayloadTooLarge);
+        }
+        parts.push(part);
+        Ok(())
+    }
+    // Adds a piece of a streamed part, a file is moved to a temporary
+    // file once it grows past the threshold
+    async fn stream_data(&self, part: &mut Part<'_>, data: &[u8]) -> Result<(), StatusCode> {
+        let len = part.data.len() + data.len();
+        if self.max_part_size.map_or(false, |max| len > max) {
+            return Err(StatusCode::PayloadTooLarge);
+        }
+        let spool = part.is_file() && self.spool_files.map_or(false, |threshold| len > threshold);
+        if let PartData::Memory(memory) = &mut part.data {
+            if !spool {
+                memory.to_mut().extend_from_slice(data);
+                return Ok(());
+            }
+            let buffered = std::mem::take(memory);
+            let mut file = TempFile::create(&self.spool_dir).await?;
+            file.append(&buffered).await?;
+            part.data = PartData::Spooled(file);
+        }
+        if let PartData::Spooled(file) = &mut part.data {
+            file.append(data).await?;
+        }
+        Ok(())
+    }
+}
+
+default!(MultipartParser);
+
+/// The parts of a `multipart/form-data` body, in the order
+/// they were sent
+#[derive(Debug)]
+pub struct Multipart<'a> {
+    /// The fields and files of the form
+    pub parts: Vec<Part<'a>>,
+}
+
+impl<'a> Multipart<'a> {
+    /// Returns the value of the first field named `name`, None if
+    /// there is none or it isn't valid utf-8
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields()
+            .find(|part| part.name == name)
+            .and_then(Part::text)
+    }
+    /// Returns the first file sent as `name`
+    pub fn file(&self, name: &str) -> Option<&Part<'a>> {
+        self.files().find(|part| part.name == name)
+    }
+    /// Returns the parts which aren't files
+    pub fn fields(&self) -> impl Iterator<Item = &Part<'a>> {
+        self.parts.iter().filter(|part| !part.is_file())
+    }
+    /// Returns the parts which are files
+    pub fn files(&self) -> impl Iterator<Item = &Part<'a>> {
+        self.parts.iter().filter(|part| part.is_file())
+    }
+}
+
+/// A single field or file of a form
+#[derive(Debug)]
+pub struct Part<'a> {
+    /// The name of the form field
+    pub name: String,
+    /// The name of the file without its directories, only set on
+    /// files. It's empty if the client didn't send a usable one
+    pub filename: Option<String>,
+    /// The type of the data, `text/plain` if it wasn't given
+    pub content_type: String,
+    /// All the headers of the part
+    pub headers: Headers,
+    /// The content of the part
+    pub data: PartData<'a>,
+}
+
+impl<'a> Part<'a> {
+    /// Returns true if the part was sent as a file
+    pub fn is_file(&self) -> bool {
+        self.filename.is_some()
+    }
+    /// Returns the data as a str, None if it was spooled to
+    /// a file or isn't valid utf-8
+    pub fn text(&self) -> Option<&str> {
+        match &self.data {
+            PartData::Memory(data) => std::str::from_utf8(data).ok(),
+            PartData::Spooled(_) => None,
+        }
+    }
+}
+
+/// Where the content of a part is held
+#[derive(Debug)]
+pub enum PartData<'a> {
+    /// The content, borrowed from the body of the request unless
+    /// it was streamed
+    Memory(Cow<'a, [u8]>),
+    /// The content was written to a temporary file
+    Spooled(TempFile),
+}
+
+impl<'a> PartData<'a> {
+    /// Returns the size of the content in bytes
+    pub fn len(&self) -> usize {
+        match self {
+            PartData::Memory(data) => data.len(),
+            PartData::Spooled(file) => file.len,
+        }
+    }
+    /// Returns true if the content is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A temporary file holding a spooled part, it's removed when
+/// dropped unless it was persisted
+#[derive(Debug)]
+pub struct TempFile {
+    path: PathBuf,
+    len: usize,
+    persisted: bool,
+    // kept open while a streamed part is written
+    writer: Option<tokio::fs::File>,
+}
+
+impl TempFile {
+    fn new_path(dir: &Path) -> PathBuf {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos());
+        let count = SPOOL_COUNTER.fetch_add(1, Ordering::Relaxed);
+        dir.join(format!(
+            "octane-upload-{}-{:08x}{:08x}",
+            process::id(),
+            nanos,
+            count
+        ))
+    }
+    fn write(dir: &Path, data: &[u8]) -> Result<Self, StatusCode> {
+        let path = Self::new_path(dir);
+        let mut handle = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|_| StatusCode::InternalServerError)?;
+        // dropped on a failed write, which removes the file
+        let file = TempFile {
+            path,
+            len: data.len(),
+            persisted: false,
+            writer: None,
+        };
+        handle
+            .write_all(data)
+            .map_err(|_| StatusCode::InternalServerError)?;
+        Ok(file)
+    }
+    // Creates an empty file to append the pieces of a streamed part to
+    async fn create(dir: &Path) -> Result<Self, StatusCode> {
+        let path = Self::new_path(dir);
+        let writer = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .await
+            .map_err(|_| StatusCode::InternalServerError)?;
+        Ok(TempFile {
+            path,
+            len: 0,
+            persisted: false,
+            writer: Some(writer),
+        })
+    }
+    async fn append(&mut self, data: &[u8]) -> Result<(), StatusCode> {
+        if let Some(writer) = &mut self.writer {
+            writer
+                .write_all(data)
+                .await
+                .map_err(|_| StatusCode::InternalServerError)?;
+            self.len += data.len();
+        }
+        Ok(())
+    }
+    // Waits for the pending writes once the whole part was appended
+    async fn close(&mut self) -> Result<(), StatusCode> {
+        if let Some(mut writer) = self.writer.take() {
+            writer
+                .flush()
+                .await
+                .map_err(|_| StatusCode::InternalServerError)?;
+        }
+        Ok(())
+    }
+    /// Returns the path of the temporary file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// Opens the temporary file for reading
+    pub fn open(&self) -> io::Result<File> {
+        File::open(&self.path)
+    }
+    /// Moves the file to `to`, so that it's kept once the part
+    /// is dropped. It's copied when `to` is on another file system
+    pub fn persist<P: AsRef<Path>>(mut self, to: P) -> io::Result<()> {
+        let to = to.as_ref();
+        if fs::rename(&self.path, to).is_err() {
+            let mut target = File::create(to)?;
+            io::copy(&mut self.open()?, &mut target)?;
+            target.flush()?;
+            fs::remove_file(&self.path).ok();
+        }
+        self.persisted = true;
+        Ok(())
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            fs::remove_file(&self.path).ok();
+        }
+    }
+}
+
+// Closes the temporary file of the last part once it's complete
+async fn close_spooled(parts: &mut Vec<Part<'_>>) -> Result<(), StatusCode> {
+    match parts.last_mut() {
+        Some(Part {
+            data: PartData::Spooled(file),
+            ..
+        }) => file.close().await,
+        _ => Ok(()),
+    }
+}
+
+// How far the splitter got in the body
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Start,
+    Preamble,
+    Delimiter,
+    Headers,
+    Data,
+    Epilogue,
+}
+
+// What the splitter found at the start of the bytes it was given
+enum Event<'b> {
+    // a new part, with its headers parsed and no data yet
+    Part(Part<'static>),
+    // more of the content of the last part
+    Data(&'b [u8]),
+    // the closing delimiter
+    End,
+}
+
+// Splits a body into its parts as it arrives, only a delimiter
+// and the headers of a part ever have to be buffered
+struct Splitter {
+    delimiter: Vec<u8>,
+    state: State,
+}
+
+impl Splitter {
+    fn new(boundary: &str) -> Self {
+        Splitter {
+            delimiter: format!("{}--{}", CRLF, boundary).into_bytes(),
+            state: State::Start,
+        }
+    }
+    // Returns the event at the start of `input` along with the number
+    // of bytes it used, none of them and no event means that more of
+    // the body is needed. `last` is set once `input` has all of it
+    fn next<'b>(
+        &mut self,
+        input: &'b [u8],
+        last: bool,
+    ) -> Result<(Option<Event<'b>>, usize), StatusCode> {
+        let delimiter = &self.delimiter[..];
+        match self.state {
+            // the first delimiter doesn't need a CRLF before it
+            State::Start => {
+                let first = &delimiter[2..];
+                if input.starts_with(first) {
+                    self.state = State::Delimiter;
+                    return Ok((None, first.len()));
+                }
+                if !last && first.starts_with(input) {
+                    return Ok((None, 0));
+                }
+                self.state = State::Preamble;
+                self.next(input, last)
+            }
+            // anything coming before the first delimiter is ignored
+            State::Preamble => match find_in_slice(input, delimiter) {
+                Some(start) => {
+                    self.state = State::Delimiter;
+                    Ok((None, start + delimiter.len()))
+                }
+                None if last => Err(StatusCode::BadRequest),
+                None => Ok((None, input.len().saturating_sub(delimiter.len() - 1))),
+            },
+            State::Delimiter => {
+                // the closing delimiter, the epilogue after it is ignored
+                if input.starts_with(b"--") {
+                    self.state = State::Epilogue;
+                    return Ok((Some(Event::End), input.len()));
+                }
+                // the delimiter line can end with whitespace
+                let padding = input
+                    .iter()
+                    .take_while(|&&c| c == SP as u8 || c == HT as u8)
+                    .count();
+                let rest = &input[padding..];
+                if rest.starts_with(B_CRLF) {
+                    self.state = State::Headers;
+                    return Ok((None, padding + 2));
+                }
+                if last || !(rest.is_empty() || rest == b"\r" || input == b"-") {
+                    return Err(StatusCode::BadRequest);
+                }
+                Ok((None, 0))
+            }
+            State::Headers => {
+                let (head, used) = if input.starts_with(B_CRLF) {
+                    (&input[..0], 2)
+                } else {
+                    match find_in_slice(input, b"\r\n\r\n") {
+                        Some(end) => (&input[..end], end + 4),
+                        None if last => return Err(StatusCode::BadRequest),
+                        None if input.len() > MAX_PART_HEADERS_LEN => {
+                            return Err(StatusCode::RequestHeaderFieldsTooLarge)
+                        }
+                        None => return Ok((None, 0)),
+                    }
+                };
+                let part = parse_head(head)?;
+                self.state = State::Data;
+                Ok((Some(Event::Part(part)), used))
+            }
+            State::Data => match find_in_slice(input, delimiter) {
+                Some(end) => {
+                    self.state = State::Delimiter;
+                    Ok((Some(Event::Data(&input[..end])), end + delimiter.len()))
+                }
+                // a part which isn't followed by a delimiter was cut short
+                None if last => Err(StatusCode::BadRequest),
+                // the end can be the start of a delimiter
+                None => match input.len().saturating_sub(delimiter.len() - 1) {
+                    0 => Ok((None, 0)),
+                    safe => Ok((Some(Event::Data(&input[..safe])), safe)),
+                },
+            },
+            State::Epilogue => Ok((None, input.len())),
+        }
+    }
+}
+
+// Returns a part without data from the headers of a part
+fn parse_head(head: &[u8]) -> Result<Part<'static>, StatusCode> {
+    let headers = if head.is_empty() {
+        Headers::default()
+    } else {
+        String::from_utf8(head.to_vec())
+            .ok()
+            .and_then(Headers::parse)
+            .ok_or(StatusCode::BadRequest)?
+    };
+    let disposition = headers
+        .get("content-disposition")
+        .ok_or(StatusCode::BadRequest)?;
+    let (kind, params) = parse_params(disposition);
+    if !kind.eq_ignore_ascii_case("form-data") {
+        return Err(StatusCode::BadRequest);
+    }
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.to_owned())
+    };
+    let name = param("name").ok_or(StatusCode::BadRequest)?;
+    let filename = param("filename*")
+        .and_then(|value| extended_value(&value))
+        .or_else(|| param("filename"))
+        .map(|filename| base_name(&filename));
+    // the default of RFC 7578, 4.4
+    let content_type = headers
+        .get("content-type")
+        .map_or("text/plain", String::as_str)
+        .to_owned();
+    Ok(Part {
+        name,
+        filename,
+        content_type,
+        headers,
+        data: PartData::Memory(Cow::Borrowed(&[])),
+    })
+}
+
+fn request_boundary(request: &Request<'_>) -> Result<String, StatusCode> {
+    let content_type = request
+        .headers
+        .get("content-type")
+        .ok_or(StatusCode::UnsupportedMediaType)?;
+    boundary(content_type)
+}
+
+// Returns the boundary of a `multipart/form-data` content type
+fn boundary(content_type: &str) -> Result<String, StatusCode> {
+    let (kind, params) = parse_params(content_type);
+    if !kind.eq_ignore_ascii_case("multipart/form-data") {
+        return Err(StatusCode::UnsupportedMediaType);
+    }
+    let boundary = params
+        .into_iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value)
+        .ok_or(StatusCode::BadRequest)?;
+    if boundary.is_empty() || boundary.len() > MAX_BOUNDARY_LEN {
+        return Err(StatusCode::BadRequest);
+    }
+    Ok(boundary)
+}
+
+// Decodes a `UTF-8''name` value of RFC 5987, other charsets are ignored
+fn extended_value(value: &str) -> Option<String> {
+    let mut toks = value.splitn(3, '\'');
+    let charset = toks.next()?;
+    let encoded = toks.nth(1)?;
+    if charset.eq_ignore_ascii_case("utf-8") {
+        Some(unescape_hex(encoded))
+    } else {
+        None
+    }
+}
+
+// Old browsers send the full path of the file, the directories
+// mustn't end up in a path on the server. A name which would still
+// point to a directory is left empty
+fn base_name(filename: &str) -> String {
+    let name: String = filename
+        .rsplit(|c| c == '/' || c == '\\')
+        .next()
+        .unwrap_or("")
+        .chars()
+        .filter(|&c| c != '\0')
+        .collect();
+    match name.as_str() {
+        "." | ".." => String::new(),
+        _ => name,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::request::{Connection, RequestLine};
+
+    const BODY: &[u8] = b"preamble\r\n--XyZ\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+        Holiday\r\n--XyZ  \r\n\
+        Content-Disposition: form-data; name=\"photo\"; filename=\"C:\\\\pics\\\\a \\\"b\\\".png\"\r\n\
+        Content-Type: image/png\r\n\r\n\
+        \x89PNG\r\n\r\n--XyZ\r\n\
+        Content-Disposition: form-data; name=\"note\"; filename*=UTF-8''%C3%A9t%C3%A9.txt\r\n\r\n\
+        \r\n--XyZ--\r\nepilogue";
+
+    #[test]
+    fn success_parse() {
+        // Fields and files should be split with their metadata.
+        let form = MultipartParser::new().parse_body(BODY, "XyZ").unwrap();
+        assert_eq!(form.parts.len(), 3);
+        assert_eq!(form.field("title"), Some("Holiday"));
+        assert_eq!(form.fields().count(), 1);
+        let photo = form.file("photo").unwrap();
+        assert_eq!(photo.filename.as_deref(), Some("a \"b\".png"));
+        assert_eq!(photo.content_type, "image/png");
+        assert_eq!(photo.headers.get("content-type").unwrap(), "image/png");
+        match &photo.data {
+            PartData::Memory(data) => assert_eq!(&data[..], b"\x89PNG\r\n"),
+            _ => panic!("photo should be in memory"),
+        }
+        let note = form.file("note").unwrap();
+        assert_eq!(note.filename.as_deref(), Some("été.txt"));
+        assert_eq!(note.content_type, "text/plain");
+        assert!(note.data.is_empty());
+    }
+
+    fn stream_headers(len: usize) -> Headers {
+        Headers::parse(format!(
+            "Content-Type: multipart/form-data; boundary=XyZ\r\nContent-Length: {}",
+            len
+        ))
+        .unwrap()
+    }
+
+    // A request streaming its body from a connection which sends it
+    // a few bytes at a time and is then left open
+    fn streamed(headers: &Headers, body: Vec<u8>) -> Request<'_> {
+        let (mut client, server) = tokio::io::duplex(7);
+        let server: Box<dyn Connection> = Box::new(server);
+        let (reader, _) = tokio::io::split(server);
+        tokio::spawn(async move {
+            client.write_all(&body).await.ok();
+            std::future::pending::<()>().await;
+        });
+        let request_line = RequestLine::parse("POST /upload HTTP/1.1").unwrap();
+        Request::from_stream(headers, request_line, b"", reader, None).unwrap()
+    }
+
+    #[crate::test]
+    async fn success_parse_stream() {
+        // Streamed files should be written to disk as they arrive.
+        let headers = stream_headers(BODY.len());
+        let req = streamed(&headers, BODY.to_vec());
+        let mut parser = MultipartParser::new();
+        parser.spool_files(Some(4));
+        let form = parser.parse_stream(&req).await.unwrap();
+        assert_eq!(form.parts.len(), 3);
+        assert_eq!(form.field("title"), Some("Holiday"));
+        let photo = form.file("photo").unwrap();
+        assert_eq!(photo.filename.as_deref(), Some("a \"b\".png"));
+        match &photo.data {
+            PartData::Spooled(file) => {
+                assert_eq!(photo.data.len(), 6);
+                assert_eq!(fs::read(file.path()).unwrap(), b"\x89PNG\r\n");
+            }
+            _ => panic!("photo should be spooled"),
+        }
+        assert_eq!(
+            form.file("note").unwrap().filename.as_deref(),
+            Some("été.txt")
+        );
+        assert!(form.file("note").unwrap().data.is_empty());
+        // the epilogue was read, so the connection can be reused
+        assert!(req.body_stream.as_ref().unwrap().finish().await.is_some());
+    }
+
+    #[crate::test]
+    async fn fail_parse_stream() {
+        // A part over the limit should be refused before the body is all sent.
+        let mut body =
+            b"--XyZ\r\nContent-Disposition: form-data; name=\"a\"; filename=\"a\"\r\n\r\n".to_vec();
+        body.extend_from_slice(&[b'x'; 100]);
+        let headers = stream_headers(1024 * 1024);
+        let req = streamed(&headers, body);
+        let mut parser = MultipartParser::new();
+        parser.max_part_size(Some(64));
+        assert_eq!(
+            parser.parse_stream(&req).await.err(),
+            Some(StatusCode::PayloadTooLarge)
+        );
+
+        // A body without its closing delimiter was cut short.
+        let body = &BODY[..BODY.len() - 20];
+        let headers = stream_headers(body.len());
+        let req = streamed(&headers, body.to_vec());
+        assert_eq!(
+            MultipartParser::new().parse_stream(&req).await.err(),
+            Some(StatusCode::BadRequest)
+        );
+    }
+
+    #[test]
+    fn success_base_name() {
+        assert_eq!(base_name("a.png"), "a.png");
+        assert_eq!(base_name("C:\\pics\\a.png"), "a.png");
+        assert_eq!(base_name("/etc/../passwd"), "passwd");
+        assert_eq!(base_name("a\0.png"), "a.png");
+        assert_eq!(base_name(".hidden"), ".hidden");
+        // names which would point to a directory
+        assert_eq!(base_name(""), "");
+        assert_eq!(base_name("."), "");
+        assert_eq!(base_name(".."), "");
+        assert_eq!(base_name("a/.."), "");
+        assert_eq!(base_name("..\\"), "");
+        assert_eq!(base_name(".\0."), "");
+    }
+
+    #[test]
+    fn success_boundary() {
+        assert_eq!(
+            boundary("multipart/form-data; boundary=abc").unwrap(),
+            "abc"
+        );
+        assert_eq!(
+            boundary("Multipart/Form-Data; charset=utf-8; BOUNDARY=\"a b;c\"").unwrap(),
+            "a b;c"
+        );
+        assert_eq!(
+            boundary("application/json"),
+            Err(StatusCode::UnsupportedMediaType)
+        );
+        assert_eq!(boundary("multipart/form-data"), Err(StatusCode::BadRequest));
+    }
+
+    #[test]
+    fn success_spool() {
+        // Large files should be written to temporary files.
+        let mut parser = MultipartParser::new();
+        parser.spool_files(Some(4));
+        let form = parser.parse_body(BODY, "XyZ").unwrap();
+        assert_eq!(form.field("title"), Some("Holiday"));
+        let path = match &form.file("photo").unwrap().data {
+            PartData::Spooled(file) => {
+                assert_eq!(fs::read(file.path()).unwrap(), b"\x89PNG\r\n");
+                file.path().to_path_buf()
+            }
+            _ => panic!("photo should be spooled"),
+        };
+        drop(form);
+        assert!(!path.exists());
+
+        let form = parser.parse_body(BODY, "XyZ").unwrap();
+        let target = std::env::temp_dir().join(format!("octane-persist-{}", process::id()));
+        match form.parts.into_iter().nth(1).unwrap().data {
+            PartData::Spooled(file) => file.persist(&target).unwrap(),
+            _ => panic!("photo should be spooled"),
+        }
+        assert_eq!(fs::read(&target).unwrap(), b"\x89PNG\r\n");
+        fs::remove_file(&target).unwrap();
+    }
+
+    #[test]
+    fn fail_parse() {
+        let parser = MultipartParser::new();
+        // no delimiter, a missing closing delimiter, no name
+        assert!(parser.parse_body(b"abc", "XyZ").is_err());
+        assert!(parser.parse_body(&BODY[..BODY.len() - 20], "XyZ").is_err());
+        assert!(parser
+            .parse_body(
+                b"--XyZ\r\nContent-Disposition: form-data\r\n\r\nx\r\n--XyZ--",
+                "XyZ"
+            )
+            .is_err());
+        assert!(parser
+            .parse_body(b"--XyZ\r\n\r\nx\r\n--XyZ--", "XyZ")
+            .is_err());
+
+        let mut parser = MultipartParser::new();
+        parser.max_part_size(Some(4));
+        assert_eq!(
+            parser.parse_body(BODY, "XyZ").err(),
+            Some(StatusCode::PayloadTooLarge)
+        );
+        let mut parser = MultipartParser::new();
+        parser.max_parts(Some(2));
+        assert_eq!(
+            parser.parse_body(BODY, "XyZ").err(),
+            Some(StatusCode::PayloadTooLarge)
+        );
+    }
+}
@@ -94,6 +94,29 @@ impl PathBuf {
         Some(())
     }
 
+    /// Check if the given pathbuf in url starts with this one, the
+    /// variables of this one take the values of their chunks
+    #[cfg(feature = "url_variables")]
+    pub fn check_prefix<'a, 'b>(&'a self, other: &'b PathBuf) -> Option<HashMap<&'a str, &'b str>> {
+        if self.len() > other.len() {
+            return None;
+        }
+        let mut vars = HashMap::new();
+        for (a, b) in self.iter().zip(other.iter()) {
+            if a.as_bytes()[0] == b':' {
+                vars.insert(&a[1..], &b[..]);
+            } else if a != b {
+                return None;
+            }
+        }
+        Some(vars)
+    }
+
+    #[cfg(not(feature = "url_variables"))]
+    pub fn check_prefix(&self, other: &PathBuf) -> Option<()> {
+        other.subtract(self).map(|_| ())
+    }
+
     pub fn subtract(&self, other: &PathBuf) -> Option<PathBuf> {
         if self.len() < other.len() {
             return None;
@@ -327,7 +350,7 @@ impl<'a, T> Iterator for PathNodeIterator<'a, T> {
 }
 
 impl<T> PathNode<T> {
-    pub fn iter(&self) -> PathNodeIterator<T> {
+    pub fn iter(&self) -> PathNodeIterator<'_, T> {
         match self {
             PathNode::Node(n) => PathNodeIterator {
                 stack: vec![n.values()],
@@ -498,6 +521,31 @@ mod test {
         assert!(path1.subtract(&path4).is_none());
     }
 
+    #[test]
+    fn success_prefix() {
+        let path = PathBuf::parse("/api/v1/users/3").unwrap();
+        assert!(PathBuf::parse("/api/v1")
+            .unwrap()
+            .check_prefix(&path)
+            .is_some());
+        assert!(PathBuf::parse("/").unwrap().check_prefix(&path).is_some());
+        assert!(path.check_prefix(&path).is_some());
+        assert!(PathBuf::parse("/api/v2")
+            .unwrap()
+            .check_prefix(&path)
+            .is_none());
+        assert!(PathBuf::parse("/api/v1/users/3/4")
+            .unwrap()
+            .check_prefix(&path)
+            .is_none());
+        #[cfg(feature = "url_variables")]
+        {
+            let prefix = PathBuf::parse("/api/:version").unwrap();
+            let vars = prefix.check_prefix(&path).unwrap();
+            assert_eq!(vars.get("version"), Some(&"v1"));
+        }
+    }
+
     #[test]
     #[should_panic]
     fn fail_traversal() {
@@ -1,42 +1,47 @@
+use crate::default;
+use crate::request::Request;
+use crate::util::parse_params;
+use octane_http::StatusCode;
 use std::collections::HashMap;
 
-pub fn unescape_hex(string: &str) -> String {
-    let mut ret = "".to_owned();
+pub(crate) fn unescape_hex(string: &str) -> String {
+    // escaped bytes are collected first, a character can span several of them
+    let mut ret = Vec::with_capacity(string.len());
     let mut chars = string.chars();
     let mut peekable = DoublePeek::new(&mut chars);
     while let Some(val) = peekable.next() {
         if val != '%' {
-            ret.push(val);
+            ret.extend_from_slice(val.encode_utf8(&mut [0; 4]).as_bytes());
         } else {
             peekable.unpeek = true;
             let c1 = match peekable.peek() {
                 Some(&v) => v,
                 None => {
-                    ret.push('%');
+                    ret.push(b'%');
                     continue;
                 }
             };
             let c2 = match peekable.peek() {
                 Some(&v) => v,
                 None => {
-                    ret.push('%');
+                    ret.push(b'%');
                     continue;
                 }
             };
             let (h1, h2) = (from_hex(c1), from_hex(c2));
             if h1.is_none() || h2.is_none() {
-                ret.push('%');
+                ret.push(b'%');
                 continue;
             }
-            ret.push((h1.unwrap() * 16 + h2.unwrap()) as char);
+            ret.push(h1.unwrap() * 16 + h2.unwrap());
             peekable.next();
             peekable.next();
         }
     }
-    ret
+    String::from_utf8_lossy(&ret).into_owned()
 }
 
-pub struct DoublePeek<'a, T>
+pub(crate) struct DoublePeek<'a, T>
 where
     T: Default,
 {
@@ -99,7 +104,7 @@ where
     }
 }
 
-pub fn from_hex(chr: char) -> Option<u8> {
+pub(crate) fn from_hex(chr: char) -> Option<u8> {
     if chr > 'f' {
         return None;
     }
@@ -116,29 +121,191 @@ pub fn from_hex(chr: char) -> Option<u8> {
     None
 }
 
-pub fn parse_query(query: &str) -> HashMap<String, String> {
-    let toks = query.split('&');
+pub(crate) fn parse_query(query: &str) -> HashMap<String, String> {
+    collect_query(parse_pairs(query.as_bytes(), false, Charset::Utf8, None).unwrap_or_default())
+}
+
+fn collect_query(pairs: Vec<(String, String)>) -> HashMap<String, String> {
     let mut ret: HashMap<String, String> = HashMap::new();
-    for tok in toks {
+    for (name, val) in pairs {
+        if cfg!(feature = "faithful") && name.is_empty() {
+            continue;
+        }
+        ret.insert(name, val);
+    }
+    ret
+}
+
+/// The charset the escaped bytes of a query are decoded with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Charset {
+    Utf8,
+    Latin1,
+}
+
+impl Charset {
+    // None for the charsets which aren't supported
+    pub(crate) fn from_label(label: &str) -> Option<Self> {
+        match label.trim().to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" | "us-ascii" => Some(Charset::Utf8),
+            "iso-8859-1" | "iso8859-1" | "latin1" | "l1" => Some(Charset::Latin1),
+            _ => None,
+        }
+    }
+    fn decode(self, bytes: &[u8]) -> String {
+        match self {
+            Charset::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Charset::Latin1 => bytes.iter().map(|&b| char::from(b)).collect(),
+        }
+    }
+}
+
+// Splits a query into its unescaped names and values, forms also
+// escape spaces as `+`. More than `max` fields give a 413
+pub(crate) fn parse_pairs(
+    query: &[u8],
+    plus_as_space: bool,
+    charset: Charset,
+    max: Option<usize>,
+) -> Result<Vec<(String, String)>, StatusCode> {
+    let mut pairs = Vec::new();
+    for tok in query.split(|&c| c == b'&') {
         if cfg!(feature = "faithful") && tok.is_empty() {
             continue;
         }
-        match tok.find('=') {
-            Some(v) => {
-                let (name, val) = tok.split_at(v);
-                if cfg!(feature = "faithful") && name.is_empty() {
-                    continue;
-                }
-                ret.insert(unescape_hex(name), unescape_hex(&val[1..]));
-            }
-            None => {
-                ret.insert(unescape_hex(tok), "".to_owned());
+        if max.map_or(false, |max| pairs.len() >= max) {
+            return Err(StatusCode::PayloadTooLarge);
+        }
+        let (name, val) = match tok.iter().position(|&c| c == b'=') {
+            Some(v) => (&tok[..v], &tok[v + 1..]),
+            None => (tok, &[][..]),
+        };
+        pairs.push((
+            charset.decode(&unescape_bytes(name, plus_as_space)),
+            charset.decode(&unescape_bytes(val, plus_as_space)),
+        ));
+    }
+    Ok(pairs)
+}
+
+fn unescape_bytes(escaped: &[u8], plus_as_space: bool) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(escaped.len());
+    let mut i = 0;
+    while i < escaped.len() {
+        let hex = escaped
+            .get(i + 1..i + 3)
+            .and_then(|h| Some(from_hex(h[0] as char)? * 16 + from_hex(h[1] as char)?));
+        match (escaped[i], hex) {
+            (b'%', Some(byte)) => {
+                ret.push(byte);
+                i += 3;
+                continue;
             }
+            (b'+', _) if plus_as_space => ret.push(b' '),
+            (c, _) => ret.push(c),
         }
+        i += 1;
     }
     ret
 }
 
+/// Parses `application/x-www-form-urlencoded` request bodies, the
+/// format of the html forms without files, with the same rules as
+/// the query of the url. Spaces can be sent as `+` and the charset
+/// is taken from the `Content-Type` or from a `_charset_` field,
+/// `utf-8` and `iso-8859-1` are supported
+///
+/// # Example
+///
+/// ```
+/// use octane::prelude::*;
+/// use octane::query::FormParser;
+///
+/// let mut app = Octane::new();
+/// app.post(
+///     "/login",
+///     route!(|req, res| {
+///         let mut parser = FormParser::new();
+///         parser.max_fields(Some(8));
+///         match parser.parse(req) {
+///             Ok(form) => {
+///                 let user = form.get("user").map_or("", String::as_str);
+///                 res.send(format!("Hello, {}", user));
+///             }
+///             Err(status) => {
+///                 res.status(status).send("Invalid form");
+///             }
+///         }
+///         Flow::Stop
+///     }),
+/// )
+/// .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct FormParser {
+    max_fields: Option<usize>,
+}
+
+impl FormParser {
+    /// Returns a parser accepting up to 1000 fields
+    pub fn new() -> Self {
+        FormParser {
+            max_fields: Some(1000),
+        }
+    }
+    /// Sets the largest number of fields accepted, more give a 413
+    pub fn max_fields(&mut self, max: Option<usize>) -> &mut Self {
+        self.max_fields = max;
+        self
+    }
+    /// Parses the body of the request. A request which isn't
+    /// `application/x-www-form-urlencoded` or has another charset
+    /// gives a 415
+    pub fn parse(&self, request: &Request) -> Result<HashMap<String, String>, StatusCode> {
+        Ok(collect_query(self.parse_pairs(request)?))
+    }
+    /// Parses the body of the request like
+    /// [`parse`](FormParser::parse) with the nested fields of
+    /// [`Request::get_extended_query`](crate::request::Request::get_extended_query)
+    #[cfg(feature = "extended_queries")]
+    pub fn parse_extended(
+        &self,
+        request: &Request,
+    ) -> Result<HashMap<String, QueryValue>, StatusCode> {
+        Ok(collect_extended_query(self.parse_pairs(request)?))
+    }
+    fn parse_pairs(&self, request: &Request) -> Result<Vec<(String, String)>, StatusCode> {
+        let content_type = request
+            .headers
+            .get("content-type")
+            .ok_or(StatusCode::UnsupportedMediaType)?;
+        let (kind, params) = parse_params(content_type);
+        if !kind.eq_ignore_ascii_case("application/x-www-form-urlencoded") {
+            return Err(StatusCode::UnsupportedMediaType);
+        }
+        // browsers don't label forms, the `_charset_` field is
+        // filled with the charset they used
+        let label = params
+            .into_iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("charset"))
+            .map(|(_, value)| value)
+            .or_else(|| {
+                request
+                    .body
+                    .split(|&c| c == b'&')
+                    .find_map(|tok| tok.strip_prefix(b"_charset_="))
+                    .map(|label| String::from_utf8_lossy(label).into_owned())
+            });
+        let charset = match label {
+            Some(label) => Charset::from_label(&label).ok_or(StatusCode::UnsupportedMediaType)?,
+            None => Charset::Utf8,
+        };
+        parse_pairs(request.body, true, charset, self.max_fields)
+    }
+}
+
+default!(FormParser);
+
 #[cfg(feature = "extended_queries")]
 /// An parsed unit from an `extended_query`. We catch a request
 /// which has an extended query as the path, we call `get_extended_query()`
@@ -194,65 +361,42 @@ impl QueryValue {
 }
 
 #[cfg(feature = "extended_queries")]
-pub fn parse_extended_query(query: &str) -> HashMap<String, QueryValue> {
-    let toks = query.split('&');
+pub(crate) fn parse_extended_query(query: &str) -> HashMap<String, QueryValue> {
+    collect_extended_query(
+        parse_pairs(query.as_bytes(), false, Charset::Utf8, None).unwrap_or_default(),
+    )
+}
+
+// Names ending with `[]` are collected in arrays and the ones ending
+// with `[key]` in objects, the names are matched once unescaped
+#[cfg(feature = "extended_queries")]
+fn collect_extended_query(pairs: Vec<(String, String)>) -> HashMap<String, QueryValue> {
     let mut ret: HashMap<String, QueryValue> = HashMap::new();
-    for tok in toks {
-        if cfg!(feature = "faithful") && tok.is_empty() {
+    for (name, val) in pairs {
+        if name.is_empty() {
             continue;
         }
-        match tok.find('=') {
-            Some(v) => {
-                let (name, val) = tok.split_at(v);
-                let unescaped_val = unescape_hex(&val[1..]);
-                if name.is_empty() {
-                    continue;
-                }
-                let bytes = name.as_bytes();
-                let bytelen = bytes.len();
-                if bytes[bytelen - 1] == b']' {
-                    if let Some(i) = bytes.iter().rev().position(|&v| v == b'[') {
-                        let ind = bytelen - i - 1;
-                        let outside = match String::from_utf8((&bytes[0..ind]).to_vec()) {
-                            Ok(v) => v,
-                            Err(_) => continue,
-                        };
-                        let inside =
-                            match String::from_utf8((&bytes[ind + 1..bytelen - 1]).to_vec()) {
-                                Ok(v) => v,
-                                Err(_) => continue,
-                            };
-                        if inside.is_empty() {
-                            if ret.get(&outside).is_none() {
-                                ret.insert(outside.clone(), QueryValue::Arr(Vec::new()));
-                            }
-                            match ret.get_mut(&outside) {
-                                Some(QueryValue::Arr(v)) => {
-                                    v.push(unescaped_val);
-                                    continue;
-                                }
-                                _ => continue,
-                            }
-                        } else {
-                            if ret.get(&outside).is_none() {
-                                ret.insert(outside.clone(), QueryValue::Obj(HashMap::new()));
-                            }
-                            match ret.get_mut(&outside) {
-                                Some(QueryValue::Obj(v)) => {
-                                    v.insert(inside, unescaped_val);
-                                    continue;
-                                }
-                                _ => continue,
-                            }
-                        }
+        if name.ends_with(']') {
+            if let Some(ind) = name.rfind('[') {
+                let outside = name[..ind].to_owned();
+                let inside = name[ind + 1..name.len() - 1].to_owned();
+                if inside.is_empty() {
+                    if let QueryValue::Arr(v) = ret
+                        .entry(outside)
+                        .or_insert_with(|| QueryValue::Arr(Vec::new()))
+                    {
+                        v.push(val);
                     }
+                } else if let QueryValue::Obj(v) = ret
+                    .entry(outside)
+                    .or_insert_with(|| QueryValue::Obj(HashMap::new()))
+                {
+                    v.insert(inside, val);
                 }
-                ret.insert(unescape_hex(name), QueryValue::Str(unescaped_val));
-            }
-            None => {
-                ret.insert(unescape_hex(tok), QueryValue::Str("".to_owned()));
+                continue;
             }
         }
+        ret.insert(name, QueryValue::Str(val));
     }
     ret
 }
@@ -261,6 +405,13 @@ pub fn parse_extended_query(query: &str) -> HashMap<String, QueryValue> {
 mod test {
     use super::*;
 
+    #[test]
+    fn success_unescape_hex() {
+        assert_eq!(unescape_hex("ab%20cd%4A"), "ab cdJ");
+        assert_eq!(unescape_hex("caf%C3%A9%"), "café%");
+        assert_eq!(unescape_hex("%zz"), "%zz");
+    }
+
     #[test]
     #[cfg(feature = "extended_queries")]
     fn success_standard_extended_queries() {
@@ -331,4 +482,69 @@ mod test {
         assert_eq!(parse_query("=x").len(), 0);
         assert_eq!(parse_query("=x&=y").len(), 0);
     }
+
+    fn form(
+        content_type: &str,
+        body: &[u8],
+        max: Option<usize>,
+    ) -> Result<HashMap<String, String>, StatusCode> {
+        use crate::request::{Headers, RequestLine};
+        let headers = Headers::parse(format!("Content-Type: {}", content_type)).unwrap();
+        let request_line = RequestLine::parse("POST / HTTP/1.1").unwrap();
+        let request = Request::parse(request_line, &headers, body).unwrap();
+        FormParser::new().max_fields(max).parse(&request)
+    }
+
+    #[test]
+    fn success_form() {
+        // Forms should decode `+` and their charset.
+        let urlencoded = "application/x-www-form-urlencoded";
+        let parsed = form(urlencoded, b"name=Jane+Doe&city=K%C3%B6ln&sum=1%2B1", None).unwrap();
+        assert_eq!(parsed["name"], "Jane Doe");
+        assert_eq!(parsed["city"], "Köln");
+        assert_eq!(parsed["sum"], "1+1");
+        let latin1 = format!("{}; charset=ISO-8859-1", urlencoded);
+        assert_eq!(form(&latin1, b"city=K%F6ln", None).unwrap()["city"], "Köln");
+        let parsed = form(urlencoded, b"city=K%F6ln&_charset_=iso-8859-1", None).unwrap();
+        assert_eq!(parsed["city"], "Köln");
+        // the query of the url keeps `+`
+        assert_eq!(parse_query("a=b+c")["a"], "b+c");
+    }
+
+    #[test]
+    fn fail_form() {
+        let urlencoded = "application/x-www-form-urlencoded";
+        assert_eq!(
+            form("text/plain", b"a=b", None),
+            Err(StatusCode::UnsupportedMediaType)
+        );
+        assert_eq!(
+            form(&format!("{}; charset=koi8-r", urlencoded), b"a=b", None),
+            Err(StatusCode::UnsupportedMediaType)
+        );
+        assert_eq!(form(urlencoded, b"a=1&b=2&c=3", Some(3)).unwrap().len(), 3);
+        assert_eq!(
+            form(urlencoded, b"a=1&b=2&c=3&d=4", Some(3)),
+            Err(StatusCode::PayloadTooLarge)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "extended_queries")]
+    fn success_extended_form() {
+        // Escaped brackets, as browsers send them, should nest fields.
+        let headers = crate::request::Headers::parse(
+            "Content-Type: application/x-www-form-urlencoded".to_owned(),
+        )
+        .unwrap();
+        let request_line = crate::request::RequestLine::parse("POST / HTTP/1.1").unwrap();
+        let body = b"tags%5B%5D=a+b&tags%5B%5D=c&user%5Bname%5D=Jane";
+        let request = Request::parse(request_line, &headers, body).unwrap();
+        let parsed = request.get_extended_form().unwrap();
+        assert_eq!(
+            parsed["tags"],
+            QueryValue::Arr(vec!["a b".to_owned(), "c".to_owned()])
+        );
+        assert_eq!(parsed["user"].get_obj().unwrap()["name"], "Jane");
+    }
 }
@@ -0,0 +1,268 @@
+use std::cmp;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, ReadBuf};
+
+// A header with more ranges is ignored and the whole body is sent,
+// many small overlapping ranges are a cheap way to amplify a response
+const MAX_RANGES: usize = 32;
+
+static BOUNDARY_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// The outcome of a `Range` header checked against the
+/// length of the body, the ranges are inclusive
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Ranges {
+    /// The header is malformed or uses another unit, it's ignored
+    Ignore,
+    /// None of the ranges overlap the body
+    Unsatisfiable,
+    Satisfiable(Vec<(u64, u64)>),
+}
+
+impl Ranges {
+    pub(crate) fn parse(header: &str, len: u64) -> Self {
+        let (unit, specs) = match header.split_once('=') {
+            Some(split) => split,
+            None => return Ranges::Ignore,
+        };
+        if !unit.trim().eq_ignore_ascii_case("bytes") {
+            return Ranges::Ignore;
+        }
+        let mut ranges = Vec::new();
+        let mut count = 0;
+        for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            count += 1;
+            if count > MAX_RANGES {
+                return Ranges::Ignore;
+            }
+            let (first, last) = match spec.split_once('-') {
+                Some(split) => split,
+                None => return Ranges::Ignore,
+            };
+            let (first, last) = (first.trim(), last.trim());
+            if first.is_empty() {
+                // a suffix range, the last bytes of the body
+                let suffix = match parse_digits(last) {
+                    Some(suffix) => suffix,
+                    None => return Ranges::Ignore,
+                };
+                if suffix > 0 && len > 0 {
+                    ranges.push((len.saturating_sub(suffix), len - 1));
+                }
+                continue;
+            }
+            let first = match parse_digits(first) {
+                Some(first) => first,
+                None => return Ranges::Ignore,
+            };
+            let last = if last.is_empty() {
+                u64::MAX
+            } else {
+                match parse_digits(last) {
+                    Some(last) if last >= first => last,
+                    _ => return Ranges::Ignore,
+                }
+            };
+            if first < len {
+                ranges.push((first, cmp::min(last, len - 1)));
+            }
+        }
+        if count == 0 {
+            Ranges::Ignore
+        } else if ranges.is_empty() {
+            Ranges::Unsatisfiable
+        } else {
+            Ranges::Satisfiable(ranges)
+        }
+    }
+}
+
+fn parse_digits(value: &str) -> Option<u64> {
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+/// Returns a boundary for a `multipart/byteranges` body
+pub(crate) fn boundary() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.subsec_nanos())
+        .unwrap_or(0);
+    let count = BOUNDARY_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("octane-{:08x}{:08x}", nanos, count)
+}
+
+enum Part {
+    Bytes(Cursor<Vec<u8>>),
+    Span { start: u64, remaining: u64 },
+}
+
+/// Reads the given ranges of a file, with the headers and
+/// delimiters of a `multipart/byteranges` body if there are
+/// several of them
+pub(crate) struct RangeReader {
+    file: File,
+    parts: VecDeque<Part>,
+    len: u64,
+}
+
+impl RangeReader {
+    pub(crate) fn single(file: File, (first, last): (u64, u64)) -> Self {
+        let mut parts = VecDeque::new();
+        parts.push_back(Part::Span {
+            start: first,
+            remaining: last - first + 1,
+        });
+        RangeReader {
+            file,
+            parts,
+            len: last - first + 1,
+        }
+    }
+
+    pub(crate) fn multipart(
+        file: File,
+        ranges: &[(u64, u64)],
+        file_len: u64,
+        content_type: &str,
+        boundary: &str,
+    ) -> Self {
+        let mut parts = VecDeque::new();
+        let mut len = 0;
+        for (index, (first, last)) in ranges.iter().enumerate() {
+            let head = format!(
+                "{}--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                if index == 0 { "" } else { "\r\n" },
+                boundary,
+                content_type,
+                first,
+                last,
+                file_len
+            );
+            len += head.len() as u64 + last - first + 1;
+            parts.push_back(Part::Bytes(Cursor::new(head.into_bytes())));
+            parts.push_back(Part::Span {
+                start: *first,
+                remaining: last - first + 1,
+            });
+        }
+        let tail = format!("\r\n--{}--\r\n", boundary);
+        len += tail.len() as u64;
+        parts.push_back(Part::Bytes(Cursor::new(tail.into_bytes())));
+        RangeReader { file, parts, len }
+    }
+    /// The number of bytes the reader yields
+    pub(crate) fn len(&self) -> u64 {
+        self.len
+    }
+}
+
+impl AsyncRead for RangeReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        while buf.remaining() > 0 {
+            let read = match this.parts.front_mut() {
+                None => break,
+                Some(Part::Bytes(cursor)) => cursor.read(buf.initialize_unfilled())?,
+                Some(Part::Span { start, remaining }) => {
+                    if *remaining == 0 {
+                        0
+                    } else {
+                        let max = cmp::min(*remaining, buf.remaining() as u64) as usize;
+                        this.file.seek(SeekFrom::Start(*start))?;
+                        let read = this.file.read(&mut buf.initialize_unfilled()[..max])?;
+                        if read == 0 {
+                            return Poll::Ready(Err(io::Error::new(
+                                io::ErrorKind::UnexpectedEof,
+                                "The file was truncated while it was sent",
+                            )));
+                        }
+                        *start += read as u64;
+                        *remaining -= read as u64;
+                        read
+                    }
+                }
+            };
+            if read == 0 {
+                this.parts.pop_front();
+                continue;
+            }
+            buf.advance(read);
+            break;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[test]
+    fn success_parse() {
+        let parse = |header| Ranges::parse(header, 100);
+        assert_eq!(parse("bytes=0-9"), Ranges::Satisfiable(vec![(0, 9)]));
+        assert_eq!(parse("bytes=90-"), Ranges::Satisfiable(vec![(90, 99)]));
+        assert_eq!(parse("bytes=-10"), Ranges::Satisfiable(vec![(90, 99)]));
+        assert_eq!(parse("bytes=-500"), Ranges::Satisfiable(vec![(0, 99)]));
+        assert_eq!(parse("bytes=95-200"), Ranges::Satisfiable(vec![(95, 99)]));
+        assert_eq!(
+            parse("Bytes=0-0, 200-300 ,-1"),
+            Ranges::Satisfiable(vec![(0, 0), (99, 99)])
+        );
+    }
+
+    #[test]
+    fn fail_parse() {
+        let parse = |header| Ranges::parse(header, 100);
+        assert_eq!(parse("bytes=100-"), Ranges::Unsatisfiable);
+        assert_eq!(parse("bytes=-0"), Ranges::Unsatisfiable);
+        assert_eq!(Ranges::parse("bytes=-5", 0), Ranges::Unsatisfiable);
+        assert_eq!(parse("bytes=9-0"), Ranges::Ignore);
+        assert_eq!(parse("bytes=+1-2"), Ranges::Ignore);
+        assert_eq!(parse("bytes="), Ranges::Ignore);
+        assert_eq!(parse("items=0-1"), Ranges::Ignore);
+        assert_eq!(
+            parse(&format!("bytes={}", "0-0,".repeat(33))),
+            Ranges::Ignore
+        );
+    }
+
+    #[crate::test]
+    async fn success_multipart() {
+        let path = std::env::temp_dir().join(format!("octane-range-{}", std::process::id()));
+        std::fs::write(&path, b"0123456789").unwrap();
+        let mut reader = RangeReader::single(File::open(&path).unwrap(), (2, 4));
+        let mut body = String::new();
+        reader.read_to_string(&mut body).await.unwrap();
+        assert_eq!(body, "234");
+
+        let ranges = [(0, 1), (8, 9)];
+        let mut reader =
+            RangeReader::multipart(File::open(&path).unwrap(), &ranges, 10, "text/plain", "B");
+        let len = reader.len();
+        let mut body = String::new();
+        reader.read_to_string(&mut body).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            body,
+            "--B\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/10\r\n\r\n01\
+             \r\n--B\r\nContent-Type: text/plain\r\nContent-Range: bytes 8-9/10\r\n\r\n89\
+             \r\n--B--\r\n"
+        );
+        assert_eq!(len, body.len() as u64);
+    }
+}
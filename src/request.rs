@@ -1,22 +1,35 @@
+use crate::compression::decode_body;
 use crate::constants::*;
 #[cfg(feature = "cookies")]
 use crate::cookie::Cookie;
 use crate::deref;
+use crate::json::FromJSON;
+use crate::multipart::{Multipart, MultipartParser};
 use crate::path::is_ctl;
 use crate::path::PathBuf;
-use crate::query::parse_query;
 #[cfg(feature = "extended_queries")]
 use crate::query::{parse_extended_query, QueryValue};
-use crate::util::Spliterator;
-use octane_http::HttpVersion;
+use crate::query::{parse_query, FormParser};
+use crate::util::{parse_params, Spliterator};
+use octane_http::http1x::chunked::ChunkedDecoder;
+pub use octane_http::HttpVersion;
+use octane_http::StatusCode;
+#[cfg(feature = "json")]
+use serde::Deserialize;
 use std::cfg;
 use std::collections::HashMap;
+use std::fmt;
 #[cfg(not(feature = "raw_headers"))]
 use std::marker::PhantomData;
 use std::str;
 use std::string::ToString;
+use std::sync::Arc;
 use tokio::io::ReadHalf;
 use tokio::prelude::*;
+use tokio::sync::Mutex;
+
+// The most a single read of a streamed body returns
+const STREAM_CHUNK_LEN: usize = 64 * 1024;
 
 /// Holds the type of request method, like GET,
 /// POST etc.
@@ -77,6 +90,8 @@ pub struct RequestLine {
     pub method: RequestMethod,
     /// The path of the request
     pub path: PathBuf,
+    /// The query string of the request target, without the `?`
+    pub query: Option<String>,
     /// Http version of the request
     pub version: HttpVersion,
 }
@@ -87,10 +102,13 @@ impl RequestLine {
     pub(crate) fn parse(request_line: &str) -> Option<Self> {
         let mut toks = request_line.split(SP);
         let method = toks.next()?;
-        let path = match PathBuf::parse(toks.next()?) {
-            Ok(val) => val,
-            Err(e) => panic!("{:?}", e),
+        let target = toks.next()?;
+        let (path, query) = match target.find('?') {
+            Some(i) => (&target[..i], Some(target[i + 1..].to_owned())),
+            None => (target, None),
         };
+        // a path going above the root is a bad request
+        let path = PathBuf::parse(path).ok()?;
         let version = toks.next()?;
         let (first, ver) = version.split_at(5);
         let enum_ver = match ver {
@@ -119,6 +137,7 @@ impl RequestLine {
         Some(Self {
             method: request_method,
             path,
+            query,
             version: enum_ver,
         })
     }
@@ -190,7 +209,7 @@ impl Header {
 ///     }),
 /// );
 /// ```
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
 pub struct Headers {
     /// Headers, serialized to a HashMap
     pub parsed: HashMap<String, String>,
@@ -228,6 +247,85 @@ impl Headers {
             raw: PhantomData,
         })
     }
+    // Merge the trailers of a chunked body, fields that control
+    // the framing or routing of the request can't be sent as trailers
+    // and are dropped
+    pub(crate) fn append_trailers(&mut self, trailers: Self) {
+        #[cfg(feature = "raw_headers")]
+        let mut trailers = trailers;
+        for (name, value) in trailers.parsed.iter() {
+            if FORBIDDEN_TRAILERS.contains(&name.as_str()) {
+                continue;
+            }
+            self.parsed
+                .entry(name.to_owned())
+                .and_modify(|v| *v = format!("{}, {}", v, value))
+                .or_insert_with(|| value.to_owned());
+        }
+        #[cfg(feature = "raw_headers")]
+        self.raw.extend(
+            trailers
+                .raw
+                .drain(..)
+                .filter(|h| !FORBIDDEN_TRAILERS.contains(&h.name.to_ascii_lowercase().as_str())),
+        );
+    }
+}
+
+// The way the body of a request is delimited
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum BodyFraming {
+    Chunked,
+    Sized(usize),
+}
+
+impl BodyFraming {
+    // Decide how the body is framed from the `Transfer-Encoding`
+    // and `Content-Length` headers, as described in RFC 7230, 3.3.3
+    pub(crate) fn detect(headers: &Headers, version: &HttpVersion) -> Result<Self, StatusCode> {
+        if let Some(encoding) = headers.get("transfer-encoding") {
+            // a message with both could be an attempt at request smuggling
+            if headers.get("content-length").is_some() {
+                return Err(StatusCode::BadRequest);
+            }
+            let codings: Vec<String> = encoding
+                .split(',')
+                .map(|c| c.trim().to_ascii_lowercase())
+                .filter(|c| !c.is_empty())
+                .collect();
+            return match codings.last().map(String::as_str) {
+                // the length can't be known if chunked isn't the last coding
+                Some("chunked") if codings.len() == 1 => {
+                    if *version == HttpVersion::Http10 {
+                        // http 1.0 clients can't send chunked bodies
+                        Err(StatusCode::LengthRequired)
+                    } else {
+                        Ok(BodyFraming::Chunked)
+                    }
+                }
+                Some("chunked") => Err(StatusCode::NotImplemented),
+                _ => Err(StatusCode::BadRequest),
+            };
+        }
+        match headers.get("content-length") {
+            Some(length) => {
+                // repeated headers are joined with commas, they must all agree
+                let mut lengths = length.split(',').map(str::trim);
+                let first = lengths.next().unwrap_or("");
+                if first.is_empty()
+                    || !first.bytes().all(|c| c.is_ascii_digit())
+                    || lengths.any(|l| l != first)
+                {
+                    return Err(StatusCode::BadRequest);
+                }
+                first
+                    .parse()
+                    .map(BodyFraming::Sized)
+                    .map_err(|_| StatusCode::BadRequest)
+            }
+            None => Ok(BodyFraming::Sized(0)),
+        }
+    }
 }
 
 /// Represents a single request
@@ -262,8 +360,13 @@ pub struct Request<'a> {
     pub request_line: RequestLine,
     /// Headers in the request
     pub headers: Headers,
-    /// The body of the request
+    /// The body of the request, it's empty on the routes that
+    /// stream their body
     pub body: &'a [u8],
+    /// The body of the request as it's read from the client, only
+    /// set on the routes that stream their body, see
+    /// [`Router::stream_body`](crate::router::Router::stream_body)
+    pub body_stream: Option<BodyStream>,
     #[cfg(feature = "cookies")]
     /// Cookies in the request
     pub cookies: Vec<Cookie<'a>>,
@@ -291,35 +394,120 @@ impl<'a> Request<'a> {
             #[cfg(feature = "cookies")]
             cookies,
             body,
+            body_stream: None,
         })
     }
+    // Reads the body according to the framing headers and returns the
+    // request, `pipelined` gets the bytes that were read past the body.
+    // A body with a `Content-Encoding` is decoded into `body_vec`, a
+    // body larger than `max_body_size` is refused before it's read
     pub(crate) async fn from_raw<T: AsyncRead + Unpin>(
         headers: &'a Headers,
         request_line: RequestLine,
-        mut body: &'a [u8],
         body_vec: &'a mut Vec<u8>,
         body_remainder: &'a [u8],
-        mut reader: ReadHalf<T>,
-    ) -> Option<Request<'a>> {
-        let body_len = headers
-            .get("content-length")
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(0);
-        if body_len > 0 {
-            if body_remainder.len() < body_len {
-                let mut temp: Vec<u8> = vec![0; body_len - body_remainder.len()];
-                reader.read_exact(&mut temp[..]).await.ok().unwrap();
-                *body_vec = Vec::with_capacity(body_len);
-                body_vec.extend_from_slice(body_remainder);
-                body_vec.extend_from_slice(&temp[..]);
-                body = &body_vec[..];
-            } else {
-                body = body_remainder;
+        pipelined: &mut Vec<u8>,
+        reader: &mut ReadHalf<T>,
+        max_body_size: Option<usize>,
+        max_decompressed_size: Option<usize>,
+    ) -> Result<Request<'a>, StatusCode> {
+        let mut trailers = String::new();
+        // the length of the body when it's still in the remainder,
+        // None when it was read into body_vec
+        let mut in_remainder = match BodyFraming::detect(headers, &request_line.version)? {
+            BodyFraming::Chunked => {
+                let chunked = ChunkedDecoder::new(reader, body_remainder)
+                    .max_len(max_body_size)
+                    .decode(body_vec)
+                    .await?;
+                *pipelined = chunked.surplus;
+                trailers = chunked.trailers;
+                None
             }
-        } else {
-            body = &[];
+            BodyFraming::Sized(body_len) => {
+                if max_body_size.map_or(false, |max| body_len > max) {
+                    return Err(StatusCode::PayloadTooLarge);
+                }
+                if body_remainder.len() < body_len {
+                    let mut temp: Vec<u8> = vec![0; body_len - body_remainder.len()];
+                    reader
+                        .read_exact(&mut temp[..])
+                        .await
+                        .map_err(|_| StatusCode::BadRequest)?;
+                    *body_vec = Vec::with_capacity(body_len);
+                    body_vec.extend_from_slice(body_remainder);
+                    body_vec.extend_from_slice(&temp[..]);
+                    None
+                } else {
+                    *pipelined = body_remainder[body_len..].to_vec();
+                    Some(body_len)
+                }
+            }
+        };
+        let mut decoded = false;
+        if let Some(content_encoding) = headers.get("content-encoding") {
+            let raw = match in_remainder {
+                Some(body_len) => &body_remainder[..body_len],
+                None => &body_vec[..],
+            };
+            if let Some(body) = decode_body(raw, content_encoding, max_decompressed_size).await? {
+                *body_vec = body;
+                in_remainder = None;
+                decoded = true;
+            }
+        }
+        let body: &[u8] = match in_remainder {
+            Some(body_len) => &body_remainder[..body_len],
+            None => &body_vec[..],
+        };
+        let mut request = Self::parse(request_line, headers, body).ok_or(StatusCode::BadRequest)?;
+        if decoded {
+            // the closures see the body as it is once decoded
+            request.headers.parsed.remove("content-encoding");
+            request
+                .headers
+                .parsed
+                .insert("content-length".to_owned(), body.len().to_string());
+        }
+        if !trailers.is_empty() {
+            let trailers = Headers::parse(trailers).ok_or(StatusCode::BadRequest)?;
+            request.headers.append_trailers(trailers);
         }
-        Self::parse(request_line, headers, body)
+        Ok(request)
+    }
+
+    // Returns a request whose body is left on the connection for the
+    // closures to stream. The body isn't decoded even if it has a
+    // `Content-Encoding`
+    pub(crate) fn from_stream(
+        headers: &'a Headers,
+        request_line: RequestLine,
+        body_remainder: &[u8],
+        reader: ConnReader,
+        max_body_size: Option<usize>,
+    ) -> Result<Request<'a>, StatusCode> {
+        let state = match BodyFraming::detect(headers, &request_line.version)? {
+            BodyFraming::Chunked => StreamState::Chunked(
+                ChunkedDecoder::new(reader, body_remainder).max_len(max_body_size),
+            ),
+            BodyFraming::Sized(body_len) => {
+                if max_body_size.map_or(false, |max| body_len > max) {
+                    return Err(StatusCode::PayloadTooLarge);
+                }
+                let buffered = body_remainder.len().min(body_len);
+                StreamState::Sized {
+                    buffered: body_remainder[..buffered].to_vec(),
+                    surplus: body_remainder[buffered..].to_vec(),
+                    reader,
+                    left: body_len - buffered,
+                }
+            }
+        };
+        let mut request = Self::parse(request_line, headers, &[]).ok_or(StatusCode::BadRequest)?;
+        request.body_stream = Some(BodyStream {
+            state: Arc::new(Mutex::new(state)),
+        });
+        Ok(request)
     }
 
     /// Parse the query and return the key value pairs in the form
@@ -336,17 +524,318 @@ impl<'a> Request<'a> {
     /// }));
     /// ```
     pub fn get_query(&self) -> HashMap<String, String> {
-        parse_query(&self.request_line.path.to_string())
+        parse_query(self.request_line.query.as_deref().unwrap_or(""))
+    }
+
+    /// Deserialize the json body of the request, the values can
+    /// borrow from the body. A request without an `application/json`
+    /// or `+json` content type gives a 415 and a body which isn't
+    /// valid json for `T` a 400. Requires the `json` feature
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    /// use std::collections::HashMap;
+    ///
+    /// let mut app = Octane::new();
+    ///
+    /// app.post("/", route!(|req, res| {
+    ///     match req.json::<HashMap<&str, u64>>() {
+    ///         Ok(counts) => res.send(format!("{} counters", counts.len())),
+    ///         Err(status) => res.status(status).send("Invalid json"),
+    ///     };
+    ///     Flow::Stop
+    /// }));
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn json<T: Deserialize<'a>>(&self) -> Result<T, StatusCode> {
+        self.check_json()?;
+        serde_json::from_slice(self.body).map_err(|_| StatusCode::BadRequest)
+    }
+
+    /// Parse the json body of the request with the dependency free
+    /// [`FromJSON`](crate::json::FromJSON) trait, with the same status
+    /// codes as [`json`](Request::json) on failure
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    ///
+    /// #[derive(FromJSON)]
+    /// struct Counter {
+    ///     name: String,
+    ///     count: u64,
+    /// }
+    ///
+    /// let mut app = Octane::new();
+    ///
+    /// app.post("/", route!(|req, res| {
+    ///     match req.parse_json::<Counter>() {
+    ///         Ok(counter) => res.send(format!("{} is at {}", counter.name, counter.count)),
+    ///         Err(status) => res.status(status).send("Invalid json"),
+    ///     };
+    ///     Flow::Stop
+    /// }));
+    /// ```
+    pub fn parse_json<T: FromJSON>(&self) -> Result<T, StatusCode> {
+        self.check_json()?;
+        let body = str::from_utf8(self.body).map_err(|_| StatusCode::BadRequest)?;
+        T::from_json_string(body).ok_or(StatusCode::BadRequest)
+    }
+
+    fn check_json(&self) -> Result<(), StatusCode> {
+        let content_type = self
+            .headers
+            .get("content-type")
+            .ok_or(StatusCode::UnsupportedMediaType)?;
+        let (kind, _) = parse_params(content_type);
+        let kind = kind.to_ascii_lowercase();
+        if kind != "application/json" && !kind.ends_with("+json") {
+            return Err(StatusCode::UnsupportedMediaType);
+        }
+        Ok(())
+    }
+
+    /// Parse an `application/x-www-form-urlencoded` body and return
+    /// the key value pairs in the form of an HashMap, with up to 1000
+    /// fields. See [`FormParser`](crate::query::FormParser) to change the limit
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    ///
+    /// let mut app = Octane::new();
+    ///
+    /// app.post("/", route_next!(|req, res| {
+    ///     if let Ok(form) = req.get_form() {
+    ///         let value = form.get("value");
+    ///     }
+    /// }));
+    /// ```
+    pub fn get_form(&self) -> Result<HashMap<String, String>, StatusCode> {
+        FormParser::new().parse(self)
+    }
+
+    /// Parse an `application/x-www-form-urlencoded` body with the
+    /// nested fields of an extended query. See [`QueryValue`](../enum.QueryValue.html)
+    #[cfg(feature = "extended_queries")]
+    pub fn get_extended_form(&self) -> Result<HashMap<String, QueryValue>, StatusCode> {
+        FormParser::new().parse_extended(self)
+    }
+
+    /// Parse a `multipart/form-data` body without limits, keeping every
+    /// part in memory. See [`MultipartParser`](crate::multipart::MultipartParser)
+    /// to set limits
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    ///
+    /// let mut app = Octane::new();
+    ///
+    /// app.post("/", route_next!(|req, res| {
+    ///     if let Ok(form) = req.multipart() {
+    ///         let name = form.field("name");
+    ///     }
+    /// }));
+    /// ```
+    pub fn multipart(&self) -> Result<Multipart<'a>, StatusCode> {
+        MultipartParser::new().parse(self)
     }
 
     /// Parse the extended query and return the key value pairs in the form
     /// of an HashMap. See [`QueryValue`](../enum.QueryValue.html)
     #[cfg(feature = "extended_queries")]
     pub fn get_extended_query(&self) -> HashMap<String, QueryValue> {
-        parse_extended_query(&self.request_line.path.to_string())
+        parse_extended_query(self.request_line.query.as_deref().unwrap_or(""))
     }
 }
 
+/// A connection the server reads requests from
+pub(crate) trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+// The read half of a connection, the type of the stream is erased
+// so that a streamed body can own it without naming it
+pub(crate) type ConnReader = ReadHalf<Box<dyn Connection>>;
+
+enum StreamState {
+    Sized {
+        // the part of the body read with the request head
+        buffered: Vec<u8>,
+        // bytes read with the head past the body
+        surplus: Vec<u8>,
+        reader: ConnReader,
+        // bytes still to be read from the connection
+        left: usize,
+    },
+    Chunked(ChunkedDecoder<ConnReader>),
+    Done {
+        trailers: Option<Headers>,
+        // the reader and the bytes read past the body, until the
+        // server takes them back
+        rest: Option<(ConnReader, Vec<u8>)>,
+    },
+    Failed(StatusCode),
+}
+
+/// The body of a request read piece by piece from the connection, so
+/// that large uploads never have to be held in memory. The body is
+/// sent as the client encoded it, the `Content-Encoding` isn't undone
+///
+/// # Example
+///
+/// ```no_run
+/// use octane::prelude::*;
+/// use octane::request::RequestMethod;
+/// use std::fs::File;
+/// use std::io::Write;
+///
+/// let mut app = Octane::new();
+/// app.stream_body(RequestMethod::Post, "/upload").unwrap();
+/// app.post(
+///     "/upload",
+///     async_route!(|req, res| {
+///         let stream = req.body_stream.as_ref().unwrap();
+///         let mut file = File::create("upload.bin").unwrap();
+///         loop {
+///             match stream.chunk().await {
+///                 Ok(Some(chunk)) => file.write_all(&chunk).unwrap(),
+///                 Ok(None) => break,
+///                 Err(status) => {
+///                     res.with_type("text/plain").status(status).send("Bad upload");
+///                     return Flow::Stop;
+///                 }
+///             }
+///         }
+///         res.send("Uploaded");
+///         Flow::Stop
+///     }),
+/// )
+/// .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct BodyStream {
+    state: Arc<Mutex<StreamState>>,
+}
+
+impl BodyStream {
+    /// Reads the next piece of the body, None once all of it was
+    /// read. A body growing past the
+    /// [`max_body_size`](crate::config::OctaneConfig::max_body_size)
+    /// gives a 413 and a malformed or truncated one a 400, the
+    /// error is returned again on the following calls
+    pub async fn chunk(&self) -> Result<Option<Vec<u8>>, StatusCode> {
+        let mut state = self.state.lock().await;
+        let result = Self::next_chunk(&mut state).await;
+        if let Err(status) = result {
+            *state = StreamState::Failed(status);
+        }
+        result
+    }
+    async fn next_chunk(state: &mut StreamState) -> Result<Option<Vec<u8>>, StatusCode> {
+        match state {
+            StreamState::Sized {
+                buffered,
+                reader,
+                left,
+                ..
+            } => {
+                if !buffered.is_empty() {
+                    return Ok(Some(std::mem::take(buffered)));
+                }
+                if *left == 0 {
+                    return Ok(None);
+                }
+                let mut chunk = vec![0; (*left).min(STREAM_CHUNK_LEN)];
+                return match reader.read(&mut chunk).await {
+                    // the client closed the connection mid body
+                    Ok(0) | Err(_) => Err(StatusCode::BadRequest),
+                    Ok(read) => {
+                        *left -= read;
+                        chunk.truncate(read);
+                        Ok(Some(chunk))
+                    }
+                };
+            }
+            StreamState::Chunked(decoder) => {
+                let mut chunk = Vec::new();
+                if decoder.decode_chunk(&mut chunk).await? {
+                    return Ok(Some(chunk));
+                }
+            }
+            StreamState::Done { .. } => return Ok(None),
+            StreamState::Failed(status) => return Err(*status),
+        }
+        // the last chunk was read, keep the trailers and what follows it
+        let failed = StreamState::Failed(StatusCode::BadRequest);
+        if let StreamState::Chunked(mut decoder) = std::mem::replace(state, failed) {
+            let body = decoder.finish()?;
+            let trailers = if body.trailers.is_empty() {
+                None
+            } else {
+                Some(Headers::parse(body.trailers).ok_or(StatusCode::BadRequest)?)
+            };
+            *state = StreamState::Done {
+                trailers,
+                rest: Some((decoder.into_inner(), body.surplus)),
+            };
+        }
+        Ok(None)
+    }
+    /// Reads the rest of the body into memory
+    pub async fn read_to_end(&self) -> Result<Vec<u8>, StatusCode> {
+        let mut body = Vec::new();
+        while let Some(chunk) = self.chunk().await? {
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    }
+    /// Returns the trailers of a chunked body once all of it was
+    /// read, None if there were none
+    pub async fn trailers(&self) -> Option<Headers> {
+        match &*self.state.lock().await {
+            StreamState::Done { trailers, .. } => trailers.clone(),
+            _ => None,
+        }
+    }
+    // Gives the connection back to the server along with the bytes
+    // read past the body once the closures are done with it, None if
+    // the body wasn't read to its end and the connection can't be
+    // used for another request
+    pub(crate) async fn finish(&self) -> Option<(ConnReader, Vec<u8>)> {
+        let mut state = self.state.lock().await;
+        match &mut *state {
+            StreamState::Done { rest, .. } => rest.take(),
+            StreamState::Sized { left: 0, .. } => {
+                let done = StreamState::Done {
+                    trailers: None,
+                    rest: None,
+                };
+                match std::mem::replace(&mut *state, done) {
+                    StreamState::Sized {
+                        reader, surplus, ..
+                    } => Some((reader, surplus)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Debug for BodyStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BodyStream").finish()
+    }
+}
+
+// Two streams are equal when they read the same body
+impl PartialEq for BodyStream {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+}
+
+impl Eq for BodyStream {}
+
 /// The KeepAlive struct represents the value
 /// parsed in the KeepAlive header. It holds the
 /// timeout and max duration as a u64, (only http 1.0 and below)
@@ -388,7 +877,6 @@ impl KeepAlive {
     pub fn timeout(&self) -> Option<u64> {
         self.timeout
     }
-    #[allow(dead_code)]
     // Returns the amount of max requests specified in the keep alive header
     pub fn max(&self) -> Option<u64> {
         self.max
@@ -450,6 +938,17 @@ deref!(Headers, HashMap<String, String>, parsed);
 mod test {
     use super::*;
 
+    #[test]
+    fn success_request_line_query() {
+        // The query is split from the path and doesn't change routing
+        let req = RequestLine::parse("GET /abc/def?a=1&b=/x/y? HTTP/1.1").unwrap();
+        assert_eq!(req.path, PathBuf::parse("/abc/def").ok().unwrap());
+        assert_eq!(req.query, Some("a=1&b=/x/y?".to_owned()));
+        let req = RequestLine::parse("GET /abc? HTTP/1.1").unwrap();
+        assert_eq!(req.query, Some("".to_owned()));
+        assert_eq!(RequestLine::parse("GET /abc HTTP/1.1").unwrap().query, None);
+    }
+
     #[test]
     fn success_standard_request_line() {
         // Parsing should work as expected.
@@ -586,6 +1085,84 @@ mod test {
         Header::parse("Test Header: test".to_string()).unwrap();
     }
 
+    #[test]
+    fn success_body_framing() {
+        // Framing should follow the transfer-encoding and content-length headers.
+        let framing = |headers: &str, version| {
+            BodyFraming::detect(&Headers::parse(headers.to_string()).unwrap(), &version)
+        };
+        assert_eq!(
+            framing("Content-Length: 20", HttpVersion::Http11),
+            Ok(BodyFraming::Sized(20))
+        );
+        assert_eq!(
+            framing(
+                "Content-Length: 20\r\nContent-Length: 20",
+                HttpVersion::Http11
+            ),
+            Ok(BodyFraming::Sized(20))
+        );
+        assert_eq!(
+            framing("Host: localhost", HttpVersion::Http11),
+            Ok(BodyFraming::Sized(0))
+        );
+        assert_eq!(
+            framing("Transfer-Encoding: Chunked", HttpVersion::Http11),
+            Ok(BodyFraming::Chunked)
+        );
+    }
+
+    #[test]
+    fn fail_body_framing() {
+        // Invalid or ambiguous framing should error with the right status.
+        let framing = |headers: &str, version| {
+            BodyFraming::detect(&Headers::parse(headers.to_string()).unwrap(), &version)
+        };
+        assert_eq!(
+            framing(
+                "Content-Length: 20\r\nContent-Length: 2",
+                HttpVersion::Http11
+            ),
+            Err(StatusCode::BadRequest)
+        );
+        assert_eq!(
+            framing("Content-Length: -1", HttpVersion::Http11),
+            Err(StatusCode::BadRequest)
+        );
+        assert_eq!(
+            framing(
+                "Content-Length: 5\r\nTransfer-Encoding: chunked",
+                HttpVersion::Http11
+            ),
+            Err(StatusCode::BadRequest)
+        );
+        assert_eq!(
+            framing("Transfer-Encoding: chunked, gzip", HttpVersion::Http11),
+            Err(StatusCode::BadRequest)
+        );
+        assert_eq!(
+            framing("Transfer-Encoding: gzip, chunked", HttpVersion::Http11),
+            Err(StatusCode::NotImplemented)
+        );
+        assert_eq!(
+            framing("Transfer-Encoding: chunked", HttpVersion::Http10),
+            Err(StatusCode::LengthRequired)
+        );
+    }
+
+    #[test]
+    fn success_trailers() {
+        // Trailers should be merged, except for forbidden ones.
+        let mut headers = Headers::parse("Host: localhost".to_string()).unwrap();
+        headers.append_trailers(
+            Headers::parse("X-Checksum: abc\r\nHost: evil\r\nContent-Length: 2".to_string())
+                .unwrap(),
+        );
+        assert_eq!(headers.get("x-checksum").unwrap(), "abc");
+        assert_eq!(headers.get("host").unwrap(), "localhost");
+        assert!(headers.get("content-length").is_none());
+    }
+
     #[test]
     fn success_keepalive() {
         // Parsing should work as expected.
@@ -601,4 +1178,226 @@ mod test {
         assert_eq!(req.timeout(), Some(5));
         assert_eq!(req.max(), None);
     }
+
+    async fn stream(
+        head: &str,
+        buffered: &[u8],
+        rest: &'static [u8],
+        max: Option<usize>,
+    ) -> Result<BodyStream, StatusCode> {
+        let (client, server) = tokio::io::duplex(1024);
+        let server: Box<dyn Connection> = Box::new(server);
+        let (reader, _writer) = tokio::io::split(server);
+        let (_, mut client) = tokio::io::split(client);
+        client.write_all(rest).await.unwrap();
+        drop(client);
+        let headers = Headers::parse(head.to_string()).unwrap();
+        let request_line = RequestLine::parse("POST /upload HTTP/1.1").unwrap();
+        let request = Request::from_stream(&headers, request_line, buffered, reader, max)?;
+        assert!(request.body.is_empty());
+        Ok(request.body_stream.unwrap())
+    }
+
+    #[crate::test]
+    async fn success_body_stream() {
+        // Sized bodies should be streamed from the buffer, then the connection.
+        let body = stream("Content-Length: 10", b"01234", b"56789", None)
+            .await
+            .unwrap();
+        assert!(body.finish().await.is_none());
+        assert_eq!(body.chunk().await, Ok(Some(b"01234".to_vec())));
+        assert_eq!(body.chunk().await, Ok(Some(b"56789".to_vec())));
+        assert_eq!(body.chunk().await, Ok(None));
+        let (_, surplus) = body.finish().await.unwrap();
+        assert!(surplus.is_empty());
+        assert_eq!(body.chunk().await, Ok(None));
+
+        // Surplus bytes of a buffered body should go to the next request.
+        let body = stream("Content-Length: 3", b"abcGET", b"", None)
+            .await
+            .unwrap();
+        assert_eq!(body.read_to_end().await, Ok(b"abc".to_vec()));
+        let (_, surplus) = body.finish().await.unwrap();
+        assert_eq!(surplus, b"GET");
+
+        // Chunked bodies should keep their trailers and surplus.
+        let body = stream(
+            "Transfer-Encoding: chunked",
+            b"5\r\nhel",
+            b"lo\r\n0\r\nX-Sum: 1\r\n\r\nGET",
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(body.read_to_end().await, Ok(b"hello".to_vec()));
+        assert_eq!(body.trailers().await.unwrap().get("x-sum").unwrap(), "1");
+        let (_, surplus) = body.finish().await.unwrap();
+        assert_eq!(surplus, b"GET");
+    }
+
+    #[crate::test]
+    async fn fail_body_stream() {
+        // Oversized bodies should be refused, truncated ones should error.
+        assert_eq!(
+            stream("Content-Length: 10", b"", b"", Some(5)).await.err(),
+            Some(StatusCode::PayloadTooLarge)
+        );
+        let body = stream("Content-Length: 10", b"", b"01234", None)
+            .await
+            .unwrap();
+        assert_eq!(body.read_to_end().await, Err(StatusCode::BadRequest));
+        assert_eq!(body.chunk().await, Err(StatusCode::BadRequest));
+        assert!(body.finish().await.is_none());
+        let body = stream(
+            "Transfer-Encoding: chunked",
+            b"5\r\nhello\r\nFF\r\n",
+            b"",
+            Some(16),
+        )
+        .await
+        .unwrap();
+        assert_eq!(body.chunk().await, Ok(Some(b"hello".to_vec())));
+        assert_eq!(body.chunk().await, Err(StatusCode::PayloadTooLarge));
+    }
+
+    #[derive(Debug, PartialEq, octane_macros::FromJSON, octane_macros::ToJSON)]
+    enum Event<T> {
+        Ping,
+        #[json(rename = "msg")]
+        Message(String),
+        Move(i32, i32),
+        Batch {
+            ids: HashMap<String, T>,
+            #[json(skip)]
+            seen: bool,
+        },
+    }
+
+    #[derive(Debug, PartialEq, octane_macros::FromJSON, octane_macros::ToJSON)]
+    struct Payload<T>
+    where
+        T: Clone,
+    {
+        pub r#type: String,
+        #[json(rename = "userId")]
+        user_id: u64,
+        note: Option<String>,
+        events: Vec<Event<T>>,
+        #[json(skip)]
+        cached: Option<T>,
+        pair: Pair,
+        unit: Unit,
+    }
+
+    #[derive(Debug, PartialEq, octane_macros::FromJSON, octane_macros::ToJSON)]
+    struct Pair(pub u8, #[json(skip)] bool, String);
+
+    #[derive(Debug, PartialEq, octane_macros::FromJSON, octane_macros::ToJSON)]
+    struct Unit;
+
+    #[test]
+    fn success_parse_json() {
+        use crate::json::ToJSON;
+
+        let body = br#"{"type": "sync", "userId": 7, "pair": [1, "a"], "unit": null, "events": [
+            "Ping", {"msg": "hi"}, {"Move": [-1, 2]}, {"Batch": {"ids": {"a": 3}}}
+        ]}"#;
+        let headers = Headers::parse("Content-Type: application/json".to_owned()).unwrap();
+        let request_line = RequestLine::parse("POST / HTTP/1.1").unwrap();
+        let request = Request::parse(request_line, &headers, body).unwrap();
+        let payload = request.parse_json::<Payload<u32>>().unwrap();
+        let mut ids = HashMap::new();
+        ids.insert("a".to_owned(), 3);
+        let expected = Payload {
+            r#type: "sync".to_owned(),
+            user_id: 7,
+            note: None,
+            events: vec![
+                Event::Ping,
+                Event::Message("hi".to_owned()),
+                Event::Move(-1, 2),
+                Event::Batch { ids, seen: false },
+            ],
+            cached: None,
+            pair: Pair(1, false, "a".to_owned()),
+            unit: Unit,
+        };
+        assert_eq!(payload, expected);
+        assert_eq!(
+            payload.to_json_string().unwrap(),
+            r#"{"events":["Ping",{"msg":"hi"},{"Move":[-1,2]},{"Batch":{"ids":{"a":3}}}],"note":null,"pair":[1,"a"],"type":"sync","unit":null,"userId":7}"#
+        );
+        assert_eq!(
+            crate::json::FromJSON::from_json_string(&payload.to_json_string().unwrap()),
+            Some(expected)
+        );
+    }
+
+    #[test]
+    fn fail_parse_json() {
+        let request = |content_type: &str, body: &'static [u8]| {
+            let headers = Headers::parse(format!("Content-Type: {}", content_type)).unwrap();
+            let request_line = RequestLine::parse("POST / HTTP/1.1").unwrap();
+            let request = Request::parse(request_line, &headers, body).unwrap();
+            request.parse_json::<Pair>()
+        };
+        assert!(request("application/json", b"[1, \"a\"]").is_ok());
+        assert_eq!(
+            request("text/plain", b"[1, \"a\"]"),
+            Err(StatusCode::UnsupportedMediaType)
+        );
+        // too many elements, unknown variants and invalid utf-8
+        assert_eq!(
+            request("application/json", b"[1, \"a\", 2]"),
+            Err(StatusCode::BadRequest)
+        );
+        assert_eq!(
+            request("application/json", b"[1, \"\xff\"]"),
+            Err(StatusCode::BadRequest)
+        );
+        let headers = Headers::parse("Content-Type: application/json".to_owned()).unwrap();
+        let request_line = RequestLine::parse("POST / HTTP/1.1").unwrap();
+        for body in [
+            &b"\"Pong\""[..],
+            b"{\"Ping\": null}",
+            b"{\"msg\": \"a\", \"Ping\": 1}",
+            b"{\"Move\": [1]}",
+        ]
+        .iter()
+        {
+            let request = Request::parse(request_line.clone(), &headers, body).unwrap();
+            assert_eq!(
+                request.parse_json::<Event<u8>>(),
+                Err(StatusCode::BadRequest)
+            );
+        }
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn success_json() {
+        let request = |content_type: &str, body: &'static [u8]| {
+            let headers = Headers::parse(format!("Content-Type: {}", content_type)).unwrap();
+            let request_line = RequestLine::parse("POST / HTTP/1.1").unwrap();
+            let request = Request::parse(request_line, &headers, body).unwrap();
+            request.json::<HashMap<String, Vec<u32>>>()
+        };
+        let parsed = request("application/json; charset=utf-8", b"{\"ids\": [1, 2]}").unwrap();
+        assert_eq!(parsed["ids"], vec![1, 2]);
+        assert!(request("application/problem+json", b"{}")
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            request("text/plain", b"{}"),
+            Err(StatusCode::UnsupportedMediaType)
+        );
+        assert_eq!(
+            request("application/json", b"{\"ids\": 1}"),
+            Err(StatusCode::BadRequest)
+        );
+        assert_eq!(
+            request("application/json", b""),
+            Err(StatusCode::BadRequest)
+        );
+    }
 }
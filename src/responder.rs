@@ -1,30 +1,46 @@
+#[cfg(feature = "compression")]
+use crate::compression::{Encoding, MIN_COMPRESS_LEN};
+use crate::config::EntityTag;
 use crate::constants::*;
 #[cfg(feature = "cookies")]
 use crate::cookie::Cookie;
 use crate::file_handler::FileHandler;
-use crate::time::Time;
+use crate::json::ToJSON;
+use crate::range::{self, RangeReader, Ranges};
+use crate::request::{Request, RequestMethod};
+use crate::time::{self, HttpDate};
+use crate::util::AsyncReader;
+use octane_http::http1x::chunked::ChunkedEncoder;
 use octane_http::{HttpVersion, StatusCode};
+#[cfg(feature = "json")]
+use serde::Serialize;
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
+use std::fs::File;
 use std::io::Cursor;
 use std::marker::PhantomData;
 use std::path::PathBuf;
+use std::time::SystemTime;
 use tokio::io::AsyncRead;
+use tokio::task;
 
 pub(crate) type BoxReader = Box<dyn AsyncRead + Unpin + Send>;
 
 pub(crate) enum ResBody {
     None,
-    Sized(usize, BoxReader),
+    Sized(BoxReader),
     Unsized(BoxReader),
+    // kept seekable so that ranges of it can be sent
+    File(usize, File),
 }
 
 impl ResBody {
     pub fn get_reader(self) -> BoxReader {
         match self {
-            ResBody::Sized(_, reader) => reader,
+            ResBody::Sized(reader) => reader,
             ResBody::Unsized(reader) => reader,
+            ResBody::File(_, file) => Box::new(AsyncReader::new(file)) as BoxReader,
             ResBody::None => Box::new(Cursor::new(Vec::new())) as BoxReader,
         }
     }
@@ -66,9 +82,36 @@ pub struct Response<'a> {
     /// Cookies that will be sent with the response
     #[cfg(feature = "cookies")]
     pub cookies: Vec<Cookie<'a>>,
+    pub(crate) entity_tag: EntityTag,
+    // a file sent without its strong tag, which is hashed after the
+    // closures, with the size and modification time it was sent with
+    pending_tag: Option<(PathBuf, u64, Option<SystemTime>)>,
     marker: PhantomData<&'a ()>,
 }
 
+// Checks an If-Match or If-None-Match list against the entity tag
+// of the response, the strong comparison never matches a weak tag
+fn etag_matches(tags: &str, etag: Option<&str>, strong: bool) -> bool {
+    if tags.trim() == "*" {
+        return true;
+    }
+    let etag = match etag {
+        Some(etag) => etag,
+        None => return false,
+    };
+    if strong && etag.starts_with("W/") {
+        return false;
+    }
+    let opaque = etag.trim_start_matches("W/");
+    tags.split(',').map(str::trim).any(|tag| {
+        if strong {
+            tag == etag
+        } else {
+            tag.trim_start_matches("W/") == opaque
+        }
+    })
+}
+
 impl<'a> Response<'a> {
     /// Adds appends a custom header with the headers
     /// that will be sent.
@@ -137,8 +180,7 @@ impl<'a> Response<'a> {
     /// ```
     pub fn send<T: AsRef<[u8]>>(&mut self, body: T) {
         let body_slice = body.as_ref();
-        let len = body_slice.len();
-        self.body = ResBody::Sized(len, Box::new(Cursor::new(body_slice.to_vec())) as BoxReader);
+        self.body = ResBody::Sized(Box::new(Cursor::new(body_slice.to_vec())) as BoxReader);
         self.content_len = Some(body_slice.len());
         self.default_headers();
     }
@@ -150,9 +192,7 @@ impl<'a> Response<'a> {
             self.headers
                 .insert("Content-Length".to_string(), x.to_string());
         }
-        if let Some(date) = Time::now() {
-            self.headers.insert("Date".to_string(), date.format());
-        }
+        self.headers.insert("Date".to_string(), time::date_header());
         if self.headers.get("Content-Type").is_none() {
             let mut format = String::from("text/html");
             if let Some(charset) = &self.charset {
@@ -199,6 +239,15 @@ impl<'a> Response<'a> {
     /// Send a file as the response, automatically detect the
     /// mime type and set the headers accordingly
     ///
+    /// The `ETag` and `Last-Modified` validators of the file are
+    /// sent too, the kind of `ETag` is chosen with
+    /// [`entity_tag`](crate::config::OctaneConfig::entity_tag). The
+    /// conditional headers of the request are evaluated against
+    /// them once the closures have run, answering with
+    /// `304 Not Modified` or `412 Precondition Failed`. A strong
+    /// `ETag` that isn't cached yet is only hashed then, away from
+    /// the workers, so it isn't in the headers right after this call
+    ///
     /// # Example
     ///
     /// ```
@@ -219,51 +268,175 @@ impl<'a> Response<'a> {
     ///
     /// ```
     pub fn send_file(&mut self, file: &str) -> Result<Option<()>, Box<dyn Error>> {
-        let file = FileHandler::handle_file(&PathBuf::from(file))?;
+        let path = PathBuf::from(file);
+        let mut file = FileHandler::handle_file(&path)?;
+        if self.entity_tag != EntityTag::Strong {
+            let tag = file.entity_tag(self.entity_tag)?;
+            self.send_opened_file(file, tag);
+            return Ok(Some(()));
+        }
+        // hashing the file blocks, it's left for later unless cached
+        let tag = file.cached_tag();
+        let pending = match tag {
+            Some(_) => None,
+            None => Some((path, file.meta.len(), file.meta.modified().ok())),
+        };
+        self.send_opened_file(file, tag);
+        self.pending_tag = pending;
+        Ok(Some(()))
+    }
+    // Hashes the strong tag left by send_file on the blocking threads,
+    // no tag is sent if the file changed in the meantime or if the
+    // closures replaced the file
+    pub(crate) async fn resolve_entity_tag(&mut self) {
+        let (path, len, modified) = match self.pending_tag.take() {
+            Some(pending) if matches!(self.body, ResBody::File(..)) => pending,
+            _ => return,
+        };
+        let tag = task::spawn_blocking(move || {
+            let mut file = FileHandler::handle_file(&path).ok()?;
+            if file.meta.len() != len || file.meta.modified().ok() != modified {
+                return None;
+            }
+            file.entity_tag(EntityTag::Strong).ok().flatten()
+        })
+        .await;
+        if let Ok(Some(tag)) = tag {
+            self.set("ETag", &tag);
+        }
+    }
+    // Sends a file which was opened and tagged already, so the
+    // blocking part of sending a file can run elsewhere
+    pub(crate) fn send_opened_file(&mut self, file: FileHandler, tag: Option<String>) {
+        self.pending_tag = None;
+        if let Some(tag) = tag {
+            self.set("ETag", &tag);
+        }
+        if let Some(modified) = file.last_modified() {
+            self.set("Last-Modified", &modified);
+        }
         self.headers.insert(
             "Content-Type".to_string(),
             FileHandler::mime_type(file.extension),
         );
         let len = file.meta.len() as usize;
         self.content_len = Some(len);
-        self.body = ResBody::Sized(len, Box::new(file.file) as BoxReader);
-        Ok(Some(()))
+        self.body = ResBody::File(len, file.file.reader);
+        self.set("Accept-Ranges", "bytes");
+        self.default_headers();
     }
 
-    /// Converts the structure to a json string and sends
-    /// it as the response with the mime type `application/json`.
-    /// The structure which will be passed should implement
-    /// `ToJSON` from `octane_macros::convert`
-    ///
-    /// TODO: add a example here with a struct that implements
-    /// ToJSON and then do res.json(structure)
+    /// Streams the data read from the given reader as the
+    /// response body. The length of the body doesn't have to be
+    /// known beforehand, it is sent with the chunked transfer
+    /// coding to http 1.1 clients, the connection is closed after
+    /// the body for older clients
     ///
     /// # Example
     ///
     /// ```
     /// use octane::prelude::*;
-    /// use std::path::PathBuf;
+    /// use std::io::Cursor;
     ///
     /// let mut app = Octane::new();
     /// app.get(
     ///     "/",
     ///     route!(
     ///         |req, res| {
-    ///             // add example here
-    ///             // assert_eq!(res.get("Content-Type"),  Some(&"application/json".to_owned()));
+    ///             res.stream(Cursor::new(b"a generated export".to_vec()));
     ///             Flow::Stop
     ///         }
     ///     ),
     /// );
     ///
     /// ```
-    #[cfg(feature = "json")]
-    pub fn json<T: Serialize>(&mut self, structure: T) -> serde_json::Result<()> {
-        self.body =
-            ResBody::Unsized(Box::new(Cursor::new(serde_json::to_vec(structure))) as BoxReader);
-        self.with_type("application/json");
+    pub fn stream<R>(&mut self, reader: R)
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        self.body = ResBody::Unsized(Box::new(reader) as BoxReader);
+        self.content_len = None;
+        self.headers.remove("Content-Length");
         self.default_headers();
     }
+    /// Serializes the value to json and sends it as the response
+    /// with the mime type `application/json`. Nothing is sent if
+    /// the value can't be serialized, like a map with keys which
+    /// aren't strings. Requires the `json` feature
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    /// use std::collections::HashMap;
+    ///
+    /// let mut app = Octane::new();
+    /// app.get(
+    ///     "/",
+    ///     route!(
+    ///         |req, res| {
+    ///             let mut server = HashMap::new();
+    ///             server.insert("name", "Octane");
+    ///             res.json(&server).unwrap();
+    ///             Flow::Stop
+    ///         }
+    ///     ),
+    /// );
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn json<T: Serialize + ?Sized>(&mut self, value: &T) -> serde_json::Result<()> {
+        let body = serde_json::to_vec(value)?;
+        self.with_type("application/json").send(body);
+        Ok(())
+    }
+    /// Same as [`json`](Response::json) but the json is indented,
+    /// which is easier to read while debugging
+    #[cfg(feature = "json")]
+    pub fn json_pretty<T: Serialize + ?Sized>(&mut self, value: &T) -> serde_json::Result<()> {
+        let body = serde_json::to_vec_pretty(value)?;
+        self.with_type("application/json").send(body);
+        Ok(())
+    }
+    /// Converts the value with the dependency free [`ToJSON`](crate::json::ToJSON)
+    /// trait and sends it as the response with the mime type
+    /// `application/json`. Nothing is sent if the value has no json
+    /// representation, like a `NaN` float
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    ///
+    /// #[derive(ToJSON)]
+    /// struct Server {
+    ///     name: String,
+    ///     #[json(rename = "maxConnections")]
+    ///     max_connections: u32,
+    /// }
+    ///
+    /// let mut app = Octane::new();
+    /// app.get(
+    ///     "/",
+    ///     route!(
+    ///         |req, res| {
+    ///             let server = Server { name: "Octane".to_owned(), max_connections: 1024 };
+    ///             res.send_json(&server).unwrap();
+    ///             Flow::Stop
+    ///         }
+    ///     ),
+    /// );
+    /// ```
+    pub fn send_json<T: ToJSON + ?Sized>(&mut self, value: &T) -> Option<()> {
+        let body = value.to_json_string()?;
+        self.with_type("application/json").send(body);
+        Some(())
+    }
+    /// Same as [`send_json`](Response::send_json) but the json is indented
+    pub fn send_json_pretty<T: ToJSON + ?Sized>(&mut self, value: &T) -> Option<()> {
+        let body = value.to_json_string_pretty()?;
+        self.with_type("application/json").send(body);
+        Some(())
+    }
     /// Set the status code from the status code enum
     ///
     /// # Example
@@ -433,6 +606,205 @@ impl<'a> Response<'a> {
     pub(crate) fn has_body(&self) -> bool {
         self.body.is_some()
     }
+    // Frames an unsized body with the chunked transfer coding if the
+    // client supports it, returns false if the end of the body can
+    // only be told by closing the connection
+    pub(crate) fn frame_body(&mut self, version: &HttpVersion) -> bool {
+        if !matches!(self.body, ResBody::Unsized(_)) {
+            return true;
+        }
+        if *version != HttpVersion::Http11 {
+            return false;
+        }
+        if let ResBody::Unsized(reader) = std::mem::replace(&mut self.body, ResBody::None) {
+            self.body = ResBody::Unsized(Box::new(ChunkedEncoder::new(reader)) as BoxReader);
+        }
+        self.set("Transfer-Encoding", "chunked");
+        true
+    }
+    // Evaluates the conditional headers of the request against the
+    // validators of a successful response, in the order of RFC 7232
+    // section 6, the body is dropped when a condition fails
+    pub(crate) fn apply_conditions(&mut self, request: &Request) {
+        if !(200..300).contains(&i32::from(self.status_code)) {
+            return;
+        }
+        let headers = &request.headers;
+        let method = &request.request_line.method;
+        let safe = *method == RequestMethod::Get || *method == RequestMethod::Head;
+        let etag = self.headers.get("ETag").map(String::as_str);
+        let modified = self
+            .headers
+            .get("Last-Modified")
+            .and_then(|date| HttpDate::parse(date));
+        let since = |name| headers.get(name).and_then(|date| HttpDate::parse(date));
+        let failed = if let Some(tags) = headers.get("if-match") {
+            !etag_matches(tags, etag, true)
+        } else if let (Some(since), Some(modified)) = (since("if-unmodified-since"), modified) {
+            modified > since
+        } else {
+            false
+        };
+        let status = if failed {
+            StatusCode::PreconditionFailed
+        } else if let Some(tags) = headers.get("if-none-match") {
+            if !etag_matches(tags, etag, false) {
+                return;
+            } else if safe {
+                StatusCode::NotModified
+            } else {
+                StatusCode::PreconditionFailed
+            }
+        } else if let (true, Some(since), Some(modified)) =
+            (safe, since("if-modified-since"), modified)
+        {
+            if modified > since {
+                return;
+            }
+            StatusCode::NotModified
+        } else {
+            return;
+        };
+        self.status(status);
+        if status == StatusCode::NotModified {
+            // a 304 has no body and only repeats the validators
+            // and caching headers of the full response
+            self.body = ResBody::None;
+            self.content_len = None;
+            self.headers.remove("Content-Length");
+            self.headers.remove("Content-Type");
+        } else {
+            self.body = ResBody::Sized(Box::new(Cursor::new(Vec::new())) as BoxReader);
+            self.content_len = Some(0);
+            self.set("Content-Length", "0");
+        }
+    }
+    // Compresses the body with the coding the client prefers, bodies
+    // that are compressed already, partial or too small are sent as
+    // they are
+    #[cfg(feature = "compression")]
+    pub(crate) fn compress(&mut self, request: &Request) {
+        let status = i32::from(self.status_code);
+        if !self.body.is_some()
+            || status < 200
+            || status == 204
+            || status == 206
+            || status == 304
+            || self.headers.contains_key("Content-Range")
+            || self.headers.contains_key("Content-Encoding")
+            || self
+                .headers
+                .get("Content-Type")
+                .map_or(false, |mime| FileHandler::is_compressed(mime))
+        {
+            return;
+        }
+        let vary = match self.headers.get("Vary") {
+            Some(vary) if vary.to_lowercase().contains("accept-encoding") => vary.clone(),
+            Some(vary) => format!("{}, Accept-Encoding", vary),
+            None => "Accept-Encoding".to_owned(),
+        };
+        self.set("Vary", &vary);
+        if self.content_len.map_or(false, |len| len < MIN_COMPRESS_LEN) {
+            return;
+        }
+        let encoding = match request
+            .headers
+            .get("accept-encoding")
+            .and_then(|accept| Encoding::negotiate(accept, &Encoding::SUPPORTED))
+        {
+            Some(encoding) => encoding,
+            None => return,
+        };
+        let body = std::mem::replace(&mut self.body, ResBody::None).get_reader();
+        // the length of the encoded body isn't known until it's sent
+        self.body = ResBody::Unsized(encoding.encode(body));
+        self.content_len = None;
+        self.headers.remove("Content-Length");
+        self.set("Content-Encoding", encoding.name());
+        // the encoded bytes differ, a strong tag would be a lie
+        if let Some(etag) = self
+            .headers
+            .get("ETag")
+            .filter(|etag| !etag.starts_with("W/"))
+        {
+            let weak = format!("W/{}", etag);
+            self.set("ETag", &weak);
+        }
+    }
+    // Narrows a file body down to the ranges asked for by a GET
+    // request, the whole file is sent if the Range header doesn't apply
+    pub(crate) fn apply_range(&mut self, request: &Request) {
+        if request.request_line.method != RequestMethod::Get || self.status_code != StatusCode::Ok {
+            return;
+        }
+        let header = match request.headers.get("range") {
+            Some(header) => header,
+            None => return,
+        };
+        if let Some(validator) = request.headers.get("if-range") {
+            if !self.if_range_matches(validator.trim()) {
+                return;
+            }
+        }
+        let (len, file) = match std::mem::replace(&mut self.body, ResBody::None) {
+            ResBody::File(len, file) => (len, file),
+            body => {
+                self.body = body;
+                return;
+            }
+        };
+        let (reader, body_len) = match Ranges::parse(header, len as u64) {
+            Ranges::Ignore => {
+                self.body = ResBody::File(len, file);
+                return;
+            }
+            Ranges::Unsatisfiable => {
+                self.status(StatusCode::RangeNotSatisfiable)
+                    .set("Content-Range", &format!("bytes */{}", len));
+                (Box::new(Cursor::new(Vec::new())) as BoxReader, 0)
+            }
+            Ranges::Satisfiable(ranges) => {
+                let reader = if let [range] = ranges[..] {
+                    self.set(
+                        "Content-Range",
+                        &format!("bytes {}-{}/{}", range.0, range.1, len),
+                    );
+                    RangeReader::single(file, range)
+                } else {
+                    let boundary = range::boundary();
+                    let content_type = self
+                        .headers
+                        .get("Content-Type")
+                        .cloned()
+                        .unwrap_or_else(|| "application/octet-stream".to_owned());
+                    self.set(
+                        "Content-Type",
+                        &format!("multipart/byteranges; boundary={}", boundary),
+                    );
+                    RangeReader::multipart(file, &ranges, len as u64, &content_type, &boundary)
+                };
+                self.status(StatusCode::PartialContent);
+                let body_len = reader.len() as usize;
+                (Box::new(reader) as BoxReader, body_len)
+            }
+        };
+        self.body = ResBody::Sized(reader);
+        self.content_len = Some(body_len);
+        self.set("Content-Length", &body_len.to_string());
+    }
+    // An If-Range validator has to match the representation exactly,
+    // a weak entity tag never does
+    fn if_range_matches(&self, validator: &str) -> bool {
+        let current = if validator.starts_with('"') {
+            self.headers.get("ETag")
+        } else if validator.starts_with("W/") {
+            None
+        } else {
+            self.headers.get("Last-Modified")
+        };
+        current.map_or(false, |current| current == validator)
+    }
     // Creates a new response from a slice
     pub(crate) fn new_from_slice<T: AsRef<[u8]>>(body: T) -> Self {
         let body_slice = body.as_ref();
@@ -452,14 +824,16 @@ impl<'a> Response<'a> {
             charset: None,
             #[cfg(feature = "cookies")]
             cookies: Vec::new(),
+            entity_tag: EntityTag::Weak,
+            pending_tag: None,
             marker: PhantomData,
         }
     }
     // Generates a new empty response
     fn new(body: BoxReader, content_len: Option<usize>) -> Self {
         let body_res: ResBody;
-        if let Some(x) = content_len {
-            body_res = ResBody::Sized(x, body)
+        if content_len.is_some() {
+            body_res = ResBody::Sized(body)
         } else {
             body_res = ResBody::Unsized(body)
         }
@@ -472,6 +846,8 @@ impl<'a> Response<'a> {
             charset: None,
             #[cfg(feature = "cookies")]
             cookies: Vec::new(),
+            entity_tag: EntityTag::Weak,
+            pending_tag: None,
             marker: PhantomData,
         }
     }
@@ -534,6 +910,7 @@ impl fmt::Debug for Response<'_> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::request::{Headers, RequestLine};
     use octane_http::HttpVersion;
     use tokio::io::AsyncReadExt;
 
@@ -565,6 +942,19 @@ mod test {
         );
     }
 
+    #[crate::test]
+    async fn success_stream() {
+        // Streamed bodies should be chunked for http 1.1 clients only
+        let mut res = Response::new_empty();
+        res.stream(Cursor::new(b"hello".to_vec()));
+        assert!(!res.frame_body(&HttpVersion::Http10));
+        assert!(res.frame_body(&HttpVersion::Http11));
+        let data = data_to_string(res.get_data()).await;
+        assert!(data.contains("Transfer-Encoding: chunked\r\n"));
+        assert!(!data.contains("Content-Length"));
+        assert!(data.ends_with("\r\n\r\n5\r\nhello\r\n0\r\n\r\n"));
+    }
+
     #[crate::test]
     async fn response_with_different_http_version() {
         // Reponse with different status codes should work
@@ -577,4 +967,204 @@ mod test {
             "HTTP/1.0 201 CREATED\r\n\r\n"
         );
     }
+
+    #[crate::test]
+    async fn success_range() {
+        // a satisfiable range of a file gives a partial response
+        let path = std::env::temp_dir().join(format!("octane-responder-{}", std::process::id()));
+        std::fs::write(&path, b"0123456789").unwrap();
+        let ranged = |headers: &str| {
+            let headers = Headers::parse(headers.to_owned()).unwrap();
+            let request_line = RequestLine::parse("GET / HTTP/1.1").unwrap();
+            let request = Request::parse(request_line, &headers, &[]).unwrap();
+            let mut res = Response::new_empty();
+            res.send_file(path.to_str().unwrap()).unwrap();
+            res.apply_range(&request);
+            res
+        };
+        let mut res = ranged("Range: bytes=2-4");
+        assert_eq!(res.status_code, StatusCode::PartialContent);
+        assert_eq!(res.get("Content-Range"), Some(&"bytes 2-4/10".to_owned()));
+        assert_eq!(res.get("Content-Length"), Some(&"3".to_owned()));
+        assert!(data_to_string(res.get_data())
+            .await
+            .ends_with("\r\n\r\n234"));
+
+        let mut res = ranged("Range: bytes=20-");
+        assert_eq!(res.status_code, StatusCode::RangeNotSatisfiable);
+        assert_eq!(res.get("Content-Range"), Some(&"bytes */10".to_owned()));
+
+        // the validator doesn't match, the whole file is sent
+        let res = ranged("Range: bytes=2-4\r\nIf-Range: \"abc\"");
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(res.status_code, StatusCode::Ok);
+        assert!(data_to_string(res.get_data()).await.ends_with("0123456789"));
+    }
+
+    #[test]
+    fn success_conditions() {
+        let path = std::env::temp_dir().join(format!("octane-conditions-{}", std::process::id()));
+        std::fs::write(&path, b"0123456789").unwrap();
+        let conditional = |headers: String, method: &str| {
+            let headers = Headers::parse(headers).unwrap();
+            let request_line = RequestLine::parse(&format!("{} / HTTP/1.1", method)).unwrap();
+            let request = Request::parse(request_line, &headers, &[]).unwrap();
+            let mut res = Response::new_empty();
+            res.send_file(path.to_str().unwrap()).unwrap();
+            res.apply_conditions(&request);
+            res
+        };
+        let mut res = conditional("Host: localhost".to_owned(), "GET");
+        let etag = res.get("ETag").unwrap().clone();
+        let modified = res.get("Last-Modified").unwrap().clone();
+        assert!(etag.starts_with("W/\""));
+
+        let mut res = conditional(format!("If-None-Match: \"a\", {}", etag), "GET");
+        assert_eq!(res.status_code, StatusCode::NotModified);
+        assert_eq!(res.get("Content-Length"), None);
+        assert_eq!(res.get("ETag"), Some(&etag));
+        let res = conditional(format!("If-Modified-Since: {}", modified), "GET");
+        assert_eq!(res.status_code, StatusCode::NotModified);
+        let res = conditional(format!("If-None-Match: {}", etag), "POST");
+        assert_eq!(res.status_code, StatusCode::PreconditionFailed);
+        // a weak tag never passes the strong comparison of If-Match
+        let res = conditional(format!("If-Match: {}", etag), "GET");
+        assert_eq!(res.status_code, StatusCode::PreconditionFailed);
+        let res = conditional("If-Match: *".to_owned(), "GET");
+        assert_eq!(res.status_code, StatusCode::Ok);
+        let res = conditional("If-None-Match: \"a\"".to_owned(), "GET");
+        assert_eq!(res.status_code, StatusCode::Ok);
+        let res = conditional(
+            "If-Unmodified-Since: Thu, 01 Jan 1970 00:00:00 GMT".into(),
+            "GET",
+        );
+        assert_eq!(res.status_code, StatusCode::PreconditionFailed);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[crate::test]
+    async fn success_strong_entity_tag() {
+        let path = std::env::temp_dir().join(format!("octane-strong-tag-{}", std::process::id()));
+        std::fs::write(&path, b"0123456789").unwrap();
+        let strong = || {
+            let mut res = Response::new_empty();
+            res.entity_tag = EntityTag::Strong;
+            res.send_file(path.to_str().unwrap()).unwrap();
+            res
+        };
+        let tag = "\"a-50c0aafd8b4330b2\"".to_owned();
+        // the first response hashes the file after the closures
+        let mut res = strong();
+        assert_eq!(res.get("ETag"), None);
+        res.resolve_entity_tag().await;
+        assert_eq!(res.get("ETag"), Some(&tag));
+        // the next ones get it from the cache right away
+        let mut res = strong();
+        assert_eq!(res.get("ETag"), Some(&tag));
+        // a file replaced by the closures isn't hashed
+        std::fs::write(&path, b"01234567890").unwrap();
+        let mut res = strong();
+        res.send("body");
+        res.resolve_entity_tag().await;
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(res.get("ETag"), None);
+    }
+
+    #[cfg(feature = "compression")]
+    #[crate::test]
+    async fn success_compress() {
+        use async_compression::tokio_03::bufread::GzipDecoder;
+        use tokio::io::BufReader;
+
+        let compressed = |accept: &str, body: &str| {
+            let headers = Headers::parse(format!("Accept-Encoding: {}", accept)).unwrap();
+            let request_line = RequestLine::parse("GET / HTTP/1.1").unwrap();
+            let request = Request::parse(request_line, &headers, &[]).unwrap();
+            let mut res = Response::new_empty();
+            res.set("ETag", "\"strong\"").send(body);
+            res.compress(&request);
+            res
+        };
+        let body = "{\"key\": \"value\"}".repeat(200);
+        let mut res = compressed("br;q=0.1, gzip", &body);
+        assert_eq!(res.get("Content-Encoding"), Some(&"gzip".to_owned()));
+        assert_eq!(res.get("Vary"), Some(&"Accept-Encoding".to_owned()));
+        assert_eq!(res.get("ETag"), Some(&"W/\"strong\"".to_owned()));
+        assert_eq!(res.get("Content-Length"), None);
+        let mut decoded = String::new();
+        GzipDecoder::new(BufReader::new(res.get_data().1))
+            .read_to_string(&mut decoded)
+            .await
+            .unwrap();
+        assert_eq!(decoded, body);
+
+        // too small to be worth it, but it still varies
+        let mut res = compressed("gzip", "{}");
+        assert_eq!(res.get("Content-Encoding"), None);
+        assert_eq!(res.get("Vary"), Some(&"Accept-Encoding".to_owned()));
+        let mut res = compressed("identity", &body);
+        assert_eq!(res.get("Content-Encoding"), None);
+        assert_eq!(res.get("Content-Length"), Some(&body.len().to_string()));
+    }
+
+    #[crate::test]
+    async fn success_send_json() {
+        #[derive(octane_macros::ToJSON)]
+        struct Stats<'a> {
+            name: &'a str,
+            #[json(rename = "loadAverage")]
+            load: Vec<f64>,
+        }
+
+        let mut res = Response::new_empty();
+        let stats = Stats {
+            name: "Octane",
+            load: vec![0.5],
+        };
+        res.send_json(&stats).unwrap();
+        assert_eq!(
+            res.get("Content-Type"),
+            Some(&"application/json".to_owned())
+        );
+        let body = "{\"loadAverage\":[0.5],\"name\":\"Octane\"}";
+        assert_eq!(res.get("Content-Length"), Some(&body.len().to_string()));
+        assert!(data_to_string(res.get_data()).await.ends_with(body));
+
+        let mut res = Response::new_empty();
+        res.send_json_pretty(&stats).unwrap();
+        let pretty = "{\n  \"loadAverage\": [\n    0.5\n  ],\n  \"name\": \"Octane\"\n}";
+        assert!(data_to_string(res.get_data()).await.ends_with(pretty));
+
+        let mut res = Response::new_empty();
+        assert!(res.send_json(&[1.0, f64::NAN][..]).is_none());
+        assert!(!res.has_body());
+    }
+
+    #[cfg(feature = "json")]
+    #[crate::test]
+    async fn success_json() {
+        let mut values = HashMap::new();
+        values.insert("name", vec!["Octane"]);
+        let mut res = Response::new_empty();
+        res.json(&values).unwrap();
+        assert_eq!(
+            res.get("Content-Type"),
+            Some(&"application/json".to_owned())
+        );
+        assert_eq!(res.get("Content-Length"), Some(&"19".to_owned()));
+        let data = data_to_string(res.get_data()).await;
+        assert!(data.ends_with("\r\n\r\n{\"name\":[\"Octane\"]}"));
+
+        let mut res = Response::new_empty();
+        res.json_pretty(&values).unwrap();
+        let pretty = "{\n  \"name\": [\n    \"Octane\"\n  ]\n}";
+        assert_eq!(res.content_len, Some(pretty.len()));
+        assert!(data_to_string(res.get_data()).await.ends_with(pretty));
+
+        let mut keys = HashMap::new();
+        keys.insert(vec![1], 1);
+        let mut res = Response::new_empty();
+        assert!(res.json(&keys).is_err());
+        assert!(!res.has_body());
+    }
 }
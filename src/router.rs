@@ -1,17 +1,24 @@
 use crate::default;
 use crate::error::InvalidPathError;
 use crate::middlewares::Closures;
-use crate::path::{MatchedPath, PathNode};
-use crate::request::{MatchedRequest, Request, RequestMethod};
+use crate::path::{MatchedPath, PathBuf, PathNode};
+use crate::request::{MatchedRequest, Request, RequestLine, RequestMethod};
 use crate::responder::Response;
 use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
 use std::result::Result;
 
 // The type of HashMap where we will be storing the all the closures
 pub(crate) type Paths = HashMap<RequestMethod, PathNode<Closures>>;
 /// The Closure type is a type alias for the type
-/// that the routes should return
-pub type Closure = Box<dyn for<'a> Fn(&'a MatchedRequest, &'a mut Response) -> Flow + Send + Sync>;
+/// that the routes should return, it gives a future
+/// which resolves to a [`Flow`](enum.Flow.html). Synchronous
+/// routes return a future that is ready right away
+pub type Closure =
+    Box<dyn for<'a> Fn(&'a MatchedRequest, &'a mut Response) -> FlowFuture<'a> + Send + Sync>;
+/// The future returned by a [`Closure`](type.Closure.html)
+pub type FlowFuture<'a> = Pin<Box<dyn Future<Output = Flow> + Send + 'a>>;
 // RouterResult is the type which the app.METHOD methods return
 pub(crate) type RouterResult = Result<(), InvalidPathError>;
 /// The flow enum works just like the next() callback
@@ -216,6 +223,12 @@ pub struct Router {
     pub middlewares: Vec<Closures>,
     /// The router paths which are to be executed on requests
     pub paths: Paths,
+    // body size limits of single routes, None lifts the limit
+    pub(crate) body_limits: HashMap<RequestMethod, PathNode<Option<usize>>>,
+    // routes whose request body is streamed instead of buffered
+    pub(crate) streaming: HashMap<RequestMethod, PathNode<()>>,
+    // middlewares of mounted routers, which only run under their prefix
+    pub(crate) scoped_middlewares: Vec<(PathBuf, Closures)>,
 }
 
 impl Router {
@@ -228,15 +241,137 @@ impl Router {
             route_counter: 0,
             middlewares: Vec::new(),
             paths: HashMap::new(),
+            body_limits: HashMap::new(),
+            streaming: HashMap::new(),
+            scoped_middlewares: Vec::new(),
         }
     }
+    /// Sets the largest request body a route accepts, replacing the
+    /// [`max_body_size`](crate::config::OctaneConfig::max_body_size)
+    /// of the config for the requests on `path`. `RequestMethod::All`
+    /// applies it to every method and `None` lifts the limit
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    /// use octane::request::RequestMethod;
+    ///
+    /// let mut router = Router::new();
+    /// router
+    ///     .body_limit(RequestMethod::Post, "/upload", Some(512 * 1024 * 1024))
+    ///     .unwrap();
+    /// ```
+    pub fn body_limit(
+        &mut self,
+        method: RequestMethod,
+        path: &str,
+        max: Option<usize>,
+    ) -> RouterResult {
+        self.body_limits
+            .entry(method)
+            .or_insert_with(PathNode::new)
+            .insert(PathBuf::parse(path)?, max);
+        Ok(())
+    }
+    // The body limit set on the routes matching the request line, the
+    // most permissive one wins when several match, None if there is none
+    pub(crate) fn route_body_limit(&self, request_line: &RequestLine) -> Option<Option<usize>> {
+        let mut limits = Vec::new();
+        for method in [&request_line.method, &RequestMethod::All].iter() {
+            if let Some(node) = self.body_limits.get(method) {
+                limits.extend(node.get(&request_line.path).iter().map(|m| *m.data));
+            }
+        }
+        if limits.is_empty() {
+            None
+        } else if limits.contains(&None) {
+            Some(None)
+        } else {
+            limits.into_iter().max()
+        }
+    }
+    /// Makes the requests on `path` stream their body instead of
+    /// buffering it, the closures get an empty `body` and read it from
+    /// [`body_stream`](crate::request::Request::body_stream). The
+    /// `Content-Encoding` of a streamed body isn't decoded.
+    /// `RequestMethod::All` applies it to every method
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    /// use octane::request::RequestMethod;
+    ///
+    /// let mut router = Router::new();
+    /// router.stream_body(RequestMethod::Put, "/files/:name").unwrap();
+    /// ```
+    pub fn stream_body(&mut self, method: RequestMethod, path: &str) -> RouterResult {
+        self.streaming
+            .entry(method)
+            .or_insert_with(PathNode::new)
+            .insert(PathBuf::parse(path)?, ());
+        Ok(())
+    }
+    // Whether a route matching the request line streams its body
+    pub(crate) fn streams_body(&self, request_line: &RequestLine) -> bool {
+        [&request_line.method, &RequestMethod::All]
+            .iter()
+            .filter_map(|method| self.streaming.get(method))
+            .any(|node| !node.get(&request_line.path).is_empty())
+    }
+    /// Mounts the routes of another router under `prefix`, so a
+    /// route on `/users` of the router answers on `/api/v1/users`
+    /// when mounted at `/api/v1`. The middlewares added to the mounted
+    /// router only run on the paths under the prefix, where they see
+    /// the full path of the request. The prefix can have url variables
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    /// use octane::StatusCode;
+    ///
+    /// let mut users = Router::new();
+    /// users
+    ///     .add(route!(|req, res| {
+    ///         if req.headers.get("authorization").is_none() {
+    ///             res.status(StatusCode::Unauthorized).send("Log in first");
+    ///             return Flow::Stop;
+    ///         }
+    ///         Flow::Next
+    ///     }))
+    ///     .unwrap();
+    /// users
+    ///     .get("/me", route_stop!(|req, res| { res.send("It's you"); }))
+    ///     .unwrap();
+    ///
+    /// let mut api = Router::new();
+    /// api.mount("/users", users).unwrap();
+    /// let mut app = Octane::new();
+    /// // GET /api/v1/users/me
+    /// app.mount("/api/v1", api).unwrap();
+    /// ```
+    pub fn mount(&mut self, prefix: &str, router: Self) -> RouterResult {
+        self.merge(router, Some(&PathBuf::parse(prefix)?));
+        Ok(())
+    }
     // append the routes stored in a custom Router to the self Router
     pub(crate) fn append(&mut self, router: Self) {
+        self.merge(router, None);
+    }
+    // Moves the routes of the router into self, under the prefix if any
+    fn merge(&mut self, router: Self, prefix: Option<&PathBuf>) {
         let self_count = self.route_counter;
         let other_count = router.route_counter;
+        let prefixed = |path: PathBuf| match prefix {
+            Some(prefix) => prefix.concat_owned(path),
+            None => path,
+        };
         for (methods, paths) in router.paths.into_iter() {
             let updated_paths = paths.into_iter().map(|mut v| {
                 v.data.index += self_count;
+                v.orig_path = prefixed(v.orig_path);
                 v
             });
             if let Some(x) = self.paths.get_mut(&methods) {
@@ -246,17 +381,45 @@ impl Router {
             }
         }
 
-        self.middlewares
-            .extend(router.middlewares.into_iter().map(|mut v| {
+        for (method, limits) in router.body_limits.into_iter() {
+            self.body_limits
+                .entry(method)
+                .or_insert_with(PathNode::new)
+                .extend(limits.into_iter().map(|mut v| {
+                    v.orig_path = prefixed(v.orig_path);
+                    v
+                }));
+        }
+        for (method, streaming) in router.streaming.into_iter() {
+            self.streaming
+                .entry(method)
+                .or_insert_with(PathNode::new)
+                .extend(streaming.into_iter().map(|mut v| {
+                    v.orig_path = prefixed(v.orig_path);
+                    v
+                }));
+        }
+        let middlewares = router.middlewares.into_iter().map(|mut v| {
+            v.index += self_count;
+            v
+        });
+        match prefix {
+            Some(prefix) => self
+                .scoped_middlewares
+                .extend(middlewares.map(|v| (prefix.clone(), v))),
+            None => self.middlewares.extend(middlewares),
+        }
+        self.scoped_middlewares
+            .extend(router.scoped_middlewares.into_iter().map(|(path, mut v)| {
                 v.index += self_count;
-                v
+                (prefixed(path), v)
             }));
         self.route_counter += other_count;
     }
 
     // Fetch the closure according to the request path, run that
     // specific closure.
-    pub(crate) fn run(&self, parsed_request: Request<'_>, mut res: &mut Response) {
+    pub(crate) async fn run(&self, parsed_request: Request<'_>, res: &mut Response<'_>) {
         let req = &parsed_request.request_line;
 
         let mut matches: Vec<Vec<MatchedPath<Closures>>> = Vec::new();
@@ -272,16 +435,28 @@ impl Router {
             matches.push(routes);
         }
 
-        matches.push(
-            self.middlewares
-                .iter()
-                .map(|c| MatchedPath {
-                    data: c,
-                    #[cfg(feature = "url_variables")]
-                    vars: HashMap::new(),
-                })
-                .collect(),
-        );
+        let mut middlewares: Vec<MatchedPath<Closures>> = self
+            .middlewares
+            .iter()
+            .map(|c| MatchedPath {
+                data: c,
+                #[cfg(feature = "url_variables")]
+                vars: HashMap::new(),
+            })
+            .collect();
+        for (prefix, c) in self.scoped_middlewares.iter() {
+            #[cfg(feature = "url_variables")]
+            let matched = prefix
+                .check_prefix(&req.path)
+                .map(|vars| MatchedPath { data: c, vars });
+            #[cfg(not(feature = "url_variables"))]
+            let matched = prefix
+                .check_prefix(&req.path)
+                .map(|_| MatchedPath { data: c });
+            middlewares.extend(matched);
+        }
+        middlewares.sort_by_key(|v| v.index);
+        matches.push(middlewares);
 
         let mut indices = vec![0_usize; matches.len()];
         let total: usize = matches.iter().map(Vec::len).sum();
@@ -307,7 +482,7 @@ impl Router {
             {
                 matched.vars = matches[minind][indices[minind]].vars.clone();
             }
-            let flow = (matches[minind][indices[minind]].closure)(&matched, &mut res);
+            let flow = (matches[minind][indices[minind]].closure)(&matched, res).await;
             indices[minind] += 1;
             if !flow.should_continue() {
                 break;
@@ -350,7 +525,71 @@ impl Router {
 macro_rules! route {
     ( | $req : ident, $res : ident | $body : expr ) => {{
         #[allow(unused_variables)]
-        Box::new(move |$req, $res| $body)
+        Box::new(move |$req, $res| {
+            #[allow(clippy::redundant_closure_call)]
+            let flow = (|| $body)();
+            Box::pin(std::future::ready(flow))
+        })
+    }};
+}
+
+/// Just like the [`route!()`](macro.route.html) macro but the body
+/// is an async block, so you can `.await` inside of it. The body
+/// should evaluate to a [`Flow`](enum.Flow.html)
+///
+/// # Example
+///
+/// ```
+/// use octane::prelude::*;
+///
+/// async fn fetch_name() -> String {
+///     "Octane".to_owned()
+/// }
+///
+/// let mut app = Octane::new();
+/// app.get(
+///     "/",
+///     async_route!(
+///         |req, res| {
+///             let name = fetch_name().await;
+///             res.send(format!("Hello, {}", name));
+///             Flow::Stop
+///         }
+///     ),
+/// );
+/// ```
+///
+/// The route can run many times while the async block takes what
+/// it uses, so values that aren't `Copy` have to be listed after
+/// `move`, they are then cloned into the block on each call
+///
+/// ```
+/// use octane::prelude::*;
+/// use std::sync::Arc;
+///
+/// let name = Arc::new(String::from("Octane"));
+/// let mut app = Octane::new();
+/// app.get(
+///     "/",
+///     async_route!(move [name] |req, res| {
+///         res.send(format!("Hello, {}", name));
+///         Flow::Stop
+///     }),
+/// );
+/// ```
+#[macro_export]
+macro_rules! async_route {
+    ( | $req : ident, $res : ident | $body : expr ) => {{
+        $crate::async_route!(move [] |$req, $res| $body)
+    }};
+    ( move [ $($cap : ident),* $(,)? ] | $req : ident, $res : ident | $body : expr ) => {{
+        #[allow(unused_variables)]
+        Box::new(move |$req, $res| {
+            $(
+                let $cap = $cap.clone();
+            )*
+            Box::pin(async move { $body })
+        })
     }};
 }
 
@@ -412,7 +651,6 @@ macro_rules! route_stop {
 
 default!(Router);
 
-#[macro_use]
 macro_rules! inject_method {
     ( $instance: expr, $path: expr, $closure: expr, $method: expr ) => {
         use crate::middlewares::Closures;
@@ -475,6 +713,179 @@ impl Route for Router {
 mod test {
     use super::*;
     use crate::path::PathBuf;
+    use crate::request::{Headers, RequestLine};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::AsyncReadExt;
+
+    async fn run(router: &Router, request_line: &str) -> String {
+        let headers = Headers::parse("Host: localhost".to_string()).unwrap();
+        let request =
+            Request::parse(RequestLine::parse(request_line).unwrap(), &headers, &[]).unwrap();
+        let mut res = Response::new_empty();
+        router.run(request, &mut res).await;
+        let (_, mut body) = res.get_data();
+        let mut ret = String::new();
+        body.read_to_string(&mut ret).await.unwrap();
+        ret
+    }
+
+    #[crate::test]
+    async fn success_mount() {
+        let mut users = Router::new();
+        // only runs under the prefix, requests with a query are denied
+        users
+            .add(route!(|req, res| {
+                if req.request_line.query.is_some() {
+                    res.send("denied");
+                    return Flow::Stop;
+                }
+                Flow::Next
+            }))
+            .unwrap();
+        users
+            .get("/", route_stop!(|req, res| res.send("users")))
+            .unwrap();
+        users
+            .get("/me", route_stop!(|req, res| res.send("me")))
+            .unwrap();
+        users.stream_body(RequestMethod::Put, "/upload").unwrap();
+        let mut api = Router::new();
+        api.mount("/users", users).unwrap();
+        let mut router = Router::new();
+        router
+            .get("/me", route_stop!(|req, res| res.send("root me")))
+            .unwrap();
+        router.mount("/api/v1", api).unwrap();
+
+        assert_eq!("me", run(&router, "GET /api/v1/users/me HTTP/1.1").await);
+        assert_eq!("users", run(&router, "GET /api/v1/users HTTP/1.1").await);
+        assert_eq!(
+            "denied",
+            run(&router, "GET /api/v1/users/me?a HTTP/1.1").await
+        );
+        assert_eq!(
+            "denied",
+            run(&router, "GET /api/v1/users/other?a HTTP/1.1").await
+        );
+        assert_eq!("root me", run(&router, "GET /me?a HTTP/1.1").await);
+        assert_eq!("", run(&router, "GET /users/me HTTP/1.1").await);
+        assert_eq!("", run(&router, "GET /api/v1/me HTTP/1.1").await);
+        let upload = RequestLine::parse("PUT /api/v1/users/upload HTTP/1.1").unwrap();
+        assert!(router.streams_body(&upload));
+        assert!(!router.streams_body(&RequestLine::parse("PUT /upload HTTP/1.1").unwrap()));
+    }
+
+    #[cfg(feature = "url_variables")]
+    #[crate::test]
+    async fn success_mount_variables() {
+        let mut items = Router::new();
+        items
+            .add(route!(|req, res| {
+                if req.vars.get("shop") == Some(&"closed") {
+                    res.send("closed");
+                    return Flow::Stop;
+                }
+                Flow::Next
+            }))
+            .unwrap();
+        items
+            .get(
+                "/:id",
+                route_stop!(|req, res| res.send(format!("{} {}", req.vars["shop"], req.vars["id"]))),
+            )
+            .unwrap();
+        let mut router = Router::new();
+        router.mount("/shops/:shop/items", items).unwrap();
+        assert_eq!("a 1", run(&router, "GET /shops/a/items/1 HTTP/1.1").await);
+        assert_eq!(
+            "closed",
+            run(&router, "GET /shops/closed/items/1 HTTP/1.1").await
+        );
+    }
+
+    #[crate::test]
+    async fn router_async_test() {
+        // async routes should be awaited in order with the sync ones
+        async fn name() -> &'static str {
+            "async"
+        }
+        let mut router = Router::new();
+        router
+            .get(
+                "/",
+                async_route!(|req, res| {
+                    res.send(name().await);
+                    Flow::Next
+                }),
+            )
+            .unwrap();
+        router
+            .get(
+                "/",
+                route!(|req, res| {
+                    if req.request_line.path.is_empty() {
+                        return Flow::Stop;
+                    }
+                    res.send("sync");
+                    Flow::Next
+                }),
+            )
+            .unwrap();
+        router
+            .get(
+                "/a",
+                async_route!(|req, res| {
+                    res.send("first");
+                    Flow::Stop
+                }),
+            )
+            .unwrap();
+        router
+            .get("/a", route_stop!(|req, res| res.send("second")))
+            .unwrap();
+        assert_eq!("async", run(&router, "GET / HTTP/1.1").await);
+        assert_eq!("first", run(&router, "GET /a HTTP/1.1").await);
+    }
+
+    #[crate::test]
+    async fn router_async_capture_test() {
+        // the captures are cloned into every call of the route
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&hits);
+        let mut router = Router::new();
+        router
+            .get(
+                "/",
+                async_route!(move [counter] |req, res| {
+                    let count = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                    res.send(count.to_string());
+                    Flow::Stop
+                }),
+            )
+            .unwrap();
+        assert_eq!("1", run(&router, "GET / HTTP/1.1").await);
+        assert_eq!("2", run(&router, "GET / HTTP/1.1").await);
+        assert_eq!(2, hits.load(Ordering::SeqCst));
+    }
+
+    #[crate::test]
+    async fn router_capture_test() {
+        // sync routes borrow what they capture, so it isn't moved out on each call
+        let greeting = String::from("hi");
+        let mut router = Router::new();
+        router
+            .get(
+                "/",
+                route!(|req, res| {
+                    res.send(&greeting);
+                    Flow::Stop
+                }),
+            )
+            .unwrap();
+        assert_eq!("hi", run(&router, "GET / HTTP/1.1").await);
+        assert_eq!("hi", run(&router, "GET / HTTP/1.1").await);
+    }
 
     #[test]
     pub fn router_test() {
@@ -581,4 +992,40 @@ mod test {
                 .len()
         );
     }
+
+    #[test]
+    #[cfg(feature = "url_variables")]
+    fn success_route_body_limit() {
+        let mut router = Router::new();
+        router
+            .body_limit(RequestMethod::Post, "/upload/:id", Some(1024))
+            .unwrap();
+        let mut other = Router::new();
+        other
+            .body_limit(RequestMethod::All, "/upload/big", None)
+            .unwrap();
+        router.append(other);
+        let limit = |line| router.route_body_limit(&RequestLine::parse(line).unwrap());
+        assert_eq!(limit("POST /upload/1 HTTP/1.1"), Some(Some(1024)));
+        assert_eq!(limit("POST /upload/big HTTP/1.1"), Some(None));
+        assert_eq!(limit("PUT /upload/1 HTTP/1.1"), None);
+        assert_eq!(limit("POST / HTTP/1.1"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "url_variables")]
+    fn success_streams_body() {
+        let mut router = Router::new();
+        router
+            .stream_body(RequestMethod::Put, "/files/:name")
+            .unwrap();
+        let mut other = Router::new();
+        other.stream_body(RequestMethod::All, "/upload").unwrap();
+        router.append(other);
+        let streams = |line| router.streams_body(&RequestLine::parse(line).unwrap());
+        assert!(streams("PUT /files/a HTTP/1.1"));
+        assert!(streams("POST /upload HTTP/1.1"));
+        assert!(!streams("POST /files/a HTTP/1.1"));
+        assert!(!streams("PUT / HTTP/1.1"));
+    }
 }
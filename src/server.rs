@@ -1,23 +1,31 @@
 use crate::config::{Config, OctaneConfig, Ssl};
 use crate::error::Error;
 use crate::http::Http;
-use crate::middlewares::Closures;
-use crate::request::{Headers, Request, RequestLine};
+use crate::middlewares::{static_dir::StaticDir, Closures};
+use crate::request::{Connection, Headers, Request, RequestLine, RequestMethod};
 use crate::responder::{BoxReader, Response};
 use crate::router::{Closure, Route, Router, RouterResult};
-use crate::server_builder::ServerBuilder;
+use crate::server_builder::{self, ServerBuilder};
+use crate::shutdown::{self, Drain, Shutdown};
 use crate::tls::AsMutStream;
 use crate::{declare_error, default};
 use octane_http::http1x::raw_request::RawRequest1x;
-use octane_http::http1x::Http1xReader;
-use octane_http::StatusCode;
+use octane_http::http1x::{HeadLimits, Http1xReader};
+use octane_http::{HttpVersion, StatusCode};
 use std::error::Error as StdError;
+use std::future::{pending, Future};
+use std::io;
 use std::marker::Unpin;
+use std::net::{SocketAddr, TcpListener as StdTcpListener, ToSocketAddrs};
+#[cfg(unix)]
+use std::path::Path;
 use std::str;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::io::{copy, split, AsyncWriteExt};
+use tokio::io::{copy, empty, split, AsyncWriteExt};
 use tokio::prelude::*;
+use tokio::stream::Stream;
+use tokio::time::timeout;
 
 /// The Octane server
 ///
@@ -104,9 +112,80 @@ impl Octane {
     pub fn with_router(&mut self, router: Router) {
         self.router.append(router);
     }
+    /// Mounts the routes of the router under `prefix`, see
+    /// [`Router::mount`](crate::router::Router::mount)
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    ///
+    /// let mut app = Octane::new();
+    /// let mut router = Router::new();
+    /// router.get("/status", route_stop!(|req, res| { res.send("Running"); }));
+    /// // answers on /api/status
+    /// app.mount("/api", router);
+    /// ```
+    pub fn mount(&mut self, prefix: &str, router: Router) -> RouterResult {
+        self.router.mount(prefix, router)
+    }
+    /// Returns a middleware serving the files of `dir` at the root
+    /// url, `index.html` is served for directories. Use a
+    /// [`StaticDir`](crate::StaticDir) for a prefix or listings
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use octane::prelude::*;
+    ///
+    /// let mut app = Octane::new();
+    /// app.add(Octane::static_dir("public"));
+    /// ```
+    pub fn static_dir(dir: &str) -> Closure {
+        StaticDir::new(dir).handler()
+    }
+    /// Sets the largest request body a route accepts, see
+    /// [`Router::body_limit`](crate::router::Router::body_limit)
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use octane::prelude::*;
+    /// use octane::request::RequestMethod;
+    ///
+    /// let mut app = Octane::new();
+    /// app.body_limit(RequestMethod::Post, "/upload", Some(512 * 1024 * 1024))
+    ///     .unwrap();
+    /// ```
+    pub fn body_limit(
+        &mut self,
+        method: RequestMethod,
+        path: &str,
+        max: Option<usize>,
+    ) -> RouterResult {
+        self.router.body_limit(method, path, max)
+    }
+    /// Makes the requests on `path` stream their body instead of
+    /// buffering it, see
+    /// [`Router::stream_body`](crate::router::Router::stream_body)
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use octane::prelude::*;
+    /// use octane::request::RequestMethod;
+    ///
+    /// let mut app = Octane::new();
+    /// app.stream_body(RequestMethod::Post, "/upload").unwrap();
+    /// ```
+    pub fn stream_body(&mut self, method: RequestMethod, path: &str) -> RouterResult {
+        self.router.stream_body(method, path)
+    }
     /// Start listening on the port specified, the listen
     /// function also starts the Ssl server if the features
-    /// are enabled and the key/certs are provided
+    /// are enabled and the key/certs are provided. The server
+    /// listens on every interface unless some hosts are set
+    /// in the config
     ///
     /// # Example
     ///
@@ -126,89 +205,573 @@ impl Octane {
     where
         F: FnOnce(),
     {
+        self.listen_with_shutdown(port, exec, pending::<()>()).await
+    }
+    /// Builds a runtime from the thread settings of the config and
+    /// listens on it until the server stops, it's an alternative to
+    /// `#[octane::main]` for a synchronous main function
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use octane::prelude::*;
+    ///
+    /// fn main() {
+    ///     let mut app = Octane::new();
+    ///     app.settings.worker_threads(2);
+    ///     app.run(8080, || println!("Server started on port 8080"))
+    ///         .expect("Cannot establish connection");
+    /// }
+    /// ```
+    pub fn run<F>(self, port: u16, exec: F) -> Result<(), Box<dyn StdError>>
+    where
+        F: FnOnce(),
+    {
+        let runtime = self.settings.build_runtime()?;
+        runtime.block_on(self.listen(port, exec))
+    }
+    /// Same as [`listen`](Octane::listen) but stops gracefully when the
+    /// `signal` future resolves. New connections are refused right
+    /// away, requests that are being served are completed and idle
+    /// keep alive connections are closed. The function returns once
+    /// every connection is closed or the `shutdown_timeout` of the config
+    /// has passed
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use octane::Octane;
+    /// use std::time::Duration;
+    ///
+    /// #[octane::main]
+    /// async fn main() {
+    ///     let mut app = Octane::new();
+    ///     app.settings.shutdown_timeout = Some(Duration::from_secs(10));
+    ///     let port = 80;
+    ///     app.listen_with_shutdown(
+    ///         port,
+    ///         || println!("Server started on port {}", port),
+    ///         tokio::time::sleep(Duration::from_secs(60)),
+    ///     )
+    ///     .await
+    ///     .expect("Cannot establish connection");
+    /// }
+    /// ```
+    pub async fn listen_with_shutdown<F, S>(
+        self,
+        port: u16,
+        exec: F,
+        signal: S,
+    ) -> Result<(), Box<dyn StdError>>
+    where
+        F: FnOnce(),
+        S: Future,
+    {
+        let addrs = self.settings.socket_addrs(port);
+        self.bind_and_serve(addrs, exec, signal).await
+    }
+    /// Start listening on every address that `addrs` resolves to,
+    /// IPv4 and IPv6 addresses can be mixed. The Ssl server, if
+    /// enabled, listens on the same hosts with the port from the
+    /// Ssl config
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use octane::Octane;
+    /// use std::net::SocketAddr;
+    ///
+    /// #[octane::main]
+    /// async fn main() {
+    ///     let app = Octane::new();
+    ///     let addrs: [SocketAddr; 2] = ["127.0.0.1:8080".parse().unwrap(), "[::1]:8080".parse().unwrap()];
+    ///     app.listen_on(&addrs[..], || println!("Server started on localhost"))
+    ///         .await
+    ///         .expect("Cannot establish connection");
+    /// }
+    /// ```
+    pub async fn listen_on<A, F>(self, addrs: A, exec: F) -> Result<(), Box<dyn StdError>>
+    where
+        A: ToSocketAddrs,
+        F: FnOnce(),
+    {
+        self.listen_on_with_shutdown(addrs, exec, pending::<()>())
+            .await
+    }
+    /// Same as [`listen_on`](Octane::listen_on) but stops gracefully when
+    /// the `signal` future resolves, like
+    /// [`listen_with_shutdown`](Octane::listen_with_shutdown)
+    pub async fn listen_on_with_shutdown<A, F, S>(
+        self,
+        addrs: A,
+        exec: F,
+        signal: S,
+    ) -> Result<(), Box<dyn StdError>>
+    where
+        A: ToSocketAddrs,
+        F: FnOnce(),
+        S: Future,
+    {
+        let addrs: Vec<SocketAddr> = addrs.to_socket_addrs()?.collect();
+        self.bind_and_serve(addrs, exec, signal).await
+    }
+
+    async fn bind_and_serve<F, S>(
+        self,
+        addrs: Vec<SocketAddr>,
+        exec: F,
+        signal: S,
+    ) -> Result<(), Box<dyn StdError>>
+    where
+        F: FnOnce(),
+        S: Future,
+    {
+        let server_builder = ServerBuilder::new(&addrs)?;
         let server = Arc::new(self);
+        let (drain, shutdown) = shutdown::channel();
         let mut _ssl = false;
 
         #[cfg(any(feature = "openSSL", feature = "rustls"))]
         {
             use crate::task;
             let clone = Arc::clone(&server);
+            let mut ssl_shutdown = shutdown.clone();
             _ssl = true;
 
-            async fn listen_ssl(server: Arc<Octane>) -> Result<(), Box<dyn StdError>> {
-                let server_builder = ServerBuilder::new(server.settings.ssl.port);
+            async fn listen_ssl(
+                server: Arc<Octane>,
+                addrs: Vec<SocketAddr>,
+                shutdown: Shutdown,
+            ) -> Result<(), Box<dyn StdError>> {
+                let server_builder = ServerBuilder::new(&addrs);
                 server_builder?
                     .listen_ssl(
-                        |stream, server| async { Octane::serve(stream, server).await },
+                        |stream, server, shutdown| async move {
+                            Octane::serve(stream, server, shutdown).await
+                        },
                         server,
+                        shutdown,
                     )
                     .await?;
                 Ok(())
             }
 
+            let mut ssl_addrs: Vec<SocketAddr> = Vec::new();
+            for addr in &addrs {
+                let ssl_addr = SocketAddr::new(addr.ip(), server.settings.ssl.port);
+                if !ssl_addrs.contains(&ssl_addr) {
+                    ssl_addrs.push(ssl_addr);
+                }
+            }
+            let shutdown = shutdown.clone();
             task!({
-                if let Err(x) = listen_ssl(clone).await {
-                    println!("WARNING: {}", x);
+                tokio::select! {
+                    result = listen_ssl(clone, ssl_addrs, shutdown) => {
+                        if let Err(x) = result {
+                            println!("WARNING: {}", x);
+                        }
+                    }
+                    _ = ssl_shutdown.recv() => {}
                 }
             });
         }
         exec();
-        let server_builder = ServerBuilder::new(port);
-        server_builder?
-            .listen(
-                move |stream, server| async move { Octane::serve(stream, server).await },
-                server,
-            )
-            .await?;
+        Octane::accept_until(server_builder, server, (drain, shutdown), signal).await
+    }
+    /// Start accepting on listeners that are already bound, like the
+    /// ones handed over by a parent process on a hot restart. See
+    /// [`listen_systemd`](Octane::listen_systemd) for systemd socket
+    /// activation. The Ssl server is not started
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use octane::Octane;
+    /// use std::net::TcpListener;
+    ///
+    /// #[octane::main]
+    /// async fn main() {
+    ///     let app = Octane::new();
+    ///     let listener = TcpListener::bind("127.0.0.1:8080").unwrap();
+    ///     app.listen_from(vec![listener], || println!("Server started"))
+    ///         .await
+    ///         .expect("Cannot establish connection");
+    /// }
+    /// ```
+    pub async fn listen_from<F>(
+        self,
+        listeners: Vec<StdTcpListener>,
+        exec: F,
+    ) -> Result<(), Box<dyn StdError>>
+    where
+        F: FnOnce(),
+    {
+        self.listen_from_with_shutdown(listeners, exec, pending::<()>())
+            .await
+    }
+    /// Same as [`listen_from`](Octane::listen_from) but stops gracefully
+    /// when the `signal` future resolves, like
+    /// [`listen_with_shutdown`](Octane::listen_with_shutdown)
+    pub async fn listen_from_with_shutdown<F, S>(
+        self,
+        listeners: Vec<StdTcpListener>,
+        exec: F,
+        signal: S,
+    ) -> Result<(), Box<dyn StdError>>
+    where
+        F: FnOnce(),
+        S: Future,
+    {
+        let server_builder = ServerBuilder::from_std(listeners)?;
+        let server = Arc::new(self);
+        exec();
+        Octane::accept_until(server_builder, server, shutdown::channel(), signal).await
+    }
+    /// Start accepting on the sockets passed to the process by
+    /// systemd socket activation, TCP and Unix ones alike. It fails
+    /// when the process wasn't activated. The Ssl server is not started
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use octane::Octane;
+    ///
+    /// #[octane::main]
+    /// async fn main() {
+    ///     let app = Octane::new();
+    ///     app.listen_systemd(|| println!("Server started"))
+    ///         .await
+    ///         .expect("Cannot establish connection");
+    /// }
+    /// ```
+    #[cfg(unix)]
+    pub async fn listen_systemd<F>(self, exec: F) -> Result<(), Box<dyn StdError>>
+    where
+        F: FnOnce(),
+    {
+        self.listen_systemd_with_shutdown(exec, pending::<()>())
+            .await
+    }
+    /// Same as [`listen_systemd`](Octane::listen_systemd) but stops
+    /// gracefully when the `signal` future resolves, like
+    /// [`listen_with_shutdown`](Octane::listen_with_shutdown)
+    #[cfg(unix)]
+    pub async fn listen_systemd_with_shutdown<F, S>(
+        self,
+        exec: F,
+        signal: S,
+    ) -> Result<(), Box<dyn StdError>>
+    where
+        F: FnOnce(),
+        S: Future,
+    {
+        let (tcp, unix) = server_builder::listen_fds()?;
+        if tcp.is_empty() && unix.is_empty() {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::NotFound,
+                "No socket was passed by systemd",
+            )));
+        }
+        let tcp = if tcp.is_empty() {
+            None
+        } else {
+            Some(ServerBuilder::from_std(tcp)?)
+        };
+        let unix = if unix.is_empty() {
+            None
+        } else {
+            Some(ServerBuilder::from_std_unix(unix)?)
+        };
+        let server = Arc::new(self);
+        let deadline = server.settings.shutdown_timeout;
+        let (drain, shutdown) = shutdown::channel();
+        let accepting = async {
+            let tcp = async {
+                match tcp {
+                    Some(builder) => {
+                        Octane::accept(builder, Arc::clone(&server), shutdown.clone()).await
+                    }
+                    None => Ok(()),
+                }
+            };
+            let unix = async {
+                match unix {
+                    Some(builder) => {
+                        Octane::accept(builder, Arc::clone(&server), shutdown.clone()).await
+                    }
+                    None => Ok(()),
+                }
+            };
+            tokio::try_join!(tcp, unix)
+        };
+        exec();
+        tokio::select! {
+            result = accepting => {
+                result?;
+            }
+            _ = signal => {}
+        }
+        drop(shutdown);
+        drain.drain(deadline).await;
 
         Ok(())
     }
+    /// Start listening on a Unix domain socket at `path`, it's useful
+    /// behind a reverse proxy on the same host. A socket file left
+    /// by a previous run is replaced, the permissions and ownership
+    /// of the new one are taken from the `unix` field of the config.
+    /// The Ssl server is not started
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use octane::Octane;
+    ///
+    /// #[octane::main]
+    /// async fn main() {
+    ///     let mut app = Octane::new();
+    ///     app.settings.unix.mode(0o660);
+    ///     app.listen_unix("/run/octane.sock", || println!("Server started"))
+    ///         .await
+    ///         .expect("Cannot establish connection");
+    /// }
+    /// ```
+    #[cfg(unix)]
+    pub async fn listen_unix<P, F>(self, path: P, exec: F) -> Result<(), Box<dyn StdError>>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(),
+    {
+        self.listen_unix_with_shutdown(path, exec, pending::<()>())
+            .await
+    }
+    /// Same as [`listen_unix`](Octane::listen_unix) but stops gracefully
+    /// when the `signal` future resolves, like
+    /// [`listen_with_shutdown`](Octane::listen_with_shutdown). The socket
+    /// file is removed when the server returns
+    #[cfg(unix)]
+    pub async fn listen_unix_with_shutdown<P, F, S>(
+        self,
+        path: P,
+        exec: F,
+        signal: S,
+    ) -> Result<(), Box<dyn StdError>>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(),
+        S: Future,
+    {
+        let path = path.as_ref();
+        let server_builder = ServerBuilder::new_unix(path, &self.settings.unix)?;
+        let server = Arc::new(self);
+        exec();
+        let result =
+            Octane::accept_until(server_builder, server, shutdown::channel(), signal).await;
+        std::fs::remove_file(path).ok();
+        result
+    }
 
-    async fn serve<S>(stream_async: S, server: Arc<Octane>) -> Result<(), Box<dyn StdError>>
+    // Serves the connections of the listeners until the signal resolves,
+    // then waits for the open connections to drain
+    async fn accept_until<L, T, S>(
+        server_builder: ServerBuilder<L>,
+        server: Arc<Octane>,
+        (drain, shutdown): (Drain, Shutdown),
+        signal: S,
+    ) -> Result<(), Box<dyn StdError>>
     where
-        S: AsyncRead + AsyncWrite + Unpin + AsMutStream,
+        L: Stream<Item = io::Result<T>> + Unpin,
+        T: AsyncRead + AsyncWrite + Unpin + AsMutStream + Send + 'static,
+        S: Future,
     {
-        let (reader, writer) = split(stream_async);
-        let mut data = Vec::new();
-        let parsed = Http1xReader::new(RawRequest1x::new(reader), &mut data).await;
-        if let Ok((raw_headers, raw_request_line, body_remainder, reader_left)) = parsed {
-            let headers = Headers::parse(raw_headers).unwrap();
-            let request_line = RequestLine::parse(raw_request_line).unwrap();
-            if let Some(request) = Request::from_raw(
-                &headers,
-                request_line,
-                Default::default(),
-                &mut Default::default(),
-                body_remainder,
-                reader_left,
+        let deadline = server.settings.shutdown_timeout;
+        let accepting = Octane::accept(server_builder, server, shutdown);
+        // dropping the accept loop stops taking new connections
+        tokio::select! {
+            result = accepting => result?,
+            _ = signal => {}
+        }
+        drain.drain(deadline).await;
+
+        Ok(())
+    }
+
+    // Serves each connection of the listeners on its own task
+    async fn accept<L, T>(
+        server_builder: ServerBuilder<L>,
+        server: Arc<Octane>,
+        shutdown: Shutdown,
+    ) -> io::Result<()>
+    where
+        L: Stream<Item = io::Result<T>> + Unpin,
+        T: AsyncRead + AsyncWrite + Unpin + AsMutStream + Send + 'static,
+    {
+        server_builder
+            .listen(
+                move |stream, server, shutdown| async move {
+                    Octane::serve(stream, server, shutdown).await
+                },
+                server,
+                shutdown,
             )
             .await
-            {
-                let request_line = &request.request_line;
-                let mut res = Response::new_empty();
-                // Detect http version and validate
-                let checker = Http::validate(&request);
-                if checker.is_malformed() {
-                    declare_error!(writer, checker.err_code.unwrap());
+    }
+
+    async fn serve<S>(
+        stream_async: S,
+        server: Arc<Octane>,
+        mut shutdown: Shutdown,
+    ) -> Result<(), Box<dyn StdError>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + AsMutStream + Send + 'static,
+    {
+        let stream_async: Box<dyn Connection> = Box::new(stream_async);
+        let (reader, mut writer) = split(stream_async);
+        // a route streaming its body takes the reader for as long as
+        // the request is handled
+        let mut reader = Some(reader);
+        // the first request waits as long as the header timeout, the
+        // following ones for what the last request negotiated
+        let mut idle_timeout = server.settings.header_timeout;
+        let mut served: u64 = 0;
+        // bytes read past the end of a request, they belong to the next
+        // pipelined request
+        let mut pipelined = Vec::new();
+        let limits = HeadLimits {
+            uri: server.settings.max_uri_len,
+            headers: server.settings.max_header_size,
+        };
+        loop {
+            let mut data = std::mem::take(&mut pipelined);
+            let raw_request = match reader.take() {
+                Some(reader) => RawRequest1x::new(reader),
+                None => return Ok(()),
+            };
+            let reading = Http1xReader::read(raw_request, &mut data, limits);
+            let reading = async {
+                match idle_timeout {
+                    Some(duration) => timeout(duration, reading).await.ok(),
+                    None => Some(reading.await),
+                }
+            };
+            // an idle connection has nothing to finish, a shutdown closes it
+            let parsed = tokio::select! {
+                parsed = reading => match parsed {
+                    Some(parsed) => parsed,
+                    None => return Ok(()),
+                },
+                _ = shutdown.recv() => return Ok(()),
+            };
+            let (raw_headers, raw_request_line, body_remainder, reader_left) = match parsed {
+                Ok(Some(parsed)) => parsed,
+                // the client closed the connection between requests
+                Ok(None) => return Ok(()),
+                Err(status_code) => {
+                    declare_error!(&mut writer, status_code);
                 }
-                if request_line.method.is_some() {
-                    // run closures
-                    server.router.run(request.clone(), &mut res);
-                    if !res.has_body() {
-                        declare_error!(writer, StatusCode::NotFound);
+            };
+            let (headers, request_line) = match (
+                Headers::parse(raw_headers),
+                RequestLine::parse(raw_request_line),
+            ) {
+                (Some(headers), Some(request_line)) => (headers, request_line),
+                _ => {
+                    declare_error!(&mut writer, StatusCode::BadRequest);
+                }
+            };
+            let max_body_size = server
+                .router
+                .route_body_limit(&request_line)
+                .unwrap_or(server.settings.max_body_size);
+            let mut body_vec = Vec::new();
+            let request = if server.router.streams_body(&request_line) {
+                Request::from_stream(
+                    &headers,
+                    request_line,
+                    body_remainder,
+                    reader_left,
+                    max_body_size,
+                )
+            } else {
+                let reader = reader.get_or_insert(reader_left);
+                Request::from_raw(
+                    &headers,
+                    request_line,
+                    &mut body_vec,
+                    body_remainder,
+                    &mut pipelined,
+                    reader,
+                    max_body_size,
+                    server.settings.max_decompressed_size,
+                )
+                .await
+            };
+            let request = match request {
+                Ok(request) => request,
+                Err(status_code) => {
+                    declare_error!(&mut writer, status_code);
+                }
+            };
+            let request_line = &request.request_line;
+            // Detect http version and validate
+            let checker = Http::validate(&request);
+            if checker.is_malformed() {
+                declare_error!(&mut writer, checker.err_code.unwrap());
+            }
+            if !request_line.method.is_some() {
+                declare_error!(&mut writer, StatusCode::NotImplemented);
+            }
+            served += 1;
+            let mut res = Response::new_empty();
+            res.entity_tag = server.settings.entity_tag;
+            // run closures
+            server.router.run(request.clone(), &mut res).await;
+            if let Some(stream) = &request.body_stream {
+                // a body left unread can't be told apart from the next
+                // request, the connection is closed instead of draining it
+                if let Some((left, surplus)) = stream.finish().await {
+                    reader = Some(left);
+                    pipelined = surplus;
+                }
+            }
+            if !res.has_body() {
+                res = Error::response(StatusCode::NotFound);
+            }
+            res.resolve_entity_tag().await;
+            res.apply_conditions(&request);
+            res.apply_range(&request);
+            #[cfg(feature = "compression")]
+            res.compress(&request);
+            // a body without a length can only be delimited by closing the connection
+            let delimited = res.frame_body(&request_line.version);
+            idle_timeout = checker
+                .timeout(server.settings.keep_alive)
+                .filter(|_| delimited && reader.is_some())
+                .filter(|_| checker.max_requests.map_or(true, |max| served < max))
+                .filter(|_| !shutdown.is_shutdown());
+            if let Some(duration) = idle_timeout {
+                if request_line.version == HttpVersion::Http10 {
+                    let mut keep_alive = format!("timeout={}", duration.as_secs());
+                    if let Some(max) = checker.max_requests {
+                        keep_alive.push_str(&format!(", max={}", max - served));
                     }
-
-                    Octane::send(res.get_data(), writer).await?;
-                } else {
-                    declare_error!(writer, StatusCode::NotImplemented);
+                    res.set("Connection", "keep-alive")
+                        .set("Keep-Alive", &keep_alive);
                 }
             } else {
-                declare_error!(writer, StatusCode::BadRequest);
+                res.set("Connection", "close");
+            }
+            let mut response = res.get_data();
+            if request_line.method == RequestMethod::Head {
+                // the head of a HEAD response still describes the body
+                // but the body itself must not be sent
+                response.1 = Box::new(empty());
+            }
+            Octane::send(response, &mut writer).await?;
+            if idle_timeout.is_none() {
+                return Ok(());
             }
-        } else {
-            declare_error!(writer, parsed.err().unwrap());
         }
-        Ok(())
     }
     pub(crate) async fn send<S>(
         mut response: (String, BoxReader),
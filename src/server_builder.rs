@@ -1,68 +1,112 @@
+#[cfg(unix)]
+use crate::config::UnixSocket;
 use crate::server::Octane;
+use crate::shutdown::Shutdown;
 use crate::task;
-use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use socket2::{Domain, SockAddr, Socket, Type};
 use std::future::Future;
-use std::io::Result;
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::io::{Error, ErrorKind, Result};
+use std::net::{self, SocketAddr};
+#[cfg(unix)]
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+#[cfg(unix)]
+use std::path::Path;
+#[cfg(unix)]
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tokio::net::{TcpListener, TcpStream};
-use tokio::stream::StreamExt;
+#[cfg(unix)]
+use std::{
+    env, fs,
+    os::unix::io::{FromRawFd, RawFd},
+    os::unix::net::{self as unix_net, UnixStream},
+};
+use tokio::net::TcpListener;
+#[cfg(any(feature = "openSSL", feature = "rustls"))]
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::stream::{Stream, StreamExt, StreamMap};
 #[cfg(feature = "openSSL")]
 use tokio_openssl::SslStream;
 #[cfg(feature = "rustls")]
 use tokio_rustls::server::TlsStream;
 
-pub struct ServerBuilder {
-    socket: TcpListener,
+pub struct ServerBuilder<L = TcpListener> {
+    sockets: StreamMap<usize, L>,
 }
 
 impl ServerBuilder {
-    pub fn new(port: u16) -> Result<Self> {
-        let stream = Type::stream();
-        let socket = Socket::new(Domain::ipv4(), stream, None)?;
-        socket.set_nonblocking(true)?;
-        let bind_add = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), port);
-        socket.bind(&SockAddr::from(bind_add))?;
-        socket.listen(2048)?;
-        socket.set_reuse_address(true)?;
-        Ok(ServerBuilder {
-            socket: TcpListener::from_std(socket.into_tcp_listener())?,
-        })
+    pub fn new(addrs: &[SocketAddr]) -> Result<Self> {
+        if addrs.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "No address to listen on",
+            ));
+        }
+        let mut sockets = StreamMap::new();
+        for (index, addr) in addrs.iter().enumerate() {
+            sockets.insert(index, Self::bind(*addr)?);
+        }
+        Ok(ServerBuilder { sockets })
     }
 
-    pub async fn listen<C, T>(mut self, exec: C, server: Arc<Octane>) -> Result<()>
-    where
-        T: Future + Send,
-        C: FnOnce(TcpStream, Arc<Octane>) -> T + Send + 'static + Copy,
-    {
-        while let Some(stream) = self.socket.next().await {
-            stream.map(|stream| {
-                let server = Arc::clone(&server);
-                task!({
-                    exec(stream, server).await;
-                })
-            })?;
+    pub fn from_std(listeners: Vec<net::TcpListener>) -> Result<Self> {
+        if listeners.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "No listener to accept on",
+            ));
         }
-        Ok(())
+        let mut sockets = StreamMap::new();
+        for (index, listener) in listeners.into_iter().enumerate() {
+            listener.set_nonblocking(true)?;
+            sockets.insert(index, TcpListener::from_std(listener)?);
+        }
+        Ok(ServerBuilder { sockets })
+    }
+
+    fn bind(addr: SocketAddr) -> Result<TcpListener> {
+        let domain = if addr.is_ipv4() {
+            Domain::ipv4()
+        } else {
+            Domain::ipv6()
+        };
+        let socket = Socket::new(domain, Type::stream(), None)?;
+        if addr.is_ipv6() {
+            // every address gets its own listener, so dual stack is
+            // listening on both an IPv4 and an IPv6 address
+            socket.set_only_v6(true)?;
+        }
+        socket.set_reuse_address(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&SockAddr::from(addr))?;
+        socket.listen(2048)?;
+        TcpListener::from_std(socket.into_tcp_listener())
     }
 
     #[cfg(feature = "openSSL")]
-    pub async fn listen_ssl<C, T>(self, exec: C, server: Arc<Octane>) -> Result<()>
+    pub async fn listen_ssl<C, T>(
+        self,
+        exec: C,
+        server: Arc<Octane>,
+        shutdown: Shutdown,
+    ) -> Result<()>
     where
         T: Future + Send,
-        C: FnOnce(SslStream<TcpStream>, Arc<Octane>) -> T + Send + 'static + Copy,
+        C: FnOnce(SslStream<TcpStream>, Arc<Octane>, Shutdown) -> T + Send + 'static + Copy,
     {
-        let mut ssl_listener = self.socket;
+        let mut ssl_listener = self.sockets;
         let acceptor = crate::tls::openssl::acceptor(&server.settings)?;
-        while let Some(stream) = ssl_listener.next().await {
+        while let Some((_, stream)) = ssl_listener.next().await {
             let acceptor = acceptor.clone();
             let tcp_stream = stream?;
             let server = Arc::clone(&server);
+            let shutdown = shutdown.clone();
 
             task!({
                 let stream = tokio_openssl::accept(&acceptor, tcp_stream).await;
                 if let Ok(stream_ssl) = stream {
-                    exec(stream_ssl, server).await;
+                    exec(stream_ssl, server, shutdown).await;
                 } else {
                     stream.map_err(|e| println!("{:?}", e)).err();
                 }
@@ -76,23 +120,25 @@ impl ServerBuilder {
         self,
         exec: C,
         server: Arc<Octane>,
+        shutdown: Shutdown,
     ) -> std::result::Result<(), Box<dyn std::error::Error>>
     where
         T: Future + Send,
-        C: FnOnce(TlsStream<TcpStream>, Arc<Octane>) -> T + Send + 'static + Copy,
+        C: FnOnce(TlsStream<TcpStream>, Arc<Octane>, Shutdown) -> T + Send + 'static + Copy,
     {
-        let mut ssl_listener = self.socket;
+        let mut ssl_listener = self.sockets;
         let acceptor = crate::tls::rustls::acceptor(&server.settings)?;
-        while let Some(stream) = ssl_listener.next().await {
+        while let Some((_, stream)) = ssl_listener.next().await {
             let acceptor = acceptor.clone();
 
             let server = Arc::clone(&server);
 
             let tcp_stream = stream?;
+            let shutdown = shutdown.clone();
             task!({
                 let stream = acceptor.accept(tcp_stream).await;
                 if let Ok(stream_ssl) = stream {
-                    exec(stream_ssl, server).await;
+                    exec(stream_ssl, server, shutdown).await;
                 } else {
                     stream
                         .map_err(|e| println!("WARNING: {:?}", e.kind()))
@@ -103,3 +149,240 @@ impl ServerBuilder {
         Ok(())
     }
 }
+
+// The first descriptor passed by systemd socket activation
+#[cfg(unix)]
+const LISTEN_FDS_START: RawFd = 3;
+
+// Set once the passed descriptors have been given an owner
+#[cfg(unix)]
+static LISTEN_FDS_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Returns the TCP and the Unix listeners that systemd passed to this
+/// process with socket activation, they are only returned once.
+/// The variables are left in the environment, removing them races
+/// with the runtime threads reading it, and the children of the
+/// server ignore them as `LISTEN_PID` isn't theirs
+#[cfg(unix)]
+pub fn listen_fds() -> Result<(Vec<net::TcpListener>, Vec<unix_net::UnixListener>)> {
+    let count = parse_listen_fds(env::var("LISTEN_PID").ok(), env::var("LISTEN_FDS").ok())?;
+    let mut tcp = Vec::new();
+    let mut unix = Vec::new();
+    if count == 0 || LISTEN_FDS_TAKEN.swap(true, Ordering::SeqCst) {
+        return Ok((tcp, unix));
+    }
+    for fd in LISTEN_FDS_START..LISTEN_FDS_START + count {
+        // systemd leaves these descriptors open for the process and
+        // the flag above makes sure each of them gets a single owner
+        #[allow(unsafe_code)]
+        let socket = unsafe { Socket::from_raw_fd(fd) };
+        sort_listener(socket, &mut tcp, &mut unix)?;
+    }
+    Ok((tcp, unix))
+}
+
+// Tells a TCP listener from a Unix one by the family of its address
+#[cfg(unix)]
+fn sort_listener(
+    socket: Socket,
+    tcp: &mut Vec<net::TcpListener>,
+    unix: &mut Vec<unix_net::UnixListener>,
+) -> Result<()> {
+    let addr = socket.local_addr()?;
+    if addr.as_std().is_some() {
+        tcp.push(socket.into_tcp_listener());
+    } else if i32::from(addr.family()) == i32::from(Domain::unix()) {
+        unix.push(socket.into_unix_listener());
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "A socket passed by systemd is neither TCP nor Unix",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn parse_listen_fds(pid: Option<String>, fds: Option<String>) -> Result<RawFd> {
+    match (pid, fds) {
+        // the variables may be meant for another process, e.g. our parent
+        (Some(pid), Some(fds)) if pid.parse() == Ok(std::process::id()) => fds
+            .parse()
+            .ok()
+            .filter(|count| *count >= 0)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Invalid LISTEN_FDS")),
+        _ => Ok(0),
+    }
+}
+
+#[cfg(unix)]
+impl ServerBuilder<UnixListener> {
+    pub fn from_std_unix(listeners: Vec<unix_net::UnixListener>) -> Result<Self> {
+        if listeners.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "No listener to accept on",
+            ));
+        }
+        let mut sockets = StreamMap::new();
+        for (index, listener) in listeners.into_iter().enumerate() {
+            listener.set_nonblocking(true)?;
+            sockets.insert(index, UnixListener::from_std(listener)?);
+        }
+        Ok(ServerBuilder { sockets })
+    }
+
+    pub fn new_unix(path: &Path, options: &UnixSocket) -> Result<Self> {
+        Self::remove_stale(path)?;
+        let socket = UnixListener::bind(path)?;
+        if let Some(mode) = options.mode {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        }
+        if options.owner.is_some() || options.group.is_some() {
+            std::os::unix::fs::chown(path, options.owner, options.group)?;
+        }
+        let mut sockets = StreamMap::new();
+        sockets.insert(0, socket);
+        Ok(ServerBuilder { sockets })
+    }
+
+    // A server that didn't exit cleanly leaves its socket file behind
+    // and the bind fails on it, it is removed if nothing accepts on it
+    fn remove_stale(path: &Path) -> Result<()> {
+        match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_socket() => {
+                if UnixStream::connect(path).is_ok() {
+                    return Err(Error::new(
+                        ErrorKind::AddrInUse,
+                        "The socket is in use by another server",
+                    ));
+                }
+                fs::remove_file(path)
+            }
+            Ok(_) => Err(Error::new(
+                ErrorKind::AlreadyExists,
+                "The socket path exists and is not a socket",
+            )),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<L, S> ServerBuilder<L>
+where
+    L: Stream<Item = Result<S>> + Unpin,
+    S: Send + 'static,
+{
+    pub async fn listen<C, T>(
+        mut self,
+        exec: C,
+        server: Arc<Octane>,
+        shutdown: Shutdown,
+    ) -> Result<()>
+    where
+        T: Future + Send,
+        C: FnOnce(S, Arc<Octane>, Shutdown) -> T + Send + 'static + Copy,
+    {
+        while let Some((_, stream)) = self.sockets.next().await {
+            stream.map(|stream| {
+                let server = Arc::clone(&server);
+                let shutdown = shutdown.clone();
+                task!({
+                    exec(stream, server, shutdown).await;
+                })
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[crate::test]
+    async fn success_bind() {
+        let addrs = [
+            SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0),
+            SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0),
+        ];
+        assert_eq!(ServerBuilder::new(&addrs).unwrap().sockets.len(), 2);
+        // not every sandbox has an IPv6 loopback
+        if std::net::TcpListener::bind((Ipv6Addr::LOCALHOST, 0)).is_ok() {
+            let addrs = [SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 0)];
+            assert!(ServerBuilder::new(&addrs).is_ok());
+        }
+    }
+
+    #[crate::test]
+    async fn fail_bind() {
+        assert!(ServerBuilder::new(&[]).is_err());
+        assert!(ServerBuilder::from_std(Vec::new()).is_err());
+    }
+
+    #[crate::test]
+    async fn success_from_std() {
+        let listener = net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        assert_eq!(
+            ServerBuilder::from_std(vec![listener])
+                .unwrap()
+                .sockets
+                .len(),
+            1
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn success_listen_fds() {
+        let pid = Some(std::process::id().to_string());
+        assert_eq!(parse_listen_fds(pid.clone(), Some("2".into())).unwrap(), 2);
+        assert_eq!(
+            parse_listen_fds(Some("1".into()), Some("2".into())).unwrap(),
+            0
+        );
+        assert_eq!(parse_listen_fds(None, None).unwrap(), 0);
+        assert!(parse_listen_fds(pid.clone(), Some("-1".into())).is_err());
+        assert!(parse_listen_fds(pid, Some("two".into())).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn success_sort_listener() {
+        let (mut tcp, mut unix) = (Vec::new(), Vec::new());
+        let listener = net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        sort_listener(Socket::from(listener), &mut tcp, &mut unix).unwrap();
+        let path = std::env::temp_dir().join(format!("octane-sort-{}.sock", std::process::id()));
+        let listener = unix_net::UnixListener::bind(&path).unwrap();
+        sort_listener(Socket::from(listener), &mut tcp, &mut unix).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(tcp.len(), 1);
+        assert_eq!(tcp[0].local_addr().unwrap(), addr);
+        assert_eq!(unix.len(), 1);
+        assert_eq!(unix[0].local_addr().unwrap().as_pathname(), Some(&*path));
+    }
+
+    #[cfg(unix)]
+    #[crate::test]
+    async fn success_unix_stale() {
+        let path = std::env::temp_dir().join(format!("octane-{}.sock", std::process::id()));
+        // a socket file that nothing accepts on anymore
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let mut options = UnixSocket::new();
+        options.mode(0o600);
+        let builder = ServerBuilder::new_unix(&path, &options).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // the socket is live now, it must not be replaced
+        assert!(ServerBuilder::new_unix(&path, &options).is_err());
+        drop(builder);
+        fs::remove_file(&path).unwrap();
+        // never remove something that isn't a socket
+        fs::write(&path, b"").unwrap();
+        assert!(ServerBuilder::new_unix(&path, &options).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::time::timeout;
+
+/// Creates a linked pair, the `Drain` half is kept by the listener
+/// and triggers the shutdown, `Shutdown` is cloned into every task
+/// that has to finish before the server returns
+pub(crate) fn channel() -> (Drain, Shutdown) {
+    let (sender, signal) = watch::channel(false);
+    let (tracker, drained) = mpsc::channel(1);
+    (
+        Drain { sender, drained },
+        Shutdown {
+            signal,
+            _tracker: tracker,
+        },
+    )
+}
+
+/// The triggering half of a graceful shutdown
+pub(crate) struct Drain {
+    sender: watch::Sender<bool>,
+    drained: mpsc::Receiver<()>,
+}
+
+impl Drain {
+    /// Notifies every `Shutdown` clone and waits for all of them
+    /// to be dropped, or for the deadline to pass if there is one
+    pub(crate) async fn drain(mut self, deadline: Option<Duration>) {
+        // an error only means that nobody is listening anymore
+        self.sender.send(true).ok();
+        let drained = self.drained.recv();
+        if let Some(deadline) = deadline {
+            timeout(deadline, drained).await.ok();
+        } else {
+            drained.await;
+        }
+    }
+}
+
+/// The listening half of a graceful shutdown, the server waits
+/// for every clone of it to go away before returning
+#[derive(Clone)]
+pub(crate) struct Shutdown {
+    signal: watch::Receiver<bool>,
+    // never read, the drain is complete when all the senders are dropped
+    _tracker: mpsc::Sender<()>,
+}
+
+impl Shutdown {
+    /// Returns true if the shutdown has been triggered
+    pub(crate) fn is_shutdown(&self) -> bool {
+        *self.signal.borrow()
+    }
+    /// Resolves once the shutdown is triggered or the `Drain`
+    /// half is dropped
+    pub(crate) async fn recv(&mut self) {
+        while !self.is_shutdown() {
+            if self.signal.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[crate::test]
+    async fn success_drain() {
+        let (drain, shutdown) = channel();
+        let mut connection = shutdown.clone();
+        drop(shutdown);
+        let task = tokio::spawn(async move {
+            connection.recv().await;
+            connection.is_shutdown()
+        });
+        drain.drain(None).await;
+        assert!(task.await.unwrap());
+    }
+
+    #[crate::test]
+    async fn success_drain_deadline() {
+        let (drain, shutdown) = channel();
+        // a connection that never finishes must not hold the server forever
+        let held = shutdown.clone();
+        drop(shutdown);
+        drain.drain(Some(Duration::from_millis(10))).await;
+        assert!(held.is_shutdown());
+    }
+}
@@ -1,8 +1,11 @@
 use crate::constants::{
-    DAYS_IN_MONTH, DAYS_PER_100Y, DAYS_PER_400Y, DAYS_PER_4Y, LEAPOCH, MONTHS, SP, WEEKS,
+    DAYS_IN_MONTH, DAYS_PER_100Y, DAYS_PER_400Y, DAYS_PER_4Y, LEAPOCH, LONG_WEEKS, MONTHS, SP,
+    WEEKS,
 };
+use std::cell::RefCell;
 use std::convert::TryInto;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 pub(crate) struct Time {
     min: i64,
@@ -26,19 +29,6 @@ impl Padding for i64 {
 }
 
 impl Time {
-    pub(crate) fn now() -> Option<Self> {
-        let stamp = match SystemTime::now().duration_since(UNIX_EPOCH) {
-            Ok(n) => {
-                if let Ok(x) = n.as_secs().try_into() {
-                    x
-                } else {
-                    return None;
-                }
-            }
-            Err(m) => panic!("Got time error: {}", m),
-        };
-        Self::time(stamp)
-    }
     pub(crate) fn format(&self) -> String {
         if let Some(month_day) = self.month_day() {
             if let Some(week_day) = self.week_day() {
@@ -64,14 +54,9 @@ impl Time {
             String::new()
         }
     }
-    /*
-    pub fn with_stamp(self, stamp: i64) -> Option<Self> {
-        Self::time(stamp)
-    }
-    */
     fn week_day(&self) -> Option<String> {
         if let Some(week) = WEEKS.iter().enumerate().find(|(i, _)| {
-            if let Ok(week_day) = self.week.try_into() {
+            if let Ok::<usize, _>(week_day) = self.week.try_into() {
                 *i == week_day
             } else {
                 false
@@ -84,7 +69,7 @@ impl Time {
     }
     fn month_day(&self) -> Option<String> {
         if let Some(month) = MONTHS.iter().enumerate().find(|(i, _)| {
-            if let Ok(month_day) = self.month.try_into() {
+            if let Ok::<usize, _>(month_day) = self.month.try_into() {
                 *i == month_day
             } else {
                 false
@@ -153,9 +138,193 @@ impl Time {
     }
 }
 
+/// A date as it's sent in http headers like `Date`, `Last-Modified`
+/// or `Expires`, with a precision of one second
+///
+/// It's parsed from any of the three formats of RFC 7231 and always
+/// formatted as an IMF-fixdate, the others are obsolete
+///
+/// # Example
+///
+/// ```
+/// use octane::HttpDate;
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let date = HttpDate::parse("Sun, 06 Nov 1994 08:49:37 GMT").unwrap();
+/// assert_eq!(HttpDate::parse("Sunday, 06-Nov-94 08:49:37 GMT"), Some(date));
+/// assert_eq!(HttpDate::parse("Sun Nov  6 08:49:37 1994"), Some(date));
+/// assert_eq!(date, HttpDate::from(UNIX_EPOCH + Duration::from_secs(784111777)));
+/// assert_eq!(date.to_string(), "Sun, 06 Nov 1994 08:49:37 GMT");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HttpDate {
+    // seconds since the unix epoch
+    secs: u64,
+}
+
+impl HttpDate {
+    /// Returns the current date
+    pub fn now() -> Self {
+        Self::from(SystemTime::now())
+    }
+    /// Parses an IMF-fixdate (`Sun, 06 Nov 1994 08:49:37 GMT`), an
+    /// RFC 850 date (`Sunday, 06-Nov-94 08:49:37 GMT`) or an asctime
+    /// date (`Sun Nov  6 08:49:37 1994`), None if the date is invalid
+    /// or before the unix epoch
+    pub fn parse(date: &str) -> Option<Self> {
+        let date = date.trim();
+        let fields = if let Some((week, rest)) = date.split_once(',') {
+            let fields: Vec<&str> = rest.split_ascii_whitespace().collect();
+            match fields[..] {
+                [day, month, year, clock, "GMT"] if WEEKS.contains(&week) => {
+                    (parse_number(day, 2)?, month, parse_number(year, 4)?, clock)
+                }
+                [day, clock, "GMT"] if LONG_WEEKS.contains(&week) => {
+                    let day: Vec<&str> = day.split('-').collect();
+                    match day[..] {
+                        [day, month, year] => (
+                            parse_number(day, 2)?,
+                            month,
+                            two_digit_year(parse_number(year, 2)?),
+                            clock,
+                        ),
+                        _ => return None,
+                    }
+                }
+                _ => return None,
+            }
+        } else {
+            let fields: Vec<&str> = date.split_ascii_whitespace().collect();
+            match fields[..] {
+                [week, month, day, clock, year] if WEEKS.contains(&week) => {
+                    let day = if day.len() == 1 {
+                        parse_number(day, 1)?
+                    } else {
+                        parse_number(day, 2)?
+                    };
+                    (day, month, parse_number(year, 4)?, clock)
+                }
+                _ => return None,
+            }
+        };
+        let (day, month, year, clock) = fields;
+        let month = MONTHS.iter().position(|name| *name == month)? as i64 + 1;
+        let clock: Vec<&str> = clock.split(':').collect();
+        let (hour, min, sec) = match clock[..] {
+            [hour, min, sec] => (
+                parse_number(hour, 2)?,
+                parse_number(min, 2)?,
+                parse_number(sec, 2)?,
+            ),
+            _ => return None,
+        };
+        // a leap second is folded into the next one
+        if year < 1970
+            || day < 1
+            || day > days_in_month(year, month)
+            || hour > 23
+            || min > 59
+            || sec > 60
+        {
+            return None;
+        }
+        let days = days_from_civil(year, month, day);
+        let secs = days * 86400 + hour * 3600 + min * 60 + sec;
+        Some(HttpDate {
+            secs: secs.try_into().ok()?,
+        })
+    }
+}
+
+impl From<SystemTime> for HttpDate {
+    /// Drops the fraction of a second, times before
+    /// the unix epoch become the epoch
+    fn from(time: SystemTime) -> Self {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        HttpDate { secs }
+    }
+}
+
+impl From<HttpDate> for SystemTime {
+    fn from(date: HttpDate) -> Self {
+        UNIX_EPOCH + Duration::from_secs(date.secs)
+    }
+}
+
+impl fmt::Display for HttpDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = self
+            .secs
+            .try_into()
+            .ok()
+            .and_then(Time::time)
+            .ok_or(fmt::Error)?;
+        write!(f, "{}", time.format())
+    }
+}
+
+thread_local! {
+    static DATE_HEADER: RefCell<(u64, String)> = RefCell::new((u64::MAX, String::new()));
+}
+
+// The value of the Date header, formatted at most once a second
+// on every thread
+pub(crate) fn date_header() -> String {
+    let now = HttpDate::now();
+    DATE_HEADER.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if cache.0 != now.secs {
+            *cache = (now.secs, now.to_string());
+        }
+        cache.1.clone()
+    })
+}
+
+fn parse_number(digits: &str, len: usize) -> Option<i64> {
+    if digits.len() != len || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+// RFC 850 years only have two digits, a year more than 50 years
+// in the future is the most recent one in the past with the same
+// last digits
+fn two_digit_year(year: i64) -> i64 {
+    let current = Time::time(HttpDate::now().secs as i64).map_or(1970, |time| time.year);
+    let mut full = current - current % 100 + year;
+    if full > current + 50 {
+        full -= 100;
+    }
+    full
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days between the unix epoch and a date of the proleptic
+// gregorian calendar, the month starts at 1
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * DAYS_PER_400Y + day_of_era - 719468
+}
+
 mod tests {
     #![cfg(test)]
-    use crate::time::Time;
+    use crate::time::{date_header, HttpDate, Time};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
     #[test]
     fn start_date() {
@@ -176,4 +345,42 @@ mod tests {
         let some_time = Time::time(333452334).unwrap().format();
         assert_eq!(some_time, "Sat, 26 Jul 1980 09:38:54 GMT");
     }
+
+    #[test]
+    fn success_parse() {
+        let secs = |date| HttpDate::parse(date).map(|date| date.secs);
+        assert_eq!(secs("Sat, 26 Jul 1980 09:38:54 GMT"), Some(333452334));
+        assert_eq!(secs("Saturday, 26-Jul-80 09:38:54 GMT"), Some(333452334));
+        assert_eq!(secs("Sat Jul 26 09:38:54 1980"), Some(333452334));
+        assert_eq!(secs("Thu Jan  1 00:00:00 1970"), Some(0));
+        let date = HttpDate::parse("Mon, 29 Feb 2016 00:00:00 GMT").unwrap();
+        assert_eq!(date.to_string(), "Mon, 29 Feb 2016 00:00:00 GMT");
+        let date = HttpDate::parse("Tue, 13 Sep 2022 12:00:00 GMT").unwrap();
+        assert_eq!(date.to_string(), "Tue, 13 Sep 2022 12:00:00 GMT");
+        assert!(date > HttpDate::parse("Tue, 13 Sep 2022 11:59:59 GMT").unwrap());
+    }
+
+    #[test]
+    fn fail_parse() {
+        assert!(HttpDate::parse("Sat, 26 Jul 1980 09:38:54").is_none());
+        assert!(HttpDate::parse("Sat, 26 July 1980 09:38:54 GMT").is_none());
+        assert!(HttpDate::parse("Sat, 6 Jul 1980 09:38:54 GMT").is_none());
+        assert!(HttpDate::parse("Sat, 26 Jul 1980 9:38:54 GMT").is_none());
+        assert!(HttpDate::parse("Sat, 30 Feb 1980 09:38:54 GMT").is_none());
+        assert!(HttpDate::parse("Sat, 26-Jul-80 09:38:54 GMT").is_none());
+        assert!(HttpDate::parse("Sat Jul 26 09:38:54 80").is_none());
+        assert!(HttpDate::parse("Wed, 31 Dec 1969 23:59:59 GMT").is_none());
+    }
+
+    #[test]
+    fn success_system_time() {
+        let time = UNIX_EPOCH + Duration::from_millis(333452334999);
+        let date = HttpDate::from(time);
+        assert_eq!(date.to_string(), "Sat, 26 Jul 1980 09:38:54 GMT");
+        assert_eq!(
+            SystemTime::from(date),
+            UNIX_EPOCH + Duration::from_secs(333452334)
+        );
+        assert!(HttpDate::parse(&date_header()).is_some());
+    }
 }
@@ -1,14 +1,14 @@
 use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
 
 pub mod openssl;
 pub mod rustls;
 
-pub trait AsMutStream {
-    fn stream_mut(&mut self) -> &mut TcpStream;
-}
+/// Marks the streams which connections can be served on
+pub trait AsMutStream {}
 
-impl AsMutStream for TcpStream {
-    fn stream_mut(&mut self) -> &mut TcpStream {
-        self
-    }
-}
+impl AsMutStream for TcpStream {}
+
+#[cfg(unix)]
+impl AsMutStream for UnixStream {}
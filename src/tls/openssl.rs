@@ -15,8 +15,4 @@ pub fn acceptor(settings: &OctaneConfig) -> Result<SslAcceptor> {
     Ok(acceptor)
 }
 
-impl AsMutStream for SslStream<TcpStream> {
-    fn stream_mut(&mut self) -> &mut TcpStream {
-        self.get_mut()
-    }
-}
+impl AsMutStream for SslStream<TcpStream> {}
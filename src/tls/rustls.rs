@@ -25,8 +25,4 @@ pub fn acceptor(settings: &OctaneConfig) -> Result<TlsAcceptor, Box<dyn Error>>
     Ok(acceptor)
 }
 
-impl AsMutStream for TlsStream<TcpStream> {
-    fn stream_mut(&mut self) -> &mut TcpStream {
-        self.get_mut().0
-    }
-}
+impl AsMutStream for TlsStream<TcpStream> {}
@@ -49,6 +49,52 @@ impl<'a, T: Eq> Iterator for Spliterator<'a, T> {
 
 impl<'a, T: Eq> FusedIterator for Spliterator<'a, T> {}
 
+// Splits a header value like `form-data; name="a; b"` into its
+// first token and its parameters, the values are unquoted
+pub fn parse_params(value: &str) -> (&str, Vec<(String, String)>) {
+    let (kind, mut rest) = match value.find(';') {
+        Some(i) => (&value[..i], &value[i + 1..]),
+        None => (value, ""),
+    };
+    let mut params = Vec::new();
+    while let Some(eq) = rest.find('=') {
+        // a parameter without a value before it is skipped
+        let key = rest[..eq]
+            .rsplit(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_owned();
+        let after = rest[eq + 1..].trim_start();
+        let mut param = String::new();
+        if let Some(quoted) = after.strip_prefix('"') {
+            let mut chars = quoted.char_indices();
+            let mut end = quoted.len();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '"' => {
+                        end = i + 1;
+                        break;
+                    }
+                    '\\' => param.extend(chars.next().map(|(_, c)| c)),
+                    c => param.push(c),
+                }
+            }
+            rest = &quoted[end..];
+        } else {
+            let end = after.find(';').unwrap_or_else(|| after.len());
+            param.push_str(after[..end].trim_end());
+            rest = &after[end..];
+        }
+        params.push((key, param));
+        rest = match rest.find(';') {
+            Some(i) => &rest[i + 1..],
+            None => "",
+        };
+    }
+    (kind.trim(), params)
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct AsyncReader<T: Read> {
     pub reader: T,
@@ -64,7 +110,8 @@ impl<T: Read + Unpin> Deref for AsyncReader<T> {
 
 impl<T: Read + Unpin> AsyncRead for AsyncReader<T> {
     fn poll_read(mut self: Pin<&mut Self>, _: &mut Context, buf: &mut ReadBuf) -> Poll<Result<()>> {
-        self.reader.read(buf.initialized_mut())?;
+        let read = self.reader.read(buf.initialize_unfilled())?;
+        buf.advance(read);
         Poll::Ready(Ok(()))
     }
 }
@@ -74,3 +121,21 @@ impl<T: Read + Unpin> AsyncReader<T> {
         Self { reader }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn success_params() {
+        let (kind, params) = parse_params("form-data; flag; name=\"a;\\\"b\"; x = y ");
+        assert_eq!(kind, "form-data");
+        assert_eq!(
+            params,
+            vec![
+                ("name".to_owned(), "a;\"b".to_owned()),
+                ("x".to_owned(), "y".to_owned())
+            ]
+        );
+    }
+}
@@ -1,24 +1,43 @@
 use octane::Octane;
 use std::future::Future;
-use tokio::runtime::Builder;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::runtime::{Builder, Runtime};
+use tokio::sync::oneshot;
 
 pub const PORT: u16 = 8080;
 
+pub fn runtime() -> Runtime {
+    let mut builder = Builder::new_multi_thread();
+    builder.enable_all().thread_name("octane-test");
+
+    builder.build().expect("Unable to build tokio runtime")
+}
+
 pub fn run<T, F>(octane: Octane, exec: T)
 where
     T: Fn() -> F,
     F: Future,
 {
-    let mut builder = Builder::new_multi_thread();
-    builder.enable_all().thread_name("octane-test");
-
-    let runtime = builder.build().expect("Unable to build tokio runtime");
+    run_on(octane, PORT, exec)
+}
 
-    runtime.block_on(async {
-        let handle = tokio::spawn(async {
-            octane.listen(PORT, || {}).await.unwrap();
+pub fn run_on<T, F>(octane: Octane, port: u16, exec: T)
+where
+    T: Fn() -> F,
+    F: Future,
+{
+    runtime().block_on(async {
+        let (stop, stopped) = oneshot::channel::<()>();
+        let handle = tokio::spawn(async move {
+            octane
+                .listen_with_shutdown(port, || {}, stopped)
+                .await
+                .unwrap();
         });
         exec().await;
+        stop.send(()).unwrap();
         handle.await.unwrap();
     });
 }
@@ -35,3 +54,84 @@ macro_rules! path {
 pub async fn client_request(url: &str) -> String {
     reqwest::get(url).await.unwrap().text().await.unwrap()
 }
+
+/// A client writing raw bytes on a single connection, to see how
+/// the server frames its responses
+#[allow(dead_code)]
+pub struct RawClient {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+}
+
+#[allow(dead_code)]
+impl RawClient {
+    /// Connects to the local server, waiting for it to listen
+    pub async fn connect(port: u16) -> Self {
+        for _ in 0..100 {
+            if let Ok(stream) = TcpStream::connect(("127.0.0.1", port)).await {
+                return RawClient {
+                    stream,
+                    buffer: Vec::new(),
+                };
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("The server didn't start on port {}", port);
+    }
+
+    pub async fn send(&mut self, data: &[u8]) {
+        self.stream.write_all(data).await.unwrap();
+    }
+
+    /// Reads the next response, its head and its body, which must
+    /// be delimited by a Content-Length
+    pub async fn response(&mut self) -> (String, String) {
+        let end = loop {
+            if let Some(end) = find(&self.buffer, b"\r\n\r\n") {
+                break end + 4;
+            }
+            assert!(self.fill().await, "The connection closed mid head");
+        };
+        let head = String::from_utf8(self.buffer.drain(..end).collect()).unwrap();
+        let len = head
+            .lines()
+            .find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                if name.eq_ignore_ascii_case("content-length") {
+                    value.trim().parse().ok()
+                } else {
+                    None
+                }
+            })
+            .expect("The response has no Content-Length");
+        while self.buffer.len() < len {
+            assert!(self.fill().await, "The connection closed mid body");
+        }
+        let body = String::from_utf8(self.buffer.drain(..len).collect()).unwrap();
+        (head, body)
+    }
+
+    /// Returns true if the server closed the connection without
+    /// sending anything more
+    pub async fn closed(&mut self) -> bool {
+        self.buffer.is_empty() && !self.fill().await
+    }
+
+    async fn fill(&mut self) -> bool {
+        let mut chunk = [0; 1024];
+        match self.stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => false,
+            Ok(read) => {
+                self.buffer.extend_from_slice(&chunk[..read]);
+                true
+            }
+        }
+    }
+}
+
+#[allow(dead_code)]
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
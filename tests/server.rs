@@ -1,4 +1,7 @@
+use common::RawClient;
 use octane::prelude::*;
+use std::time::Duration;
+use tokio::sync::oneshot;
 mod common;
 
 pub fn basic_server_hello() {
@@ -130,3 +133,123 @@ pub fn basic_server_x_req() {
         );
     });
 }
+
+#[test]
+fn graceful_shutdown() {
+    const PORT: u16 = 8081;
+    let mut app = Octane::new();
+    app.get("/", route_stop!(|req, res| res.send("fast")))
+        .unwrap();
+    app.get(
+        "/slow",
+        async_route!(|req, res| {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            res.send("slow");
+            Flow::Stop
+        }),
+    )
+    .unwrap();
+    common::runtime().block_on(async {
+        let (stop, stopped) = oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            app.listen_with_shutdown(PORT, || {}, stopped)
+                .await
+                .unwrap();
+        });
+        // a connection which never sends anything and one left idle
+        // after its first request
+        let mut silent = RawClient::connect(PORT).await;
+        let mut idle = RawClient::connect(PORT).await;
+        idle.send(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await;
+        assert_eq!(idle.response().await.1, "fast");
+        let mut slow = RawClient::connect(PORT).await;
+        slow.send(b"GET /slow HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        stop.send(()).unwrap();
+
+        // the request in flight is still answered, then its connection closed
+        let (head, body) = slow.response().await;
+        assert!(head.starts_with("HTTP/1.1 200"));
+        assert!(head.to_ascii_lowercase().contains("connection: close"));
+        assert_eq!(body, "slow");
+        assert!(slow.closed().await);
+        // the idle connections don't hold the server until the deadline
+        let closing = async {
+            assert!(silent.closed().await);
+            assert!(idle.closed().await);
+        };
+        tokio::time::timeout(Duration::from_secs(2), closing)
+            .await
+            .unwrap();
+        tokio::time::timeout(Duration::from_secs(2), server)
+            .await
+            .unwrap()
+            .unwrap();
+    });
+}
+
+#[test]
+fn keep_alive_requests() {
+    const PORT: u16 = 8082;
+    let mut app = Octane::new();
+    app.get("/first", route_stop!(|req, res| res.send("Hello, first")))
+        .unwrap();
+    app.get("/second", route_stop!(|req, res| res.send("Hello, second")))
+        .unwrap();
+    common::run_on(app, PORT, || async {
+        let mut client = RawClient::connect(PORT).await;
+        client
+            .send(b"GET /first HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await;
+        let (head, body) = client.response().await;
+        assert!(head.starts_with("HTTP/1.1 200"));
+        assert_eq!(body, "Hello, first");
+        // the same connection takes another request
+        client
+            .send(b"GET /second HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await;
+        let (head, body) = client.response().await;
+        assert!(head.starts_with("HTTP/1.1 200"));
+        assert_eq!(body, "Hello, second");
+        assert!(client.closed().await);
+    });
+}
+
+#[test]
+fn pipelined_requests() {
+    const PORT: u16 = 8083;
+    let mut app = Octane::new();
+    app.get("/first", route_stop!(|req, res| res.send("first")))
+        .unwrap();
+    app.post(
+        "/second",
+        route_stop!(|req, res| res.send(format!("second {}", String::from_utf8_lossy(req.body)))),
+    )
+    .unwrap();
+    common::run_on(app, PORT, || async {
+        let mut client = RawClient::connect(PORT).await;
+        // both requests arrive in a single write, the second one with a body
+        client
+            .send(
+                b"GET /first HTTP/1.1\r\nHost: localhost\r\n\r\n\
+                POST /second HTTP/1.1\r\nHost: localhost\r\nContent-Length: 4\r\n\r\nbody",
+            )
+            .await;
+        let (head, body) = client.response().await;
+        assert!(head.starts_with("HTTP/1.1 200"));
+        assert!(head.to_ascii_lowercase().contains("content-length: 5\r\n"));
+        assert_eq!(body, "first");
+        let (head, body) = client.response().await;
+        assert!(head.starts_with("HTTP/1.1 200"));
+        assert!(head.to_ascii_lowercase().contains("content-length: 11\r\n"));
+        assert_eq!(body, "second body");
+        // nothing follows the two responses
+        client
+            .send(b"GET /first HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await;
+        assert_eq!(client.response().await.1, "first");
+        assert!(client.closed().await);
+    });
+}